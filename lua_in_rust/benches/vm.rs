@@ -0,0 +1,221 @@
+// Benchmarks of the VM, with criterion. To compare a change against
+// the current code:
+//
+//     cargo bench --bench vm -- --save-baseline before
+//     (make the change)
+//     cargo bench --bench vm -- --baseline before
+//
+// Scripts can not define functions or do arithmetic yet, so loops are
+// driven by `more()`, tables are built by helper functions in Rust, and
+// numbers are added by `add()` and `mul()`. The fib and n-body
+// benchmarks wait for functions and arithmetic in the VM: written with
+// these helpers they would time the helpers, not the VM.
+use std::cell::Cell;
+use std::hint::black_box;
+use std::io::{self, Cursor};
+use criterion::{criterion_group, criterion_main, Criterion};
+use lua_rs::parse::{self, ParseProto};
+use lua_rs::value::{RefLock, Table, Value};
+use lua_rs::vm::ExeState;
+
+const ITERATIONS: u32 = 1000;
+
+thread_local! {
+    static LEFT: Cell<u32> = const { Cell::new(0) };
+}
+
+// more(): true for ITERATIONS calls
+fn more(state: &mut ExeState) -> i32 {
+    let left = LEFT.with(|n| n.replace(n.get().saturating_sub(1)));
+    state.push(Value::Boolean(left > 0));
+    1
+}
+
+fn noop(_: &mut ExeState) -> i32 {
+    0
+}
+
+// concat(a, b)
+fn concat(state: &mut ExeState) -> i32 {
+    let mut s = state.check_string(1, "concat").to_vec();
+    s.extend_from_slice(state.check_string(2, "concat"));
+    state.push(Value::from(&s[..]));
+    1
+}
+
+fn newtable(state: &mut ExeState) -> i32 {
+    state.push(Table::new().into());
+    1
+}
+
+// set(t, k, v)
+fn set(state: &mut ExeState) -> i32 {
+    let Value::Table(t) = state.arg(1) else {
+        panic!("bad argument #1 to 'set' (table expected)");
+    };
+    t.borrow_mut().set(state.arg(2).clone(), state.arg(3).clone());
+    0
+}
+
+// add(a, b) and mul(a, b): integers wrap, as in Lua
+fn add(state: &mut ExeState) -> i32 {
+    arith(state, i64::wrapping_add, |a, b| a + b)
+}
+
+fn mul(state: &mut ExeState) -> i32 {
+    arith(state, i64::wrapping_mul, |a, b| a * b)
+}
+
+fn arith(state: &mut ExeState, int: fn(i64, i64) -> i64, float: fn(f64, f64) -> f64) -> i32 {
+    let v = match (state.arg(1), state.arg(2)) {
+        (&Value::Integer(a), &Value::Integer(b)) => Value::Integer(int(a, b)),
+        (a, b) => Value::Float(float(number(a), number(b))),
+    };
+    state.push(v);
+    1
+}
+
+fn number(v: &Value) -> f64 {
+    match *v {
+        Value::Integer(i) => i as f64,
+        Value::Float(f) => f,
+        _ => panic!("number expected, got {}", v.type_name()),
+    }
+}
+
+fn load(src: &str) -> ParseProto {
+    parse::load(Cursor::new(src), "bench")
+}
+
+fn run(proto: &ParseProto) {
+    let mut state = ExeState::new();
+    state.set_stdout(Box::new(io::sink()));
+    for (name, f) in [("more", more as fn(&mut ExeState) -> i32), ("noop", noop),
+            ("concat", concat), ("newtable", newtable), ("set", set),
+            ("add", add), ("mul", mul)] {
+        state.set_global(name, Value::Function(f));
+    }
+    LEFT.with(|n| n.set(ITERATIONS));
+    state.execute(proto);
+}
+
+fn bench(c: &mut Criterion, name: &str, src: &str) {
+    let proto = load(src);
+    c.bench_function(name, |b| b.iter(|| run(&proto)));
+}
+
+fn benches(c: &mut Criterion) {
+    bench(c, "locals", r#"
+        local a, b, c = 1, 2.5, "a string constant"
+        while more() do
+            local x, y, z = a, b, c
+            a, b, c = z, x, y
+            do
+                local s, t = "another string", c
+                g = s
+            end
+        end
+    "#);
+
+    bench(c, "calls", r#"
+        while more() do
+            noop()
+            noop(1, "two", true, nil)
+            print("x", 1, 2.5)
+        end
+    "#);
+
+    bench(c, "string_building", r#"
+        local s = ""
+        while more() do
+            s = concat(s, "x")
+        end
+    "#);
+
+    bench(c, "table_churn", r#"
+        while more() do
+            local t = newtable()
+            set(t, "key", "value")
+            set(t, 1, t)
+            set(t, 1, nil)
+        end
+    "#);
+
+    // calls of a Rust function, with their arguments and results moved
+    // between registers
+    bench(c, "native_call_loop", r#"
+        local a, b = 0, 1
+        while more() do
+            a, b = b, add(a, b)
+        end
+    "#);
+
+    // updates of fields of tables in a table, with the values computed
+    // by calls of Rust functions
+    bench(c, "table_field_update", r#"
+        local bodies = {
+            {x = 0.0, y = 0.0, vx = 0.0, vy = 0.0},
+            {x = 4.84, y = 1.16, vx = 0.61, vy = 2.81},
+            {x = 8.34, y = 4.12, vx = 1.01, vy = 1.82},
+        }
+        local dt = 0.01
+        while more() do
+            local a, b, c = bodies[1], bodies[2], bodies[3]
+            a.x = add(a.x, mul(dt, a.vx))
+            a.y = add(a.y, mul(dt, a.vy))
+            b.x = add(b.x, mul(dt, b.vx))
+            b.y = add(b.y, mul(dt, b.vy))
+            c.x = add(c.x, mul(dt, c.vx))
+            c.y = add(c.y, mul(dt, c.vy))
+        end
+    "#);
+
+    // reads and writes of fields, each of which takes the lock of its table
+    bench(c, "table_fields", r#"
+        local t = {x = 1, y = 2, items = {1, 2, 3}}
+        while more() do
+            local x, y = t.x, t.y
+            t.x = y
+            t.y = x
+            local items = t.items
+            local first, last = items[1], items[3]
+            items[1] = last
+            items[3] = first
+            t[1] = items[2]
+            items[2] = t[1]
+            t.items = items
+        end
+    "#);
+}
+
+// The cost of the lock of every table: the same reads with and without it.
+fn reflock(c: &mut Criterion) {
+    let mut t = Table::new();
+    for i in 1..=100 {
+        t.set(Value::Integer(i), Value::Integer(i));
+        t.set(Value::from(format!("k{i}").as_str()), Value::Integer(i));
+    }
+    let keys: Vec<Value> = (1..=100).map(Value::Integer)
+        .chain((1..=100).map(|i| Value::from(format!("k{i}").as_str())))
+        .collect();
+
+    c.bench_function("table_get", |b| b.iter(|| {
+        for k in &keys {
+            black_box(t.get(black_box(k)));
+        }
+    }));
+    let t = RefLock::new(t);
+    c.bench_function("reflock_get", |b| b.iter(|| {
+        for k in &keys {
+            black_box(t.borrow().get(black_box(k)));
+        }
+    }));
+    c.bench_function("reflock_set", |b| b.iter(|| {
+        for k in &keys {
+            t.borrow_mut().set(k.clone(), Value::Boolean(true));
+        }
+    }));
+}
+
+criterion_group!(vm, benches, reflock);
+criterion_main!(vm);
@@ -0,0 +1,177 @@
+// Syntax tree with source spans and comments, for tools like the
+// language server, the linter and the formatter. It covers the grammar
+// of `parse::load`, but the interpreter itself still goes from tokens
+// to byte codes directly.
+use crate::lex::{Comment, Pos, Span};
+
+// ANCHOR: ast
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub stats: Vec<Stat>,
+    pub comments: Vec<Comment>, // all of the chunk, in its outermost block
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stat {
+    Call(FuncCall),
+    // `Name {, Name} = explist`, or `prefixexp.Name = exp` and `prefixexp[exp] = exp`
+    Assign { targets: Vec<Expr>, values: Vec<Expr>, span: Span },
+    Local { names: Vec<LocalName>, values: Vec<Expr>, span: Span },
+    Do(Block, Span),
+    While { cond: Expr, body: Block, span: Span },
+    Break(Span),
+    Return(Vec<Expr>, Span),
+}
+
+// Attributes of local variables, `local x <const>` and `local x <close>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attrib {
+    None,
+    Const,
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalName {
+    pub name: String,
+    pub attrib: Attrib,
+    pub span: Span, // of the name
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuncCall {
+    pub func: Expr,
+    pub args: Vec<Expr>,
+    pub parens: bool, // `f("x")` rather than `f "x"` or `f {...}`
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Nil(Span),
+    Boolean(bool, Span),
+    Integer(i64, Span),
+    Float(f64, Span),
+    String(Vec<u8>, Span),
+    Name(String, Span),
+    Field(Box<Expr>, String, Span),    // `exp.Name`
+    Index(Box<Expr>, Box<Expr>, Span), // `exp[exp]`
+    Call(Box<FuncCall>),
+    Table(Vec<Field>, Span),
+}
+
+// A field of a table constructor.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    Item(Expr),                // `exp`
+    Named(String, Span, Expr), // `Name = exp`
+    Keyed(Expr, Expr),         // `[exp] = exp`
+}
+// ANCHOR_END: ast
+
+impl Stat {
+    pub fn span(&self) -> Span {
+        match self {
+            Stat::Call(call) => call.span,
+            Stat::Assign { span, .. } | Stat::Local { span, .. } | Stat::While { span, .. } => *span,
+            Stat::Do(_, span) | Stat::Break(span) | Stat::Return(_, span) => *span,
+        }
+    }
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Nil(span) | Expr::Boolean(_, span) | Expr::Integer(_, span) | Expr::Float(_, span)
+                | Expr::String(_, span) | Expr::Name(_, span) | Expr::Field(_, _, span)
+                | Expr::Index(_, _, span) | Expr::Table(_, span) => *span,
+            Expr::Call(call) => call.span,
+        }
+    }
+}
+
+// ANCHOR: walk
+impl Block {
+    // all expressions in source order, each before the ones inside it
+    pub fn exprs(&self) -> Vec<&Expr> {
+        let mut exprs = Vec::new();
+        block_exprs(self, &mut exprs);
+        exprs
+    }
+
+    // all calls, as statements or in expressions
+    pub fn calls(&self) -> Vec<&FuncCall> {
+        let mut calls = Vec::new();
+        stat_calls(self, &mut calls);
+        calls.extend(self.exprs().into_iter().filter_map(|e| match e {
+            Expr::Call(call) => Some(&**call),
+            _ => None,
+        }));
+        calls
+    }
+
+    // the innermost expression at `pos`
+    pub fn expr_at(&self, pos: Pos) -> Option<&Expr> {
+        self.exprs().into_iter().rev().find(|e| e.span().contains(pos))
+    }
+}
+
+fn stat_calls<'a>(block: &'a Block, calls: &mut Vec<&'a FuncCall>) {
+    for stat in &block.stats {
+        match stat {
+            Stat::Call(call) => calls.push(call),
+            Stat::Do(body, _) | Stat::While { body, .. } => stat_calls(body, calls),
+            _ => (),
+        }
+    }
+}
+
+fn block_exprs<'a>(block: &'a Block, exprs: &mut Vec<&'a Expr>) {
+    for stat in &block.stats {
+        match stat {
+            Stat::Call(call) => call_exprs(call, exprs),
+            Stat::Assign { targets, values, .. } => {
+                targets.iter().chain(values).for_each(|e| expr_exprs(e, exprs));
+            }
+            Stat::Local { values, .. } | Stat::Return(values, _) => {
+                values.iter().for_each(|e| expr_exprs(e, exprs));
+            }
+            Stat::Do(body, _) => block_exprs(body, exprs),
+            Stat::While { cond, body, .. } => {
+                expr_exprs(cond, exprs);
+                block_exprs(body, exprs);
+            }
+            Stat::Break(_) => (),
+        }
+    }
+}
+
+fn call_exprs<'a>(call: &'a FuncCall, exprs: &mut Vec<&'a Expr>) {
+    expr_exprs(&call.func, exprs);
+    call.args.iter().for_each(|e| expr_exprs(e, exprs));
+}
+
+fn expr_exprs<'a>(expr: &'a Expr, exprs: &mut Vec<&'a Expr>) {
+    exprs.push(expr);
+    match expr {
+        Expr::Field(table, _, _) => expr_exprs(table, exprs),
+        Expr::Index(table, key, _) => {
+            expr_exprs(table, exprs);
+            expr_exprs(key, exprs);
+        }
+        Expr::Call(call) => call_exprs(call, exprs),
+        Expr::Table(fields, _) => {
+            for field in fields {
+                match field {
+                    Field::Item(v) | Field::Named(_, _, v) => expr_exprs(v, exprs),
+                    Field::Keyed(k, v) => {
+                        expr_exprs(k, exprs);
+                        expr_exprs(v, exprs);
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+}
+// ANCHOR_END: walk
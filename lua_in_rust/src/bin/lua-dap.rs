@@ -0,0 +1,6 @@
+use std::io;
+
+// Debug adapter for VS Code and other DAP clients, talking over stdio.
+fn main() {
+    lua_rs::dap::run(io::stdin(), io::stdout());
+}
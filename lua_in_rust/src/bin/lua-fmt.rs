@@ -0,0 +1,90 @@
+use std::env;
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::process;
+
+use lua_rs::format::{self, Options, Quote};
+use lua_rs::parse;
+
+const USAGE: &str = "Usage: lua-fmt [--indent N] [--quote double|single] [--line-width N] [--check | --write] [file...]";
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    process::exit(2);
+}
+
+// Format the files, or stdin if none is given. By default the result is
+// printed, `--write` rewrites the files and `--check` only tells whether
+// they are formatted already.
+fn main() {
+    let mut opts = Options::default();
+    let mut check = false;
+    let mut write = false;
+    let mut files = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--indent" => opts.indent = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| usage()),
+            "--line-width" => opts.line_width = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| usage()),
+            "--quote" => opts.quote = match args.next().as_deref() {
+                Some("double") => Quote::Double,
+                Some("single") => Quote::Single,
+                _ => usage(),
+            },
+            "--check" => check = true,
+            "--write" => write = true,
+            s if s.starts_with("--") => usage(),
+            _ => files.push(arg),
+        }
+    }
+
+    if files.is_empty() {
+        let mut src = String::new();
+        io::stdin().read_to_string(&mut src).unwrap();
+        match format_source(&src, &opts) {
+            Ok(out) => print!("{out}"),
+            Err(err) => {
+                eprintln!("stdin:{err}");
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut unformatted = false;
+    for file in files {
+        let src = fs::read_to_string(&file).unwrap_or_else(|e| {
+            eprintln!("{file}: {e}");
+            process::exit(1);
+        });
+        let out = match format_source(&src, &opts) {
+            Ok(out) => out,
+            Err(err) => {
+                eprintln!("{file}:{err}");
+                process::exit(1);
+            }
+        };
+        if check {
+            if out != src {
+                println!("{file}");
+                unformatted = true;
+            }
+        } else if write {
+            if out != src {
+                fs::write(&file, out).unwrap();
+            }
+        } else {
+            print!("{out}");
+        }
+    }
+    if unformatted {
+        process::exit(1);
+    }
+}
+
+fn format_source(src: &str, opts: &Options) -> Result<String, parse::SyntaxError> {
+    let block = parse::parse_ast(Cursor::new(src))?;
+    Ok(format::format(&block, opts))
+}
+
@@ -0,0 +1,62 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use lua_rs::lint;
+
+const USAGE: &str = "Usage: lua-lint [--globals name,...] [--format human|json] file...";
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    process::exit(2);
+}
+
+// Report problems in the files, and exit with 1 if there is any.
+fn main() {
+    let mut allowed = Vec::new();
+    let mut json = false;
+    let mut files = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--globals" => {
+                let names = args.next().unwrap_or_else(|| usage());
+                allowed.extend(names.split(',').map(|s| s.trim().to_string()));
+            }
+            "--format" => json = match args.next().as_deref() {
+                Some("human") => false,
+                Some("json") => true,
+                _ => usage(),
+            },
+            s if s.starts_with("--") => usage(),
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        usage();
+    }
+
+    let mut reports = Vec::new();
+    let mut found = false;
+    for file in &files {
+        let src = fs::read_to_string(file).unwrap_or_else(|e| {
+            eprintln!("{file}: {e}");
+            process::exit(2);
+        });
+        for diag in lint::lint(&src, &allowed) {
+            found = true;
+            if json {
+                reports.push(diag.to_json(file));
+            } else {
+                println!("{}", diag.to_human(file));
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    }
+    if found {
+        process::exit(1);
+    }
+}
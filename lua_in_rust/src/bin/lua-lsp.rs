@@ -0,0 +1,6 @@
+use std::io;
+
+// Language server for editors, talking over stdio.
+fn main() {
+    lua_rs::lsp::run(io::stdin(), io::stdout());
+}
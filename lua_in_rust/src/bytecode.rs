@@ -1,6 +1,31 @@
-#[derive(Debug)]
+// Each byte code is 32 bits, like Lua's instructions: a tag and up to
+// three u8 operands, or one u8 and one i16.
+#[derive(Debug, Clone, Copy)]
 pub enum ByteCode {
     GetGlobal(u8, u8),
+    SetGlobal(u8, u8), // name constant, source register
     LoadConst(u8, u8),
-    Call(u8, u8),
+    LoadNil(u8),
+    LoadBool(u8, bool),
+    LoadInt(u8, i16),
+    Move(u8, u8),
+    Call(u8, u8, u8), // function register, arguments, results wanted
+    Return(u8, u8),   // first register, number of values
+
+    // tables
+    NewTable(u8),
+    GetField(u8, u8, u8), // destination, table, key constant
+    GetTable(u8, u8, u8), // destination, table, key register
+    SetField(u8, u8, u8), // table, key constant, source
+    SetTable(u8, u8, u8), // table, key register, source
+
+    // jumps are relative to the next byte code
+    Jump(i16),
+    Test(u8, i16), // jump if the register is false or nil
+
+    // to-be-closed variables
+    Tbc(u8, u8), // mark the register, with the variable name constant
+    Close(u8),   // close marked registers from this one up
 }
+
+const _: () = assert!(std::mem::size_of::<ByteCode>() == 4);
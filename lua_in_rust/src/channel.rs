@@ -0,0 +1,107 @@
+// Passing values between states, e.g. running in different threads.
+//
+// Values are copied, not shared: a table sent is rebuilt in the
+// receiving state, so the states stay independent. Only plain data can
+// be sent: nil, booleans, numbers, strings, light userdata like
+// `json.null`, and tables of those, without their metatables.
+use std::sync::{mpsc, Arc};
+use crate::value::{Table, Value};
+
+// ANCHOR: data
+// A value copied out of a state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Data {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(Vec<u8>),
+    LightUserData(usize),
+    Table(Vec<(Data, Data)>),
+}
+
+impl Data {
+    pub fn from_value(v: &Value) -> Result<Data, String> {
+        copy(v, &mut Vec::new())
+    }
+
+    pub fn into_value(self) -> Value {
+        match self {
+            Data::Nil => Value::Nil,
+            Data::Boolean(b) => Value::Boolean(b),
+            Data::Integer(i) => Value::Integer(i),
+            Data::Float(f) => Value::Float(f),
+            Data::String(s) => s.into(),
+            Data::LightUserData(p) => Value::LightUserData(p),
+            Data::Table(entries) => {
+                let mut t = Table::new();
+                for (k, v) in entries {
+                    t.set(k.into_value(), v.into_value());
+                }
+                t.into()
+            }
+        }
+    }
+}
+
+// `seen` are the tables being copied, to stop at cycles
+fn copy(v: &Value, seen: &mut Vec<*const ()>) -> Result<Data, String> {
+    Ok(match v {
+        Value::Nil => Data::Nil,
+        Value::Boolean(b) => Data::Boolean(*b),
+        Value::Integer(i) => Data::Integer(*i),
+        Value::Float(f) => Data::Float(*f),
+        Value::String(s) => Data::String(s.to_vec()),
+        Value::LightUserData(p) => Data::LightUserData(*p),
+        Value::Table(t) => {
+            let ptr = Arc::as_ptr(t) as *const ();
+            if seen.contains(&ptr) {
+                return Err(String::from("cannot send a table with cycles"));
+            }
+            seen.push(ptr);
+            let t = t.borrow();
+            let mut entries = Vec::with_capacity(t.array.len() + t.map.len());
+            for (i, v) in t.array.iter().enumerate() {
+                entries.push((Data::Integer(i as i64 + 1), copy(v, seen)?));
+            }
+            for (k, v) in &t.map {
+                entries.push((copy(k, seen)?, copy(v, seen)?));
+            }
+            seen.pop();
+            Data::Table(entries)
+        }
+        Value::Function(_) => return Err(String::from("cannot send a function")),
+    })
+}
+// ANCHOR_END: data
+
+// ANCHOR: channel
+pub fn channel() -> (Sender, Receiver) {
+    let (tx, rx) = mpsc::channel();
+    (Sender(tx), Receiver(rx))
+}
+
+#[derive(Clone)]
+pub struct Sender(mpsc::Sender<Data>);
+
+pub struct Receiver(mpsc::Receiver<Data>);
+
+impl Sender {
+    // Err if the value is not plain data or the receiver is gone.
+    pub fn send(&self, v: &Value) -> Result<(), String> {
+        let data = Data::from_value(v)?;
+        self.0.send(data).map_err(|_| String::from("receiver is closed"))
+    }
+}
+
+impl Receiver {
+    // The next value, waiting for it, or None once all senders are gone.
+    pub fn recv(&self) -> Option<Value> {
+        self.0.recv().ok().map(Data::into_value)
+    }
+
+    pub fn try_recv(&self) -> Option<Value> {
+        self.0.try_recv().ok().map(Data::into_value)
+    }
+}
+// ANCHOR_END: channel
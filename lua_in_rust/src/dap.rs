@@ -0,0 +1,521 @@
+// Debug Adapter Protocol server over stdio, built on the VM hooks.
+use std::any::Any;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, LineWriter, Read, Write};
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use serde_json::{json, Value as Json};
+use crate::debug::HookEvent;
+use crate::parse::{self, ParseProto};
+use crate::value::{RefLock, Table, Value};
+use crate::vm::ExeState;
+
+// ANCHOR: message
+// Read one message framed by a `Content-Length` header.
+// Returns `None` at the end of input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            length = n.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut buf = vec![0; length];
+    input.read_exact(&mut buf)?;
+    Ok(Some(serde_json::from_slice(&buf)?))
+}
+
+pub fn write_message(output: &mut impl Write, msg: &Json) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+// ANCHOR_END: message
+
+// ANCHOR: session
+// How to go on after a stop. The depth is the call stack depth
+// when the step began.
+#[derive(Debug, Clone, Copy)]
+enum Resume {
+    Continue,
+    StepIn,
+    Next(usize),
+    StepOut(usize),
+}
+
+enum Action {
+    None,
+    Run,
+    Resume(Resume),
+    Disconnect,
+}
+
+// What a variablesReference from 2 on stands for, by reference - 2.
+// 1 is the globals.
+#[derive(Clone)]
+enum Ref {
+    Locals(usize), // of the frame at this level
+    Table(Arc<RefLock<Table>>),
+}
+
+// The panic payload which unwinds the script out of `execute` when the
+// client disconnects while it runs.
+struct Stopped;
+
+struct Session {
+    output: Box<dyn Write + Send>,
+    seq: u64,
+    requests: Receiver<Json>,
+    program: String,
+    path: PathBuf, // of the program, to match the breakpoints to
+    proto: Option<ParseProto>,
+    breakpoints: HashMap<PathBuf, BTreeSet<u32>>,
+    resume: Resume,
+    stop_on_entry: bool,
+    pause: bool,
+    // scopes and tables shown while stopped
+    refs: Vec<Ref>,
+}
+
+impl Session {
+    fn send(&mut self, mut msg: Json) {
+        self.seq += 1;
+        msg["seq"] = json!(self.seq);
+        write_message(&mut self.output, &msg).unwrap();
+    }
+
+    fn respond(&mut self, req: &Json, body: Json) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": req["seq"],
+            "command": req["command"],
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    fn respond_error(&mut self, req: &Json, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": req["seq"],
+            "command": req["command"],
+            "success": false,
+            "message": message,
+        }));
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+
+    // Handle requests until one of them needs the caller to act.
+    fn wait(&mut self, state: Option<&ExeState>) -> Action {
+        loop {
+            let Ok(req) = self.requests.recv() else {
+                return Action::Disconnect;
+            };
+            match self.handle(&req, state) {
+                Action::None => (),
+                action => return action,
+            }
+        }
+    }
+
+    fn handle(&mut self, req: &Json, state: Option<&ExeState>) -> Action {
+        let args = &req["arguments"];
+        match req["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                self.respond(req, json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsEvaluateForHovers": true,
+                }));
+                self.event("initialized", json!({}));
+            }
+            "launch" => {
+                self.program = args["program"].as_str().unwrap_or_default().to_string();
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                self.path = canonical(&self.program);
+                match File::open(&self.program) {
+                    // syntax errors are panics of the parser
+                    Ok(file) => match panic::catch_unwind(|| parse::load(file, &self.program)) {
+                        Ok(proto) => {
+                            self.proto = Some(proto);
+                            self.respond(req, json!({}));
+                        }
+                        Err(err) => {
+                            let msg = format!("{}: {}", self.program, panic_message(&*err));
+                            self.respond_error(req, &msg);
+                        }
+                    },
+                    Err(err) => {
+                        let msg = format!("cannot open {}: {err}", self.program);
+                        self.respond_error(req, &msg);
+                    }
+                }
+            }
+            "setBreakpoints" => {
+                // they replace the ones of the same source
+                let path = canonical(args["source"]["path"].as_str().unwrap_or_default());
+                let mut lines = BTreeSet::new();
+                let mut verified = Vec::new();
+                for bp in args["breakpoints"].as_array().into_iter().flatten() {
+                    let line = bp["line"].as_u64().unwrap_or(0) as u32;
+                    // only lines of the program with byte codes can be hit
+                    let ok = match &self.proto {
+                        Some(proto) => path == self.path && proto.lines.contains(&line),
+                        None => true,
+                    };
+                    if ok {
+                        lines.insert(line);
+                    }
+                    verified.push(json!({"verified": ok, "line": line}));
+                }
+                self.breakpoints.insert(path, lines);
+                self.respond(req, json!({"breakpoints": verified}));
+            }
+            "configurationDone" => {
+                self.respond(req, json!({}));
+                return Action::Run;
+            }
+            "threads" => {
+                self.respond(req, json!({"threads": [{"id": 1, "name": "main"}]}));
+            }
+            "stackTrace" => match state {
+                Some(state) => {
+                    let frames = self.stack_frames(state);
+                    let total = frames.len();
+                    self.respond(req, json!({"stackFrames": frames, "totalFrames": total}));
+                }
+                None => self.respond_error(req, "not stopped"),
+            },
+            "scopes" => {
+                let level = args["frameId"].as_u64().unwrap_or(0) as usize;
+                self.refs.push(Ref::Locals(level));
+                let locals = self.refs.len() + 1;
+                self.respond(req, json!({"scopes": [
+                    {"name": "Locals", "variablesReference": locals, "expensive": false},
+                    {"name": "Globals", "variablesReference": 1, "expensive": false},
+                ]}));
+            }
+            "variables" => match (state, args["variablesReference"].as_u64().unwrap_or(0)) {
+                (Some(state), 1) => {
+                    let mut globals: Vec<_> = state.globals().collect();
+                    globals.sort_by(|a, b| a.0.cmp(b.0));
+                    let vars: Vec<_> = globals.into_iter()
+                        .map(|(name, v)| self.variable(name.clone(), v))
+                        .collect();
+                    self.respond(req, json!({"variables": vars}));
+                }
+                (Some(state), n) if n >= 2 && ((n - 2) as usize) < self.refs.len() => {
+                    let t = match self.refs[(n - 2) as usize].clone() {
+                        Ref::Table(t) => t,
+                        Ref::Locals(level) => {
+                            let mut vars = Vec::new();
+                            let mut n = 1;
+                            while let Some((name, v)) = state.getlocal(level, n) {
+                                vars.push(self.variable(name.to_string(), v));
+                                n += 1;
+                            }
+                            self.respond(req, json!({"variables": vars}));
+                            return Action::None;
+                        }
+                    };
+                    let t = t.borrow();
+                    let mut entries: Vec<_> = t.map.iter()
+                        .map(|(k, v)| match k {
+                            Value::String(s) => (String::from_utf8_lossy(s).into_owned(), v),
+                            k => (format!("[{}]", display(k)), v),
+                        })
+                        .collect();
+                    entries.sort_by(|a, b| a.0.cmp(&b.0));
+                    let vars: Vec<_> = t.array.iter().enumerate()
+                        .map(|(i, v)| (format!("[{}]", i + 1), v))
+                        .chain(entries)
+                        .map(|(name, v)| self.variable(name, v))
+                        .collect();
+                    self.respond(req, json!({"variables": vars}));
+                }
+                (Some(_), _) => self.respond_error(req, "invalid variablesReference"),
+                (None, _) => self.respond_error(req, "not stopped"),
+            },
+            "evaluate" => {
+                let expr = args["expression"].as_str().unwrap_or_default().trim();
+                match (state, is_name(expr)) {
+                    (None, _) => self.respond_error(req, "not stopped"),
+                    (_, false) => self.respond_error(req, "only names can be evaluated"),
+                    (Some(state), true) => {
+                        // a local of the frame, or else a global
+                        let level = args["frameId"].as_u64().unwrap_or(0) as usize;
+                        let v = local(state, level, expr)
+                            .or_else(|| state.get_global(expr))
+                            .unwrap_or(&Value::Nil);
+                        let var = self.variable(String::new(), v);
+                        self.respond(req, json!({
+                            "result": var["value"],
+                            "type": var["type"],
+                            "variablesReference": var["variablesReference"],
+                        }));
+                    }
+                }
+            }
+            "continue" => {
+                self.respond(req, json!({"allThreadsContinued": true}));
+                return Action::Resume(Resume::Continue);
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let depth = state.map_or(0, |s| s.frames().len());
+                let resume = match req["command"].as_str() {
+                    Some("next") => Resume::Next(depth),
+                    Some("stepIn") => Resume::StepIn,
+                    _ => Resume::StepOut(depth),
+                };
+                self.respond(req, json!({}));
+                return Action::Resume(resume);
+            }
+            "pause" => {
+                self.pause = true;
+                self.respond(req, json!({}));
+            }
+            "disconnect" => {
+                self.respond(req, json!({}));
+                return Action::Disconnect;
+            }
+            _ => self.respond_error(req, "unsupported request"),
+        }
+        Action::None
+    }
+
+    // A variable, or the result of evaluate. Tables get a reference
+    // so the client can expand them.
+    fn variable(&mut self, name: String, v: &Value) -> Json {
+        let reference = match v {
+            Value::Table(t) => {
+                self.refs.push(Ref::Table(t.clone()));
+                self.refs.len() + 1
+            }
+            _ => 0,
+        };
+        json!({
+            "name": name,
+            "value": display(v),
+            "type": v.type_name(),
+            "variablesReference": reference,
+        })
+    }
+
+    fn stack_frames(&self, state: &ExeState) -> Vec<Json> {
+        state.frames().iter().rev().enumerate().map(|(id, ci)| {
+            if ci.what == "main" {
+                json!({
+                    "id": id,
+                    "name": "main chunk",
+                    "source": {"name": ci.source.rsplit('/').next(), "path": ci.source},
+                    "line": ci.currentline.unwrap_or(0),
+                    "column": 1,
+                })
+            } else {
+                json!({
+                    "id": id,
+                    "name": ci.name.as_deref().unwrap_or("?"),
+                    "line": 0,
+                    "column": 0,
+                    "presentationHint": "subtle",
+                })
+            }
+        }).collect()
+    }
+
+    // The line hook: decide whether to stop here. Breaks when the client
+    // disconnects, to end the script.
+    fn on_line(&mut self, state: &ExeState, line: u32) -> ControlFlow<()> {
+        // handle requests which came while running, e.g. "pause"
+        while let Ok(req) = self.requests.try_recv() {
+            if let Action::Disconnect = self.handle(&req, Some(state)) {
+                return ControlFlow::Break(());
+            }
+        }
+
+        let depth = state.frames().len();
+        let reason = if self.stop_on_entry {
+            "entry"
+        } else if self.pause {
+            "pause"
+        } else if self.breakpoints.get(&self.path).is_some_and(|lines| lines.contains(&line)) {
+            "breakpoint"
+        } else if match self.resume {
+            Resume::Continue => false,
+            Resume::StepIn => true,
+            Resume::Next(d) => depth <= d,
+            Resume::StepOut(d) => depth < d,
+        } {
+            "step"
+        } else {
+            return ControlFlow::Continue(());
+        };
+        self.stop_on_entry = false;
+        self.pause = false;
+
+        self.event("stopped", json!({"reason": reason, "threadId": 1, "allThreadsStopped": true}));
+        let action = self.wait(Some(state));
+        self.refs.clear();
+        match action {
+            Action::Resume(resume) => self.resume = resume,
+            Action::Disconnect => return ControlFlow::Break(()),
+            _ => (),
+        }
+        ControlFlow::Continue(())
+    }
+}
+// ANCHOR_END: session
+
+// "print" output of the script, sent as "output" events
+struct Output(Arc<Mutex<Session>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let output = String::from_utf8_lossy(buf);
+        self.0.lock().unwrap().event("output", json!({"category": "stdout", "output": output}));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn display(v: &Value) -> String {
+    match v {
+        // escaped as Rust does, with \xNN for bytes that are not UTF-8
+        Value::String(s) => {
+            let mut out = String::from("\"");
+            for chunk in s.utf8_chunks() {
+                out.extend(chunk.valid().chars().flat_map(char::escape_debug));
+                out.extend(chunk.invalid().iter().map(|b| format!("\\x{b:02X}")));
+            }
+            out.push('"');
+            out
+        }
+        Value::Table(t) => format!("table ({} entries)", t.borrow().array.len() + t.borrow().map.len()),
+        v => format!("{v:?}"),
+    }
+}
+
+// the innermost local called `name` in the frame at `level`
+fn local<'a>(state: &'a ExeState, level: usize, name: &str) -> Option<&'a Value> {
+    (1..).map_while(|n| state.getlocal(level, n))
+        .filter(|(local, _)| *local == name)
+        .last()
+        .map(|(_, v)| v)
+}
+
+fn canonical(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+fn panic_message(err: &(dyn Any + Send)) -> String {
+    match (err.downcast_ref::<String>(), err.downcast_ref::<&str>()) {
+        (Some(s), _) => s.clone(),
+        (_, Some(s)) => s.to_string(),
+        _ => String::from("unknown error"),
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// ANCHOR: run
+// Serve one debug session: requests are read from `input` and
+// responses and events are written to `output`.
+pub fn run(input: impl Read + Send + 'static, output: impl Write + Send + 'static) {
+    // read requests in another thread, so "pause" can arrive while running
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        while let Ok(Some(msg)) = read_message(&mut input) {
+            if sender.send(msg).is_err() {
+                break;
+            }
+        }
+    });
+
+    let session = Arc::new(Mutex::new(Session {
+        output: Box::new(output),
+        seq: 0,
+        requests,
+        program: String::new(),
+        path: PathBuf::new(),
+        proto: None,
+        breakpoints: HashMap::new(),
+        resume: Resume::Continue,
+        stop_on_entry: false,
+        pause: false,
+        refs: Vec::new(),
+    }));
+
+    // configuration, until "configurationDone"
+    let action = session.lock().unwrap().wait(None);
+    if let Action::Run = action {
+        let proto = session.lock().unwrap().proto.take();
+        let mut exit_code = 0;
+        if let Some(proto) = proto {
+            let mut state = ExeState::new();
+            state.set_stdout(Box::new(LineWriter::new(Output(session.clone()))));
+            let hook_session = session.clone();
+            state.sethook(Box::new(move |state, event| {
+                if let HookEvent::Line(line) = event {
+                    let flow = hook_session.lock().unwrap().on_line(state, line);
+                    if flow.is_break() {
+                        // not a panic, so the panic hook prints nothing
+                        panic::resume_unwind(Box::new(Stopped));
+                    }
+                }
+            }), "l", 0);
+            let result = panic::catch_unwind(AssertUnwindSafe(|| state.execute(&proto)));
+            // flush the output before the session is locked below
+            drop(state);
+            match result {
+                Ok(_) => (),
+                // the client is gone
+                Err(err) if err.is::<Stopped>() => return,
+                Err(err) => {
+                    let output = format!("{}\n", panic_message(&*err));
+                    session.lock().unwrap().event("output", json!({"category": "stderr", "output": output}));
+                    exit_code = 1;
+                }
+            }
+        }
+
+        let mut session = session.lock().unwrap();
+        session.event("terminated", json!({}));
+        session.event("exited", json!({"exitCode": exit_code}));
+        drop(session);
+    }
+
+    // answer the remaining requests until "disconnect"
+    if !matches!(action, Action::Disconnect) {
+        while !matches!(session.lock().unwrap().wait(None), Action::Disconnect) {}
+    }
+}
+// ANCHOR_END: run
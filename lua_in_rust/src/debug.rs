@@ -0,0 +1,281 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+use crate::bytecode::ByteCode;
+use crate::parse::ParseProto;
+use crate::value::{Table, Value};
+use crate::vm::ExeState;
+
+// ANCHOR: hook
+// Events passed to the hook function, as in Lua's `debug.sethook`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookEvent {
+    Call,
+    Return,
+    Line(u32),
+    Count,
+}
+
+pub type Hook = Box<dyn FnMut(&mut ExeState, HookEvent) + Send>;
+
+// Which events are passed to the hook. Built from a Lua mask
+// string like "crl" and an instruction count.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HookMask {
+    pub call: bool,
+    pub ret: bool,
+    pub line: bool,
+    pub count: u32,
+}
+
+impl HookMask {
+    pub fn new(mask: &str, count: u32) -> Self {
+        HookMask {
+            call: mask.contains('c'),
+            ret: mask.contains('r'),
+            line: mask.contains('l'),
+            count,
+        }
+    }
+
+    pub fn mask(&self) -> String {
+        let mut s = String::new();
+        if self.call { s.push('c'); }
+        if self.ret { s.push('r'); }
+        if self.line { s.push('l'); }
+        s
+    }
+}
+// ANCHOR_END: hook
+
+// ANCHOR: callinfo
+// One entry of the call stack, which is what `debug.getinfo` reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallInfo {
+    pub source: Cow<'static, str>,
+    pub what: &'static str, // "main" or "Rust"
+    pub name: Option<Arc<str>>,
+    pub currentline: Option<u32>,
+    pub nups: u8,
+    pub nparams: u8,
+}
+
+impl CallInfo {
+    pub fn main(proto: &ParseProto) -> Self {
+        CallInfo {
+            source: Cow::Owned(proto.source.clone()),
+            what: "main",
+            name: None,
+            currentline: None,
+            nups: 0,
+            nparams: 0,
+        }
+    }
+
+    // no allocation, as this is for every call
+    pub fn rust(name: Option<Arc<str>>) -> Self {
+        CallInfo {
+            source: Cow::Borrowed("[Rust]"),
+            what: "Rust",
+            name,
+            currentline: None,
+            nups: 0,
+            nparams: 0,
+        }
+    }
+}
+// ANCHOR_END: callinfo
+
+// ANCHOR: funcname
+// Find the name of the function in register `func` at `pc`, by looking
+// back for the byte code that loaded it, as Lua's `getfuncname` does.
+pub fn funcname(proto: &ParseProto, pc: usize, func: u8) -> Option<String> {
+    for code in proto.byte_codes[..pc].iter().rev() {
+        match *code {
+            ByteCode::GetGlobal(dst, name) | ByteCode::GetField(dst, _, name) if dst == func => {
+                return match &proto.constants[name as usize] {
+                    Value::String(s) => Some(String::from_utf8_lossy(s).into_owned()),
+                    _ => None,
+                };
+            }
+            ByteCode::LoadConst(dst, _) | ByteCode::Move(dst, _) | ByteCode::GetTable(dst, _, _)
+                | ByteCode::NewTable(dst) if dst == func => return None,
+            _ => (),
+        }
+    }
+    None
+}
+// ANCHOR_END: funcname
+
+// ANCHOR: traceback
+// "debug.traceback": the message followed by the current call stack,
+// innermost frame first.
+pub fn traceback(state: &ExeState, msg: Option<&str>) -> String {
+    traceback_from(state, msg, 0)
+}
+
+// the traceback without the innermost `level` frames
+fn traceback_from(state: &ExeState, msg: Option<&str>, level: usize) -> String {
+    let mut s = String::new();
+    if let Some(msg) = msg {
+        s.push_str(msg);
+        s.push('\n');
+    }
+    s.push_str("stack traceback:");
+    for ci in state.frames().iter().rev().skip(level) {
+        s.push_str("\n\t");
+        s.push_str(&ci.source);
+        if let Some(line) = ci.currentline {
+            s.push_str(&format!(":{line}"));
+        }
+        match (&ci.name, ci.what) {
+            (_, "main") => s.push_str(": in main chunk"),
+            (Some(name), _) => s.push_str(&format!(": in function '{name}'")),
+            (None, _) => s.push_str(": in function <?>"),
+        }
+    }
+    s
+}
+// ANCHOR_END: traceback
+
+// ANCHOR: lib
+// The "debug" library for Lua. Levels count from the running function,
+// which is the library function itself, so level 1 is its caller. There
+// are no Lua functions yet, so the hooks set by `sethook` are library
+// functions like `print`, and Rust code can set any with
+// `ExeState::sethook`.
+pub fn lib() -> Table {
+    let mut t = Table::new();
+    t.set("gethook".into(), Value::Function(db_gethook));
+    t.set("getinfo".into(), Value::Function(db_getinfo));
+    t.set("getlocal".into(), Value::Function(db_getlocal));
+    t.set("getupvalue".into(), Value::Function(db_getupvalue));
+    t.set("sethook".into(), Value::Function(db_sethook));
+    t.set("setlocal".into(), Value::Function(db_setlocal));
+    t.set("setupvalue".into(), Value::Function(db_setupvalue));
+    t.set("traceback".into(), Value::Function(db_traceback));
+    t
+}
+
+fn check_level(state: &ExeState, fname: &str) -> usize {
+    let level = state.check_integer(1, fname);
+    let level = usize::try_from(level).ok().filter(|&l| state.getinfo(l).is_some());
+    state.arg_check(level.is_some(), 1, fname, "level out of range");
+    level.unwrap()
+}
+
+// debug.sethook([f, mask [, count]]): call `f` with the name of the
+// event, and the line for "line" events. Without arguments, turn off
+// the hook.
+fn db_sethook(state: &mut ExeState) -> i32 {
+    if state.arg_count() == 0 {
+        state.clear_hook();
+        return 0;
+    }
+    let f = state.check_function(1, "sethook");
+    let mask = String::from_utf8_lossy(state.check_string(2, "sethook")).into_owned();
+    let count = state.opt_integer(3, "sethook", 0);
+    let count = u32::try_from(count).unwrap_or(0);
+    state.sethook(Box::new(move |state, event| {
+        let args = match event {
+            HookEvent::Call => vec!["call".into()],
+            HookEvent::Return => vec!["return".into()],
+            HookEvent::Line(line) => vec!["line".into(), Value::Integer(line as i64)],
+            HookEvent::Count => vec!["count".into()],
+        };
+        state.call(Value::Function(f), args);
+    }), &mask, count);
+    0
+}
+
+// debug.gethook(): the mask and count of the hook, or nothing
+fn db_gethook(state: &mut ExeState) -> i32 {
+    match state.gethook() {
+        Some((mask, count)) => {
+            state.push(mask.as_str().into());
+            state.push(Value::Integer(count as i64));
+            2
+        }
+        None => 0,
+    }
+}
+
+// debug.getinfo(level): a table with the fields of `CallInfo`, or nil
+fn db_getinfo(state: &mut ExeState) -> i32 {
+    let level = state.check_integer(1, "getinfo");
+    let Some(ci) = usize::try_from(level).ok().and_then(|l| state.getinfo(l)) else {
+        state.push(Value::Nil);
+        return 1;
+    };
+    let mut t = Table::new();
+    t.set("source".into(), ci.source.as_ref().into());
+    t.set("what".into(), ci.what.into());
+    if let Some(name) = &ci.name {
+        t.set("name".into(), name.as_ref().into());
+    }
+    if let Some(line) = ci.currentline {
+        t.set("currentline".into(), Value::Integer(line as i64));
+    }
+    t.set("nups".into(), Value::Integer(ci.nups as i64));
+    t.set("nparams".into(), Value::Integer(ci.nparams as i64));
+    state.push(t.into());
+    1
+}
+
+// debug.getlocal(level, n): the name and value of the local, or nil
+fn db_getlocal(state: &mut ExeState) -> i32 {
+    let level = check_level(state, "getlocal");
+    let n = state.check_integer(2, "getlocal");
+    match usize::try_from(n).ok().and_then(|n| state.getlocal(level, n)) {
+        Some((name, v)) => {
+            let (name, v) = (Value::from(name), v.clone());
+            state.push(name);
+            state.push(v);
+            2
+        }
+        None => {
+            state.push(Value::Nil);
+            1
+        }
+    }
+}
+
+// debug.setlocal(level, n, v): the name of the local, or nil
+fn db_setlocal(state: &mut ExeState) -> i32 {
+    let level = check_level(state, "setlocal");
+    let n = state.check_integer(2, "setlocal");
+    let v = state.arg(3).clone();
+    let name = usize::try_from(n).ok().and_then(|n| state.setlocal(level, n, v));
+    state.push(name.map_or(Value::Nil, |name| name.as_str().into()));
+    1
+}
+
+// debug.getupvalue(f, n) and debug.setupvalue(f, n, v): Rust functions
+// and the main chunk have no upvalues, so there is never one to return.
+fn db_getupvalue(state: &mut ExeState) -> i32 {
+    state.check_function(1, "getupvalue");
+    state.check_integer(2, "getupvalue");
+    0
+}
+
+fn db_setupvalue(state: &mut ExeState) -> i32 {
+    state.check_function(1, "setupvalue");
+    state.check_integer(2, "setupvalue");
+    0
+}
+
+// debug.traceback([msg]): the traceback from the caller, after `msg`
+// if it is a string. Other messages are returned as they are.
+fn db_traceback(state: &mut ExeState) -> i32 {
+    let s = match state.arg(1) {
+        Value::Nil => traceback_from(state, None, 1),
+        Value::String(msg) => traceback_from(state, Some(&String::from_utf8_lossy(msg)), 1),
+        v => {
+            let v = v.clone();
+            state.push(v);
+            return 1;
+        }
+    };
+    state.push(s.as_str().into());
+    1
+}
+// ANCHOR_END: lib
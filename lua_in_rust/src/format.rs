@@ -0,0 +1,318 @@
+// Source formatter, printing the syntax tree back with comments.
+use crate::ast::{Attrib, Block, Expr, Field, FuncCall, Stat};
+use crate::lex::{Comment, Pos};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quote {
+    Double,
+    Single,
+}
+
+// ANCHOR: options
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub indent: usize, // spaces per level, 0 for a tab
+    pub quote: Quote,
+    pub line_width: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options { indent: 4, quote: Quote::Double, line_width: 80 }
+    }
+}
+// ANCHOR_END: options
+
+// ANCHOR: format
+// One statement per line, and nested blocks indented. Blank lines
+// between statements are kept, but at most one, and comments stay where
+// they were: on their own line or after the statement on the same line.
+// Comments inside an expression are moved before its statement.
+pub fn format(block: &Block, opts: &Options) -> String {
+    let mut f = Formatter { opts, comments: &block.comments, next: 0, out: String::new() };
+    f.block(block, 0, Pos { line: u32::MAX, column: u32::MAX });
+    if !f.out.is_empty() {
+        f.out.push('\n');
+    }
+    f.out
+}
+
+struct Formatter<'a> {
+    opts: &'a Options,
+    comments: &'a [Comment],
+    next: usize, // the first comment not written yet
+    out: String,
+}
+
+impl Formatter<'_> {
+    // The statements of `block` at indent level `depth`, and the comments
+    // before `end`.
+    fn block(&mut self, block: &Block, depth: usize, end: Pos) {
+        let mut last_line = None; // in the source, of the last item written
+        for stat in &block.stats {
+            let span = stat.span();
+            match stat {
+                Stat::Do(body, _) => {
+                    self.comments(span.start, depth, &mut last_line);
+                    self.start_line(depth, last_line, span.start.line);
+                    self.out.push_str("do");
+                    self.body(body, depth, span.end);
+                }
+                Stat::While { cond, body, .. } => {
+                    self.comments(span.start, depth, &mut last_line);
+                    self.start_line(depth, last_line, span.start.line);
+                    let cond = self.expr(cond, depth, self.width(depth) + "while ".len(), " do".len());
+                    self.out.push_str(&format!("while {cond} do"));
+                    self.body(body, depth, span.end);
+                }
+                _ => {
+                    self.comments(span.end, depth, &mut last_line);
+                    self.start_line(depth, last_line, span.start.line);
+                    let s = self.stat(stat, depth);
+                    self.out.push_str(&s);
+                }
+            }
+            last_line = Some(span.end.line);
+        }
+        self.comments(end, depth, &mut last_line);
+    }
+
+    // a nested block, and the `end` of its statement
+    fn body(&mut self, body: &Block, depth: usize, end: Pos) {
+        self.block(body, depth + 1, end);
+        self.out.push('\n');
+        self.out.push_str(&self.indent(depth));
+        self.out.push_str("end");
+    }
+
+    // Comments before `pos`, after the last item if on its line.
+    fn comments(&mut self, pos: Pos, depth: usize, last_line: &mut Option<u32>) {
+        while let Some(comment) = self.comments.get(self.next).filter(|c| c.span.start < pos) {
+            if *last_line == Some(comment.span.start.line) {
+                self.out.push(' ');
+            } else {
+                self.start_line(depth, *last_line, comment.span.start.line);
+            }
+            self.out.push_str(comment.text.trim_end());
+            *last_line = Some(comment.span.end.line);
+            self.next += 1;
+        }
+    }
+
+    // a new line for an item at source line `line`, after a blank one
+    // if there was one in the source
+    fn start_line(&mut self, depth: usize, last_line: Option<u32>, line: u32) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        if last_line.is_some_and(|l| line > l + 1) {
+            self.out.push('\n');
+        }
+        self.out.push_str(&self.indent(depth));
+    }
+
+    fn indent(&self, depth: usize) -> String {
+        if self.opts.indent == 0 { "\t".repeat(depth) } else { " ".repeat(self.opts.indent * depth) }
+    }
+
+    // the width of the indent, a tab counted as one
+    fn width(&self, depth: usize) -> usize {
+        self.opts.indent.max(1) * depth
+    }
+
+    // a statement without a block, from column `width(depth)`
+    fn stat(&self, stat: &Stat, depth: usize) -> String {
+        let col = self.width(depth);
+        match stat {
+            Stat::Call(call) => self.call(call, depth, col, 0),
+            Stat::Assign { targets, values, .. } => {
+                let targets = self.list(targets, depth, col);
+                let values = self.list(values, depth, col + targets.len() + " = ".len());
+                format!("{targets} = {values}")
+            }
+            Stat::Local { names, values, .. } => {
+                let names: Vec<String> = names.iter()
+                    .map(|n| match n.attrib {
+                        Attrib::None => n.name.clone(),
+                        Attrib::Const => format!("{} <const>", n.name),
+                        Attrib::Close => format!("{} <close>", n.name),
+                    })
+                    .collect();
+                let names = format!("local {}", names.join(", "));
+                if values.is_empty() {
+                    return names;
+                }
+                let values = self.list(values, depth, col + names.len() + " = ".len());
+                format!("{names} = {values}")
+            }
+            Stat::Break(_) => String::from("break"),
+            Stat::Return(values, _) if values.is_empty() => String::from("return"),
+            Stat::Return(values, _) => format!("return {}", self.list(values, depth, col + "return ".len())),
+            Stat::Do(..) | Stat::While { .. } => unreachable!("written by `block`"),
+        }
+    }
+
+    // `exprs` separated by `, ` from column `col`
+    fn list(&self, exprs: &[Expr], depth: usize, mut col: usize) -> String {
+        let mut s = String::new();
+        for (i, e) in exprs.iter().enumerate() {
+            if i > 0 {
+                s.push_str(", ");
+                col += 2;
+            }
+            let tail = if i + 1 < exprs.len() { 1 } else { 0 };
+            let e = self.expr(e, depth, col, tail);
+            col = match e.rfind('\n') {
+                Some(i) => e.len() - i - 1,
+                None => col + e.len(),
+            };
+            s.push_str(&e);
+        }
+        s
+    }
+
+    // An expression from column `col` followed by `tail` columns. If it
+    // does not fit in the line width, the outermost call or table is
+    // broken with an item per line.
+    fn expr(&self, e: &Expr, depth: usize, col: usize, tail: usize) -> String {
+        match e {
+            Expr::Call(call) => self.call(call, depth, col, tail),
+            Expr::Table(fields, _) => {
+                let flat = flat(e, self.opts);
+                if col + flat.len() + tail <= self.opts.line_width || fields.is_empty() {
+                    return flat;
+                }
+                self.table(fields, depth)
+            }
+            _ => flat(e, self.opts),
+        }
+    }
+
+    // `f "x"`, `f {...}` and `f("x", "y")` as written, but one argument
+    // per line within the parentheses if the call does not fit.
+    fn call(&self, call: &FuncCall, depth: usize, col: usize, tail: usize) -> String {
+        let line = flat_call(call, self.opts);
+        if col + line.len() + tail <= self.opts.line_width || call.args.is_empty() {
+            return line;
+        }
+
+        let func = flat(&call.func, self.opts);
+        if let (false, [Expr::Table(fields, _)]) = (call.parens, &call.args[..]) {
+            if !fields.is_empty() {
+                return format!("{func} {}", self.table(fields, depth));
+            }
+        }
+        let indent = self.indent(depth + 1);
+        let col = self.width(depth + 1);
+        let mut s = format!("{func}(\n");
+        for (i, arg) in call.args.iter().enumerate() {
+            let last = i + 1 == call.args.len();
+            s.push_str(&indent);
+            s.push_str(&self.expr(arg, depth + 1, col, if last { 0 } else { 1 }));
+            if !last {
+                s.push(',');
+            }
+            s.push('\n');
+        }
+        s.push_str(&self.indent(depth));
+        s.push(')');
+        s
+    }
+
+    // a table with a field per line
+    fn table(&self, fields: &[Field], depth: usize) -> String {
+        let indent = self.indent(depth + 1);
+        let col = self.width(depth + 1);
+        let mut s = String::from("{\n");
+        for field in fields {
+            s.push_str(&indent);
+            let field = match field {
+                Field::Item(v) => self.expr(v, depth + 1, col, 1),
+                Field::Named(name, _, v) => {
+                    format!("{name} = {}", self.expr(v, depth + 1, col + name.len() + 3, 1))
+                }
+                Field::Keyed(k, v) => {
+                    let k = flat(k, self.opts);
+                    format!("[{k}] = {}", self.expr(v, depth + 1, col + k.len() + 5, 1))
+                }
+            };
+            s.push_str(&field);
+            s.push_str(",\n");
+        }
+        s.push_str(&self.indent(depth));
+        s.push('}');
+        s
+    }
+}
+
+// an expression on a single line
+fn flat(e: &Expr, opts: &Options) -> String {
+    match e {
+        Expr::Nil(_) => String::from("nil"),
+        Expr::Boolean(b, _) => b.to_string(),
+        Expr::Integer(i, _) => i.to_string(),
+        // `{:?}` keeps a `.0` or uses an exponent, so it is read back as a float
+        Expr::Float(f, _) if f.is_infinite() => String::from("1e9999"),
+        Expr::Float(f, _) => format!("{f:?}"),
+        Expr::String(s, _) => format_string(s, opts.quote),
+        Expr::Name(name, _) => name.clone(),
+        Expr::Field(prefix, name, _) => format!("{}.{name}", flat(prefix, opts)),
+        Expr::Index(prefix, key, _) => format!("{}[{}]", flat(prefix, opts), flat(key, opts)),
+        Expr::Call(call) => flat_call(call, opts),
+        Expr::Table(fields, _) => {
+            let fields: Vec<String> = fields.iter()
+                .map(|field| match field {
+                    Field::Item(v) => flat(v, opts),
+                    Field::Named(name, _, v) => format!("{name} = {}", flat(v, opts)),
+                    Field::Keyed(k, v) => format!("[{}] = {}", flat(k, opts), flat(v, opts)),
+                })
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+    }
+}
+
+fn flat_call(call: &FuncCall, opts: &Options) -> String {
+    let func = flat(&call.func, opts);
+    let args: Vec<String> = call.args.iter().map(|a| flat(a, opts)).collect();
+    if call.parens {
+        format!("{func}({})", args.join(", "))
+    } else {
+        format!("{func} {}", args.join(", "))
+    }
+}
+
+// Quote with the preferred quote, unless only the other one needs no
+// escapes. Valid UTF-8 is kept as it is, other bytes are escaped.
+fn format_string(s: &[u8], quote: Quote) -> String {
+    let (quote, other) = match quote {
+        Quote::Double => ('"', '\''),
+        Quote::Single => ('\'', '"'),
+    };
+    let q = if s.contains(&(quote as u8)) && !s.contains(&(other as u8)) { other } else { quote };
+
+    let mut out = String::new();
+    out.push(q);
+    for chunk in s.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if c == q => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                c if c.is_ascii_control() => out.push_str(&format!("\\{:03}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            out.push_str(&format!("\\x{b:02X}"));
+        }
+    }
+    out.push(q);
+    out
+}
+// ANCHOR_END: format
@@ -0,0 +1,240 @@
+// "json" library: json.encode(v [, opts]), json.decode(s) and json.null.
+use std::collections::HashSet;
+use std::sync::Arc;
+use serde_json::Value as Json;
+use crate::value::{Table, Value};
+use crate::vm::ExeState;
+
+pub fn lib() -> Table {
+    let mut t = Table::new();
+    t.set("decode".into(), Value::Function(json_decode));
+    t.set("encode".into(), Value::Function(json_encode));
+    t.set("null".into(), null());
+    t
+}
+
+// ANCHOR: null
+// JSON null, which can not be nil since tables can not hold nil. As in
+// lua-cjson, it is the light userdata of the NULL pointer.
+pub fn null() -> Value {
+    Value::LightUserData(0)
+}
+// ANCHOR_END: null
+
+// ANCHOR: options
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub pretty: bool,
+    pub indent: usize, // spaces per level when pretty, 2 if 0
+    pub sort_keys: bool,
+}
+
+impl Options {
+    // from the table given to json.encode: {pretty=, indent=, sort_keys=}
+    fn from_table(t: &Table) -> Self {
+        let indent = match t.get(&"indent".into()) {
+            Value::Integer(i) if i > 0 => i as usize,
+            _ => 0,
+        };
+        Options {
+            pretty: t.get(&"pretty".into()).is_truthy() || indent > 0,
+            indent,
+            sort_keys: t.get(&"sort_keys".into()).is_truthy(),
+        }
+    }
+}
+// ANCHOR_END: options
+
+// ANCHOR: encode
+// A table is a JSON array if it has only an array part, and an object
+// otherwise. The empty table is an empty object.
+pub fn encode(v: &Value, opts: &Options) -> Result<String, String> {
+    let mut enc = Encoder { opts, out: String::new(), seen: Vec::new() };
+    enc.value(v)?;
+    Ok(enc.out)
+}
+
+struct Encoder<'a> {
+    opts: &'a Options,
+    out: String,
+    seen: Vec<*const ()>, // tables being encoded, to find cycles
+}
+
+impl Encoder<'_> {
+    fn value(&mut self, v: &Value) -> Result<(), String> {
+        match v {
+            Value::Nil => self.out.push_str("null"),
+            Value::Boolean(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            Value::Integer(i) => self.out.push_str(&i.to_string()),
+            Value::Float(f) if f.is_finite() => self.out.push_str(&format!("{f:?}")),
+            Value::Float(f) => return Err(format!("cannot encode number {f}")),
+            Value::String(s) => self.string(s)?,
+            Value::LightUserData(0) => self.out.push_str("null"),
+            Value::Function(_) => return Err(String::from("cannot encode function")),
+            Value::LightUserData(_) => return Err(String::from("cannot encode userdata")),
+            Value::Table(t) => {
+                let ptr = Arc::as_ptr(t) as *const ();
+                if self.seen.contains(&ptr) {
+                    return Err(String::from("cannot encode cyclic table"));
+                }
+                self.seen.push(ptr);
+                let t = t.borrow();
+                if t.map.is_empty() && !t.array.is_empty() {
+                    self.array(&t.array)?;
+                } else {
+                    self.object(&t)?;
+                }
+                self.seen.pop();
+            }
+        }
+        Ok(())
+    }
+
+    fn array(&mut self, items: &[Value]) -> Result<(), String> {
+        self.out.push('[');
+        for (i, v) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.newline(self.seen.len());
+            self.value(v)?;
+        }
+        self.close(']');
+        Ok(())
+    }
+
+    fn object(&mut self, t: &Table) -> Result<(), String> {
+        let mut entries: Vec<(Vec<u8>, &Value)> = t.array.iter().enumerate()
+            .map(|(i, v)| ((i + 1).to_string().into_bytes(), v))
+            .collect();
+        for (k, v) in &t.map {
+            let key = match k {
+                Value::String(s) => s.to_vec(),
+                Value::Integer(i) => i.to_string().into_bytes(),
+                Value::Float(f) => format!("{f:?}").into_bytes(),
+                k => return Err(format!("cannot encode {} key", k.type_name())),
+            };
+            entries.push((key, v));
+        }
+        // `1` and `"1"` are different keys in Lua, but not in JSON
+        let mut keys = HashSet::new();
+        if let Some((k, _)) = entries.iter().find(|(k, _)| !keys.insert(k)) {
+            return Err(format!("duplicate key \"{}\"", String::from_utf8_lossy(k)));
+        }
+        if self.opts.sort_keys {
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+
+        self.out.push('{');
+        for (i, (k, v)) in entries.into_iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.newline(self.seen.len());
+            self.string(&k)?;
+            self.out.push_str(if self.opts.pretty { ": " } else { ":" });
+            self.value(v)?;
+        }
+        self.close('}');
+        Ok(())
+    }
+
+    fn string(&mut self, s: &[u8]) -> Result<(), String> {
+        let Ok(s) = std::str::from_utf8(s) else {
+            return Err(String::from("cannot encode string that is not UTF-8"));
+        };
+        self.out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                '\x08' => self.out.push_str("\\b"),
+                '\x0c' => self.out.push_str("\\f"),
+                c if c.is_control() => self.out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+        Ok(())
+    }
+
+    // Pretty printing: each element on a line, indented by the nesting.
+    fn newline(&mut self, depth: usize) {
+        if self.opts.pretty {
+            let indent = if self.opts.indent == 0 { 2 } else { self.opts.indent };
+            self.out.push('\n');
+            self.out.push_str(&" ".repeat(indent * depth));
+        }
+    }
+
+    // the closing bracket goes on a line of its own, unless empty
+    fn close(&mut self, c: char) {
+        if !self.out.ends_with(['[', '{']) {
+            self.newline(self.seen.len() - 1);
+        }
+        self.out.push(c);
+    }
+}
+// ANCHOR_END: encode
+
+// ANCHOR: decode
+// Arrays and objects become tables, and null becomes json.null.
+// Numbers are integers if they are written without fraction or exponent
+// and fit in one.
+pub fn decode(s: &[u8]) -> Result<Value, String> {
+    let json: Json = serde_json::from_slice(s).map_err(|e| e.to_string())?;
+    Ok(from_json(json))
+}
+
+fn from_json(json: Json) -> Value {
+    match json {
+        Json::Null => null(),
+        Json::Bool(b) => Value::Boolean(b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Float(n.as_f64().unwrap()),
+        },
+        Json::String(s) => s.as_str().into(),
+        Json::Array(items) => {
+            let mut t = Table::new();
+            for (i, v) in items.into_iter().enumerate() {
+                t.set(Value::Integer(i as i64 + 1), from_json(v));
+            }
+            t.into()
+        }
+        Json::Object(entries) => {
+            let mut t = Table::new();
+            for (k, v) in entries {
+                t.set(k.as_str().into(), from_json(v));
+            }
+            t.into()
+        }
+    }
+}
+// ANCHOR_END: decode
+
+// json.encode(v [, opts])
+fn json_encode(state: &mut ExeState) -> i32 {
+    let opts = match state.arg(2) {
+        Value::Nil => Options::default(),
+        Value::Table(t) => Options::from_table(&t.borrow()),
+        _ => panic!("bad argument #2 to 'encode' (table expected, got {})", state.arg(2).type_name()),
+    };
+    match encode(state.arg(1), &opts) {
+        Ok(s) => state.push(s.as_str().into()),
+        Err(msg) => panic!("bad argument #1 to 'encode' ({msg})"),
+    }
+    1
+}
+
+// json.decode(s)
+fn json_decode(state: &mut ExeState) -> i32 {
+    match decode(state.check_string(1, "decode")) {
+        Ok(v) => state.push(v),
+        Err(msg) => panic!("bad argument #1 to 'decode' ({msg})"),
+    }
+    1
+}
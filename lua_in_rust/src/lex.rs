@@ -1,8 +1,9 @@
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
+use crate::utf8;
 
 // ANCHOR: token
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     // keywords
     And,    Break,  Do,     Else,   Elseif, End,
@@ -23,7 +24,7 @@ pub enum Token {
     // constant values
     Integer(i64),
     Float(f64),
-    String(String),
+    String(Vec<u8>), // Lua strings are bytes, not always UTF-8
 
     // Name of variables, functions, etc.
     Name(String),
@@ -33,35 +34,162 @@ pub enum Token {
 }
 // ANCHOR_END: token
 
+fn keyword(name: &str) -> Option<Token> {
+    let t = match name {
+        "and" => Token::And,
+        "break" => Token::Break,
+        "do" => Token::Do,
+        "else" => Token::Else,
+        "elseif" => Token::Elseif,
+        "end" => Token::End,
+        "false" => Token::False,
+        "for" => Token::For,
+        "function" => Token::Function,
+        "goto" => Token::Goto,
+        "if" => Token::If,
+        "in" => Token::In,
+        "local" => Token::Local,
+        "nil" => Token::Nil,
+        "not" => Token::Not,
+        "or" => Token::Or,
+        "repeat" => Token::Repeat,
+        "return" => Token::Return,
+        "then" => Token::Then,
+        "true" => Token::True,
+        "until" => Token::Until,
+        "while" => Token::While,
+        _ => return None,
+    };
+    Some(t)
+}
+
+// ANCHOR: span
+// Position in the source. Both are 1-based and `column` counts bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pos {
+    pub line: u32,
+    pub column: u32,
+}
+
+// Range of a token or syntax node, `end` is exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: Pos,
+    pub end: Pos,
+}
+
+impl Span {
+    pub fn to(self, other: Span) -> Span {
+        Span { start: self.start, end: other.end }
+    }
+
+    // whether the cursor at `pos` touches this span
+    pub fn contains(&self, pos: Pos) -> bool {
+        self.start <= pos && pos <= self.end
+    }
+}
+// ANCHOR_END: span
+
+// ANCHOR: comment
+// A `--` comment, kept for tools like the formatter.
+// `text` includes the leading `--`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+// ANCHOR_END: comment
+
 // ANCHOR: lex
 #[derive(Debug)]
-pub struct Lex {
-    input: File,
+pub struct Lex<R = File> {
+    input: R,
+    offset: u64,
+    line: u32,
+    line_start: u64, // offset of the current line
+    span: Span,      // of the last read token
+    comments: Vec<Comment>,
 }
 // ANCHOR_END: lex
 
-impl Lex {
-    pub fn new(input: File) -> Self {
-        Lex { input }
+impl<R: Read + Seek> Lex<R> {
+    pub fn new(input: R) -> Self {
+        Lex { input, offset: 0, line: 1, line_start: 0, span: Span::default(), comments: Vec::new() }
+    }
+
+    // comments skipped so far
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
     }
 
+    // line number of the last read token
+    pub fn line(&self) -> u32 {
+        self.span.start.line
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Token {
-        let ch = self.read_char();
-        match ch {
-            ' ' | '\r' | '\n' | '\t' => self.next(),
-            '\0' => Token::Eos,
+        self.try_next().unwrap_or_else(|e| panic!("{e}"))
+    }
 
-            '"' => { // literal String
-                let mut s = String::new();
-                loop {
-                    match self.read_char() {
-                        '\0' => panic!("unfinished literal string"),
-                        '"' => break,
-                        ch => s.push(ch),
+    // Like `next`, but returns lexical errors instead of panic,
+    // with `span` covering the bad input.
+    pub fn try_next(&mut self) -> Result<Token, String> {
+        let mut start = self.pos();
+        let mut ch = self.read_char();
+        loop {
+            match ch {
+                ' ' | '\r' | '\t' => (),
+                '\n' => {
+                    self.line += 1;
+                    self.line_start = self.offset;
+                }
+                '-' => {
+                    if self.read_char() != '-' {
+                        self.span = Span { start, end: self.pos() };
+                        return Err(String::from("unexpected char: -"));
                     }
+                    let comment = self.read_comment();
+                    self.span = Span { start, end: self.pos() };
+                    self.comments.push(Comment { text: comment?, span: self.span });
                 }
-                Token::String(s)
+                _ => break,
             }
+            start = self.pos();
+            ch = self.read_char();
+        }
+
+        let token = self.read_token(ch);
+        self.span = Span { start, end: self.pos() };
+        token
+    }
+
+    fn read_token(&mut self, ch: char) -> Result<Token, String> {
+        match ch {
+            '\0' => Ok(Token::Eos),
+            '(' => Ok(Token::ParL),
+            ')' => Ok(Token::ParR),
+            '{' => Ok(Token::CurlyL),
+            '}' => Ok(Token::CurlyR),
+            '[' => Ok(Token::SqurL),
+            ']' => Ok(Token::SqurR),
+            ',' => Ok(Token::Comma),
+            ';' => Ok(Token::SemiColon),
+            '.' => match self.check_ahead('.', Token::Concat, Token::Dot) {
+                Token::Concat => Ok(self.check_ahead('.', Token::Dots, Token::Concat)),
+                t => Ok(t),
+            },
+            '=' => Ok(self.check_ahead('=', Token::Equal, Token::Assign)),
+            '<' => Ok(self.check_ahead('=', Token::LesEq, Token::Less)),
+            '>' => Ok(self.check_ahead('=', Token::GreEq, Token::Greater)),
+            '~' => Ok(self.check_ahead('=', Token::NotEq, Token::BitXor)),
+            '0'..='9' => self.read_number(ch),
+
+            '"' | '\'' => Ok(Token::String(self.read_string(ch as u8)?)), // literal String
 
             'A'..='Z' | 'a'..='z' | '_' => { // Name
                 let mut name = String::new();
@@ -70,26 +198,238 @@ impl Lex {
                     match self.read_char() {
                         '\0' => break,
                         '_' => name.push('_'),
-                        ch if ch.is_alphanumeric() => name.push(ch),
+                        ch if ch.is_ascii_alphanumeric() => name.push(ch),
                         _ => {
-                            self.input.seek(SeekFrom::Current(-1)).unwrap();
+                            self.unread_char();
+                            break;
+                        }
+                    }
+                }
+                Ok(keyword(&name).unwrap_or(Token::Name(name)))
+            }
+
+            _ if ch.is_ascii() => Err(format!("unexpected char: {ch}")),
+            _ => Err(format!("unexpected byte: 0x{:02X}", ch as u32)),
+        }
+    }
+
+    fn check_ahead(&mut self, ahead: char, long: Token, short: Token) -> Token {
+        if self.read_char() == ahead {
+            long
+        } else {
+            self.unread_char();
+            short
+        }
+    }
+
+    // decimal integer or float
+    fn read_number(&mut self, first: char) -> Result<Token, String> {
+        let mut s = String::from(first);
+        let mut is_float = false;
+        loop {
+            let ch = self.read_char();
+            match ch {
+                '0'..='9' => s.push(ch),
+                '.' if !is_float => {
+                    is_float = true;
+                    s.push(ch);
+                }
+                'e' | 'E' => {
+                    is_float = true;
+                    s.push(ch);
+                    let sign = self.read_char();
+                    if sign == '+' || sign == '-' {
+                        s.push(sign);
+                    } else {
+                        self.unread_char();
+                    }
+                }
+                '\0' => break,
+                _ if ch.is_ascii_alphanumeric() || ch == '_' || ch == '.' => {
+                    return Err(format!("malformed number near '{s}{ch}'"));
+                }
+                _ => {
+                    self.unread_char();
+                    break;
+                }
+            }
+        }
+        // integers that do not fit are floats, as in Lua
+        match s.parse::<i64>() {
+            Ok(i) if !is_float => Ok(Token::Integer(i)),
+            _ => s.parse::<f64>()
+                .map(Token::Float)
+                .map_err(|_| format!("malformed number near '{s}'")),
+        }
+    }
+
+    // ANCHOR: string
+    // literal string after the opening quote `q`
+    fn read_string(&mut self, q: u8) -> Result<Vec<u8>, String> {
+        let mut s = Vec::new();
+        loop {
+            match self.read_byte() {
+                None | Some(b'\n') => return Err(String::from("unfinished literal string")),
+                Some(b'\\') => self.read_escape(&mut s)?,
+                Some(b) if b == q => return Ok(s),
+                Some(b) => s.push(b),
+            }
+        }
+    }
+
+    // after `\` in a literal string
+    fn read_escape(&mut self, s: &mut Vec<u8>) -> Result<(), String> {
+        let Some(b) = self.read_byte() else {
+            return Err(String::from("unfinished literal string"));
+        };
+        match b {
+            b'a' => s.push(0x07),
+            b'b' => s.push(0x08),
+            b'f' => s.push(0x0c),
+            b'n' => s.push(b'\n'),
+            b'r' => s.push(b'\r'),
+            b't' => s.push(b'\t'),
+            b'v' => s.push(0x0b),
+            b'\\' | b'"' | b'\'' => s.push(b),
+            b'\n' => {
+                s.push(b'\n');
+                self.line += 1;
+                self.line_start = self.offset;
+            }
+            b'x' => { // \xXX
+                let mut n = 0;
+                for _ in 0..2 {
+                    match self.read_byte().and_then(|b| (b as char).to_digit(16)) {
+                        Some(d) => n = n * 16 + d,
+                        None => return Err(String::from("hexadecimal digit expected")),
+                    }
+                }
+                s.push(n as u8);
+            }
+            b'z' => { // skip the following white spaces
+                loop {
+                    match self.read_byte() {
+                        Some(b'\n') => {
+                            self.line += 1;
+                            self.line_start = self.offset;
+                        }
+                        Some(b' ' | b'\r' | b'\t' | 0x0b | 0x0c) => (),
+                        Some(_) => {
+                            self.unread_char();
                             break;
                         }
+                        None => break,
                     }
                 }
-                Token::Name(name)
             }
+            b'u' => { // \u{XXX}, may be beyond Unicode, up to 2^31
+                if self.read_byte() != Some(b'{') {
+                    return Err(String::from("missing '{' in \\u{xxxx}"));
+                }
+                let mut n: u32 = 0;
+                let mut digits = 0;
+                loop {
+                    match self.read_byte() {
+                        Some(b'}') if digits > 0 => break,
+                        Some(b) if (b as char).is_ascii_hexdigit() => {
+                            n = n.checked_mul(16)
+                                .map(|n| n + (b as char).to_digit(16).unwrap())
+                                .filter(|&n| n <= 0x7FFFFFFF)
+                                .ok_or_else(|| String::from("UTF-8 value too large"))?;
+                            digits += 1;
+                        }
+                        _ => return Err(String::from("hexadecimal digit expected")),
+                    }
+                }
+                utf8::encode(n, s);
+            }
+            b'0'..=b'9' => { // \ddd, up to 3 decimal digits
+                let mut n = (b - b'0') as u32;
+                for _ in 0..2 {
+                    match self.read_byte() {
+                        Some(b) if b.is_ascii_digit() => n = n * 10 + (b - b'0') as u32,
+                        Some(_) => {
+                            self.unread_char();
+                            break;
+                        }
+                        None => break,
+                    }
+                }
+                if n > 255 {
+                    return Err(String::from("decimal escape too large"));
+                }
+                s.push(n as u8);
+            }
+            _ => return Err(String::from("invalid escape sequence")),
+        }
+        Ok(())
+    }
+    // ANCHOR_END: string
 
-            _ => panic!("unexpected char: {ch}"),
+    // after `--`: a long comment `[[...]]`, `[==[...]==]`, or to the end of line
+    fn read_comment(&mut self) -> Result<String, String> {
+        let mut text = b"--".to_vec();
+        let mut b = self.read_byte();
+        if b == Some(b'[') {
+            text.push(b'[');
+            let mut level = 0;
+            b = self.read_byte();
+            while b == Some(b'=') {
+                text.push(b'=');
+                level += 1;
+                b = self.read_byte();
+            }
+            if b == Some(b'[') {
+                text.push(b'[');
+                let close = format!("]{}]", "=".repeat(level)).into_bytes();
+                while !text.ends_with(&close) {
+                    match self.read_byte() {
+                        None => return Err(String::from("unfinished long comment")),
+                        Some(b'\n') => {
+                            text.push(b'\n');
+                            self.line += 1;
+                            self.line_start = self.offset;
+                        }
+                        Some(b) => text.push(b),
+                    }
+                }
+                return Ok(String::from_utf8_lossy(&text).into_owned());
+            }
         }
+        // short comment
+        while let Some(c) = b {
+            if c == b'\n' {
+                self.unread_char();
+                break;
+            }
+            text.push(c);
+            b = self.read_byte();
+        }
+        Ok(String::from_utf8_lossy(&text).into_owned())
     }
 
+    fn pos(&self) -> Pos {
+        Pos { line: self.line, column: (self.offset - self.line_start + 1) as u32 }
+    }
+
+    // Only for ASCII tokens, non-ASCII bytes become unexpected chars.
+    // Literal strings and comments are read by `read_byte`.
     fn read_char(&mut self) -> char {
+        self.read_byte().map_or('\0', |b| b as char)
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
         let mut buf: [u8; 1] = [0];
         if self.input.read(&mut buf).unwrap() == 1 {
-            buf[0] as char
+            self.offset += 1;
+            Some(buf[0])
         } else {
-            '\0'
+            None
         }
     }
+
+    fn unread_char(&mut self) {
+        self.input.seek(SeekFrom::Current(-1)).unwrap();
+        self.offset -= 1;
+    }
 }
@@ -0,0 +1,18 @@
+pub mod value;
+pub mod string;
+pub mod json;
+pub mod serde;
+pub mod utf8;
+pub mod bytecode;
+pub mod lex;
+pub mod parse;
+pub mod vm;
+pub mod channel;
+pub mod ast;
+pub mod scope;
+pub mod debug;
+pub mod dap;
+pub mod profile;
+pub mod lsp;
+pub mod format;
+pub mod lint;
@@ -0,0 +1,255 @@
+// Static checks on the syntax tree.
+use std::collections::HashSet;
+use std::io::Cursor;
+use serde_json::{json, Value as Json};
+use crate::ast::{Attrib, Block, Expr, Stat};
+use crate::lex::Span;
+use crate::parse;
+use crate::scope::{self, Scopes};
+use crate::value::Value;
+use crate::vm::ExeState;
+
+// ANCHOR: diagnostic
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    // "file:line:column: code: message"
+    pub fn to_human(&self, file: &str) -> String {
+        format!("{file}:{}:{}: {}: {}", self.span.start.line, self.span.start.column, self.code, self.message)
+    }
+
+    pub fn to_json(&self, file: &str) -> Json {
+        json!({
+            "file": file,
+            "line": self.span.start.line,
+            "column": self.span.start.column,
+            "end_line": self.span.end.line,
+            "end_column": self.span.end.column,
+            "code": self.code,
+            "message": self.message,
+        })
+    }
+}
+// ANCHOR_END: diagnostic
+
+// ANCHOR: lint
+// Check a script. Globals of the standard library, `allowed` ones and
+// ones assigned in the script are known, any other is reported.
+pub fn lint(src: &str, allowed: &[String]) -> Vec<Diagnostic> {
+    let block = match parse::parse_ast(Cursor::new(src)) {
+        Ok(block) => block,
+        Err(err) => return vec![Diagnostic {
+            code: "syntax-error",
+            message: err.message,
+            span: err.span,
+        }],
+    };
+
+    let state = ExeState::new();
+    let scopes = scope::resolve(&block);
+    let mut diags = Vec::new();
+    undefined_globals(&scopes, &state, allowed, &mut diags);
+    unused_locals(&scopes, &mut diags);
+    shadowed_locals(&scopes, &mut diags);
+    readonly_assigns(&block, &scopes, &state, &mut diags);
+    unreachable_code(&block, &mut diags);
+    arg_counts(&block, &scopes, &mut diags);
+    diags.sort_by_key(|d| d.span.start);
+    diags
+}
+
+fn undefined_globals(scopes: &Scopes, state: &ExeState, allowed: &[String], diags: &mut Vec<Diagnostic>) {
+    let assigned: HashSet<&str> = scopes.names.iter()
+        .filter(|n| n.local.is_none() && n.write)
+        .map(|n| n.name.as_str())
+        .collect();
+    for n in scopes.names.iter().filter(|n| n.local.is_none() && !n.write) {
+        if state.get_global(&n.name).is_none() && !allowed.contains(&n.name) && !assigned.contains(n.name.as_str()) {
+            diags.push(Diagnostic {
+                code: "undefined-global",
+                message: format!("undefined global `{}`", n.name),
+                span: n.span,
+            });
+        }
+    }
+}
+
+// Locals never read. Names starting with `_` are meant to be unused, and
+// `<close>` ones are used by being closed.
+fn unused_locals(scopes: &Scopes, diags: &mut Vec<Diagnostic>) {
+    for (i, local) in scopes.locals.iter().enumerate() {
+        if local.name.starts_with('_') || local.attrib == Attrib::Close {
+            continue;
+        }
+        if !scopes.uses(&local.name, Some(i)).any(|n| !n.write) {
+            diags.push(Diagnostic {
+                code: "unused-local",
+                message: format!("unused local `{}`", local.name),
+                span: local.span,
+            });
+        }
+    }
+}
+
+fn shadowed_locals(scopes: &Scopes, diags: &mut Vec<Diagnostic>) {
+    for local in &scopes.locals {
+        if let Some(outer) = local.shadows {
+            diags.push(Diagnostic {
+                code: "shadowed-local",
+                message: format!("local `{}` shadows the one on line {}", local.name, scopes.locals[outer].span.start.line),
+                span: local.span,
+            });
+        }
+    }
+}
+
+// Assignments to `<const>` and `<close>` locals, which `load` rejects,
+// and to the globals of the standard library or their fields.
+fn readonly_assigns(block: &Block, scopes: &Scopes, state: &ExeState, diags: &mut Vec<Diagnostic>) {
+    for n in scopes.names.iter().filter(|n| n.write) {
+        let message = match n.local {
+            Some(i) if scopes.locals[i].attrib != Attrib::None => {
+                format!("attempt to assign to const variable '{}'", n.name)
+            }
+            None if state.get_global(&n.name).is_some() => format!("assignment to builtin `{}`", n.name),
+            _ => continue,
+        };
+        diags.push(Diagnostic { code: "readonly-assign", message, span: n.span });
+    }
+
+    let mut fields = Vec::new();
+    assigned_fields(block, &mut fields);
+    for (table, span) in fields {
+        let Expr::Name(name, name_span) = table else {
+            continue;
+        };
+        let global = scopes.names.iter().any(|n| n.span == *name_span && n.local.is_none());
+        if global && matches!(state.get_global(name), Some(Value::Table(_))) {
+            diags.push(Diagnostic {
+                code: "readonly-assign",
+                message: format!("assignment to a field of builtin `{name}`"),
+                span,
+            });
+        }
+    }
+}
+
+// the tables of `t.k = v` and `t[k] = v` assignments, with the targets
+fn assigned_fields<'a>(block: &'a Block, fields: &mut Vec<(&'a Expr, Span)>) {
+    for stat in &block.stats {
+        match stat {
+            Stat::Assign { targets, .. } => {
+                for target in targets {
+                    if let Expr::Field(table, _, span) | Expr::Index(table, _, span) = target {
+                        fields.push((table, *span));
+                    }
+                }
+            }
+            Stat::Do(body, _) | Stat::While { body, .. } => assigned_fields(body, fields),
+            _ => (),
+        }
+    }
+}
+
+// Statements after `break`, `return`, or a block or loop that does not
+// end normally, once for each block.
+fn unreachable_code(block: &Block, diags: &mut Vec<Diagnostic>) {
+    for stat in &block.stats {
+        if let Stat::Do(body, _) | Stat::While { body, .. } = stat {
+            unreachable_code(body, diags);
+        }
+    }
+    if let Some(i) = block.stats.iter().position(|s| !falls_through(s)) {
+        if let (Some(first), Some(last)) = (block.stats.get(i + 1), block.stats.last()) {
+            diags.push(Diagnostic {
+                code: "unreachable-code",
+                message: String::from("unreachable code"),
+                span: first.span().to(last.span()),
+            });
+        }
+    }
+}
+
+fn falls_through(stat: &Stat) -> bool {
+    match stat {
+        Stat::Break(_) | Stat::Return(..) => false,
+        Stat::Do(body, _) => body.stats.iter().all(falls_through),
+        // a loop on a true constant ends only by `break`
+        Stat::While { cond, body, .. } => !is_true(cond) || breaks(body),
+        _ => true,
+    }
+}
+
+// whether `block` has a `break` of its enclosing loop
+fn breaks(block: &Block) -> bool {
+    block.stats.iter().any(|stat| match stat {
+        Stat::Break(_) => true,
+        Stat::Do(body, _) => breaks(body),
+        _ => false,
+    })
+}
+
+fn is_true(e: &Expr) -> bool {
+    matches!(e, Expr::Boolean(true, _) | Expr::Integer(..) | Expr::Float(..) | Expr::String(..) | Expr::Table(..))
+}
+
+// Arguments the functions of the standard library take, at least and at
+// most. As an argument, a call gives one value, so the count is exact.
+const ARG_COUNTS: &[(&str, usize, Option<usize>)] = &[
+    ("assert", 1, None),
+    ("debug.gethook", 0, Some(0)),
+    ("debug.getinfo", 1, Some(1)),
+    ("debug.getlocal", 2, Some(2)),
+    ("debug.getupvalue", 2, Some(2)),
+    ("debug.sethook", 0, Some(3)),
+    ("debug.setlocal", 3, Some(3)),
+    ("debug.setupvalue", 3, Some(3)),
+    ("debug.traceback", 0, Some(1)),
+    ("json.decode", 1, Some(1)),
+    ("json.encode", 1, Some(2)),
+    ("string.pack", 1, None),
+    ("string.packsize", 1, Some(1)),
+    ("string.unpack", 2, Some(3)),
+    ("utf8.codepoint", 1, Some(4)),
+    ("utf8.codes", 1, Some(2)),
+    ("utf8.len", 1, Some(4)),
+    ("utf8.offset", 2, Some(3)),
+];
+
+fn arg_counts(block: &Block, scopes: &Scopes, diags: &mut Vec<Diagnostic>) {
+    let global = |span: &Span| scopes.names.iter().any(|n| n.span == *span && n.local.is_none());
+    for call in block.calls() {
+        let name = match &call.func {
+            Expr::Name(name, span) if global(span) => name.clone(),
+            Expr::Field(table, field, _) => match &**table {
+                Expr::Name(name, span) if global(span) => format!("{name}.{field}"),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let Some(&(_, min, max)) = ARG_COUNTS.iter().find(|(n, _, _)| *n == name) else {
+            continue;
+        };
+        let n = call.args.len();
+        if n >= min && max.is_none_or(|max| n <= max) {
+            continue;
+        }
+        let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+        let takes = match max {
+            None => format!("at least {min} {}", plural(min)),
+            Some(max) if max == min => format!("{min} {}", plural(min)),
+            Some(max) => format!("{min} to {max} arguments"),
+        };
+        diags.push(Diagnostic {
+            code: "wrong-arg-count",
+            message: format!("`{name}` takes {takes}, got {n}"),
+            span: call.span,
+        });
+    }
+}
+// ANCHOR_END: lint
@@ -0,0 +1,337 @@
+// Language server over stdio, built on the syntax tree.
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufReader, Cursor, Read, Write};
+use serde_json::{json, Value as Json};
+use crate::ast::{Block, Expr};
+use crate::dap::{read_message, write_message};
+use crate::lex::{Pos, Span};
+use crate::parse;
+use crate::scope;
+use crate::value::Value;
+use crate::vm::ExeState;
+
+// hover text of the standard library, and of the fields of its tables
+const BUILTINS: &[(&str, &str)] = &[
+    ("assert", "assert(v [, message])\n\nRaises an error with `message`, or \"assertion failed!\", if `v` is false or nil; otherwise returns all its arguments."),
+    ("print", "print(...)\n\nWrites the arguments to the standard output, separated by tabs and followed by a newline."),
+    ("debug", "debug\n\nThe debug library: `getinfo`, `getlocal`, `setlocal`, `getupvalue`, `setupvalue`, `gethook`, `sethook` and `traceback`."),
+    ("json", "json\n\nThe JSON library: `encode(v [, opts])`, `decode(s)` and the `null` sentinel."),
+    ("string", "string\n\nThe string library: `pack`, `packsize` and `unpack`."),
+    ("utf8", "utf8\n\nThe UTF-8 library: `char`, `charpattern`, `codes`, `codepoint`, `len` and `offset`."),
+    ("debug.gethook", "debug.gethook()\n\nReturns the mask and count of the hook, or nothing if there is none."),
+    ("debug.getinfo", "debug.getinfo(level)\n\nReturns a table with the `source`, `what`, `name`, `currentline`, `nups` and `nparams` of the function at `level`, or nil."),
+    ("debug.getlocal", "debug.getlocal(level, n)\n\nReturns the name and value of the local `n` of the function at `level`, or nil."),
+    ("debug.getupvalue", "debug.getupvalue(f, n)\n\nReturns the name and value of the upvalue `n` of `f`. Library functions have none."),
+    ("debug.sethook", "debug.sethook([f, mask [, count]])\n\nCalls `f` with the name of the event, and the line for \"line\" events, on calls (`c`), returns (`r`), new lines (`l`) and every `count` instructions. Without arguments, turns off the hook."),
+    ("debug.setlocal", "debug.setlocal(level, n, v)\n\nAssigns `v` to the local `n` of the function at `level` and returns its name, or nil."),
+    ("debug.setupvalue", "debug.setupvalue(f, n, v)\n\nAssigns `v` to the upvalue `n` of `f`. Library functions have none."),
+    ("debug.traceback", "debug.traceback([message])\n\nReturns `message` followed by a traceback of the calls."),
+    ("json.decode", "json.decode(s)\n\nParses the JSON text `s`. Arrays and objects become tables, and null becomes `json.null`."),
+    ("json.encode", "json.encode(v [, opts])\n\nReturns `v` as JSON text. `opts` can set `pretty`, `indent` and `sort_keys`."),
+    ("json.null", "json.null\n\nJSON null, which, unlike nil, can be kept in tables."),
+    ("string.pack", "string.pack(fmt, v1, v2, ...)\n\nReturns a binary string of the values, packed as described by `fmt`."),
+    ("string.packsize", "string.packsize(fmt)\n\nReturns the size of the strings packed with `fmt`."),
+    ("string.unpack", "string.unpack(fmt, s [, pos])\n\nReturns the values packed in `s` as described by `fmt`, and the position after them."),
+    ("utf8.char", "utf8.char(...)\n\nReturns the UTF-8 encoding of the code points."),
+    ("utf8.charpattern", "utf8.charpattern\n\nThe pattern which matches exactly one UTF-8 byte sequence."),
+    ("utf8.codes", "utf8.codes(s [, lax])\n\nReturns an iterator over the positions and code points of the characters of `s`."),
+    ("utf8.codepoint", "utf8.codepoint(s [, i [, j [, lax]]])\n\nReturns the code points of the characters of `s` which start between the byte positions `i` and `j`."),
+    ("utf8.len", "utf8.len(s [, i [, j [, lax]]])\n\nReturns the number of characters of `s` between `i` and `j`, or nil and the position of the first invalid byte."),
+    ("utf8.offset", "utf8.offset(s, n [, i])\n\nReturns the byte position where the `n`-th character, counting from position `i`, starts."),
+];
+
+// ANCHOR: features
+// LSP positions are 0-based and count UTF-16 code units in the line,
+// ours are 1-based and count bytes.
+pub fn utf16_column(line: &str, column: u32) -> u32 {
+    let mut end = (column as usize - 1).min(line.len());
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    line[..end].encode_utf16().count() as u32
+}
+
+pub fn byte_column(line: &str, character: u32) -> u32 {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= character {
+            return i as u32 + 1;
+        }
+        units += c.len_utf16() as u32;
+    }
+    line.len() as u32 + 1
+}
+
+fn line(text: &str, line: u32) -> &str {
+    text.split('\n').nth(line as usize - 1).unwrap_or_default()
+}
+
+fn range(text: &str, span: Span) -> Json {
+    let start = utf16_column(line(text, span.start.line), span.start.column);
+    let end = utf16_column(line(text, span.end.line), span.end.column);
+    json!({
+        "start": {"line": span.start.line - 1, "character": start},
+        "end": {"line": span.end.line - 1, "character": end},
+    })
+}
+
+fn position(text: &str, params: &Json) -> Pos {
+    let pos = &params["position"];
+    let line_no = pos["line"].as_u64().unwrap_or(0) as u32 + 1;
+    let character = pos["character"].as_u64().unwrap_or(0) as u32;
+    Pos { line: line_no, column: byte_column(line(text, line_no), character) }
+}
+
+pub fn diagnostics(text: &str) -> Vec<Json> {
+    match parse::parse_ast(Cursor::new(text)) {
+        Ok(_) => Vec::new(),
+        Err(err) => vec![json!({
+            "range": range(text, err.span),
+            "severity": 1,
+            "source": "lua-rs",
+            "message": err.message,
+        })],
+    }
+}
+
+// The declaration of the local at `pos`, or the first assignment of
+// the global.
+pub fn definition(block: &Block, pos: Pos) -> Option<Span> {
+    let scopes = scope::resolve(block);
+    match scopes.name_at(pos)? {
+        (_, Some(i)) => Some(scopes.locals[i].span),
+        (name, None) => scopes.uses(name, None).find(|n| n.write).map(|n| n.span),
+    }
+}
+
+// spans of all uses of the variable at `pos`, and its declaration if
+// it is a local
+pub fn references(block: &Block, pos: Pos) -> Vec<Span> {
+    let scopes = scope::resolve(block);
+    let Some((name, local)) = scopes.name_at(pos) else {
+        return Vec::new();
+    };
+    let mut spans: Vec<Span> = scopes.uses(name, local).map(|n| n.span).collect();
+    if let Some(i) = local {
+        spans.insert(0, scopes.locals[i].span);
+    }
+    spans
+}
+
+pub fn hover(block: &Block, pos: Pos) -> Option<(String, Span)> {
+    let scopes = scope::resolve(block);
+    if let Some((name, local)) = scopes.name_at(pos) {
+        let span = match block.expr_at(pos) {
+            Some(e @ Expr::Name(..)) => e.span(),
+            _ => local.map(|i| scopes.locals[i].span)?,
+        };
+        let text = match (local, BUILTINS.iter().find(|(n, _)| *n == name)) {
+            (Some(i), _) => format!("local `{name}`, declared on line {}", scopes.locals[i].span.start.line),
+            (None, Some((_, doc))) => doc.to_string(),
+            (None, None) => format!("global `{name}`"),
+        };
+        return Some((text, span));
+    }
+    let expr = block.expr_at(pos)?;
+    let text = match expr {
+        Expr::String(s, _) => format!("string, {} bytes", s.len()),
+        // a field of a library, like `string.pack`
+        Expr::Field(table, field, _) => match &**table {
+            Expr::Name(name, span) if scopes.name_at(span.start).is_some_and(|(_, local)| local.is_none()) => {
+                let name = format!("{name}.{field}");
+                BUILTINS.iter().find(|(n, _)| *n == name)?.1.to_string()
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((text, expr.span()))
+}
+
+// Globals of a fresh state, globals used in the document, and the locals
+// in scope at `pos`, with their CompletionItemKind: Function, Module for
+// libraries, or Variable. After `Name.`, the fields of the library
+// `Name` instead, Functions or Fields. This works on the text, as the
+// document does not parse while a name is typed.
+pub fn completion(text: &str, block: Option<&Block>, pos: Pos) -> Vec<Json> {
+    let state = ExeState::new();
+    let mut items = BTreeSet::new();
+    if let Some(name) = table_before(text, pos) {
+        let local = block.is_some_and(|b| scope::resolve(b).visible_at(pos).iter().any(|l| l.name == name));
+        if let (false, Some(Value::Table(t))) = (local, state.get_global(name)) {
+            for (k, v) in &t.borrow().map {
+                if let Value::String(k) = k {
+                    let kind = if let Value::Function(_) = v { 3 } else { 5 };
+                    items.insert((String::from_utf8_lossy(k).into_owned(), kind));
+                }
+            }
+        }
+    } else {
+        for (name, v) in state.globals() {
+            let kind = match v {
+                Value::Function(_) => 3,
+                Value::Table(_) => 9,
+                _ => 6,
+            };
+            items.insert((name.clone(), kind));
+        }
+        if let Some(block) = block {
+            let scopes = scope::resolve(block);
+            for n in scopes.names.iter().filter(|n| n.local.is_none()) {
+                if state.get_global(&n.name).is_none() {
+                    items.insert((n.name.clone(), 6));
+                }
+            }
+            for local in scopes.visible_at(pos) {
+                items.insert((local.name.clone(), 6));
+            }
+        }
+    }
+
+    items.into_iter()
+        .map(|(label, kind)| json!({"label": label, "kind": kind}))
+        .collect()
+}
+
+// `Name` if the text before `pos` ends with `Name.`, and maybe the start
+// of a field name
+fn table_before(text: &str, pos: Pos) -> Option<&str> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let line = line(text, pos.line);
+    let before = line.get(..pos.column as usize - 1).unwrap_or(line);
+    let before = before.trim_end_matches(is_name_char).strip_suffix('.')?;
+    let name = &before[before.trim_end_matches(is_name_char).len()..];
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_').then_some(name)
+}
+// ANCHOR_END: features
+
+// ANCHOR: server
+struct Server {
+    output: Box<dyn Write>,
+    documents: HashMap<String, String>, // text by uri
+}
+
+impl Server {
+    fn send(&mut self, msg: Json) {
+        write_message(&mut self.output, &msg).unwrap();
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) {
+        let diagnostics = self.documents.get(uri).map(|t| diagnostics(t)).unwrap_or_default();
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {"uri": uri, "diagnostics": diagnostics},
+        }));
+    }
+
+    fn text(&self, params: &Json) -> &str {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        self.documents.get(uri).map(String::as_str).unwrap_or_default()
+    }
+
+    // the document of the request, if it parses
+    fn block(&self, params: &Json) -> Option<Block> {
+        parse::parse_ast(Cursor::new(self.text(params))).ok()
+    }
+
+    // Returns the result of a request, `None` for notifications.
+    fn handle(&mut self, method: &str, params: &Json) -> Option<Json> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+        match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    "textDocumentSync": 1, // full text on every change
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {"triggerCharacters": ["."]},
+                },
+                "serverInfo": {"name": "lua-lsp"},
+            })),
+            "shutdown" => Some(Json::Null),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_string());
+                self.publish_diagnostics(&uri);
+                None
+            }
+            "textDocument/didChange" => {
+                if let Some(change) = params["contentChanges"].as_array().and_then(|c| c.last()) {
+                    let text = change["text"].as_str().unwrap_or_default();
+                    self.documents.insert(uri.clone(), text.to_string());
+                }
+                self.publish_diagnostics(&uri);
+                None
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish_diagnostics(&uri);
+                None
+            }
+            "textDocument/definition" => {
+                let text = self.text(params);
+                let span = self.block(params).and_then(|b| definition(&b, position(text, params)));
+                Some(match span {
+                    Some(span) => json!({"uri": uri, "range": range(text, span)}),
+                    None => Json::Null,
+                })
+            }
+            "textDocument/references" => {
+                let text = self.text(params);
+                let spans = self.block(params)
+                    .map(|b| references(&b, position(text, params)))
+                    .unwrap_or_default();
+                let locations: Vec<_> = spans.into_iter()
+                    .map(|span| json!({"uri": uri, "range": range(text, span)}))
+                    .collect();
+                Some(json!(locations))
+            }
+            "textDocument/hover" => {
+                let text = self.text(params);
+                let hover = self.block(params).and_then(|b| hover(&b, position(text, params)));
+                Some(match hover {
+                    Some((value, span)) => json!({
+                        "contents": {"kind": "markdown", "value": value},
+                        "range": range(text, span),
+                    }),
+                    None => Json::Null,
+                })
+            }
+            "textDocument/completion" => {
+                let text = self.text(params);
+                let pos = position(text, params);
+                Some(json!(completion(text, self.block(params).as_ref(), pos)))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Serve until the "exit" notification or the end of input.
+pub fn run(input: impl Read, output: impl Write + 'static) {
+    let mut input = BufReader::new(input);
+    let mut server = Server { output: Box::new(output), documents: HashMap::new() };
+
+    while let Ok(Some(msg)) = read_message(&mut input) {
+        let method = msg["method"].as_str().unwrap_or_default();
+        if method == "exit" {
+            break;
+        }
+        let result = server.handle(method, &msg["params"]);
+
+        // requests have an id and need an answer, notifications do not
+        if msg.get("id").is_some() {
+            let response = match result {
+                Some(result) => json!({"jsonrpc": "2.0", "id": msg["id"], "result": result}),
+                None => json!({
+                    "jsonrpc": "2.0",
+                    "id": msg["id"],
+                    "error": {"code": -32601, "message": format!("unsupported method: {method}")},
+                }),
+            };
+            server.send(response);
+        }
+    }
+}
+// ANCHOR_END: server
@@ -1,20 +1,38 @@
 use std::env;
 use std::fs::File;
+use std::io;
 
-mod value;
-mod bytecode;
-mod lex;
-mod parse;
-mod vm;
+use lua_rs::{parse, vm};
+use lua_rs::profile::Profiler;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} script", args[0]);
+    // --profile writes to profile.folded, --profile=FILE to FILE
+    let (profile, script) = match &args[1..] {
+        [script] => (None, script),
+        [flag, script] if flag == "--profile" => (Some("profile.folded"), script),
+        [flag, script] if flag.starts_with("--profile=") => (Some(&flag["--profile=".len()..]), script),
+        _ => {
+            println!("Usage: {} [--profile[=FILE]] script", args[0]);
+            return;
+        }
+    };
+    let file = File::open(script).unwrap();
+
+    let proto = parse::load(file, script);
+    let mut state = vm::ExeState::new();
+    let Some(output) = profile else {
+        state.execute(&proto);
         return;
-    }
-    let file = File::open(&args[1]).unwrap();
+    };
+
+    // --profile: write folded stacks for flamegraphs and print the hot spots
+    let profiler = Profiler::attach(&mut state);
+    state.execute(&proto);
+    state.clear_hook();
 
-    let proto = parse::load(file);
-    vm::ExeState::new().execute(&proto);
+    let profiler = profiler.lock().unwrap();
+    let mut folded = File::create(output).unwrap();
+    profiler.write_folded(&mut folded).unwrap();
+    profiler.write_report(&mut io::stderr(), 10).unwrap();
 }
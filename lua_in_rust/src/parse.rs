@@ -1,43 +1,858 @@
-use std::fs::File;
-use crate::lex::{Lex, Token};
+use std::fmt;
+use std::io::{Read, Seek};
+use std::mem;
+use crate::ast::{Attrib, Block, Expr, Field, FuncCall, LocalName, Stat};
+use crate::lex::{Lex, Span, Token};
+use std::sync::Arc;
 use crate::bytecode::ByteCode;
+use crate::debug;
 use crate::value::Value;
 
 // ANCHOR: proto
 #[derive(Debug)]
 pub struct ParseProto {
+    pub source: String,
     pub constants: Vec::<Value>,
     pub byte_codes: Vec::<ByteCode>,
+    pub lines: Vec::<u32>, // source line of each byte code
+    pub max_stack: usize,  // registers used
+    pub call_names: Vec<Option<Arc<str>>>, // of the function, for each Call byte code
+    pub locvars: Arc<[LocVar]>, // for "debug.getlocal"
+}
+
+// A local variable, in scope in byte codes `start..end`. Of the locals in
+// scope at a byte code, the n-th one declared is in register n-1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocVar {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
 }
 // ANCHOR_END: proto
 
 // ANCHOR: load
-pub fn load(input: File) -> ParseProto {
-    let mut constants = Vec::new();
-    let mut byte_codes = Vec::new();
-    let mut lex = Lex::new(input);
-
-    loop {
-        match lex.next() {
-            Token::Name(name) => { // `Name LiteralString` as function call
-                constants.push(Value::String(name));
-                byte_codes.push(ByteCode::GetGlobal(0, (constants.len()-1) as u8));
-
-                if let Token::String(s) = lex.next() {
-                    constants.push(Value::String(s));
-                    byte_codes.push(ByteCode::LoadConst(1, (constants.len()-1) as u8));
-                    byte_codes.push(ByteCode::Call(0, 1));
-                } else {
-                    panic!("expected string");
-                }
-            }
-            Token::Eos => break,
+pub fn load<R: Read + Seek>(input: R, source: &str) -> ParseProto {
+    let mut p = Parser {
+        lex: Lex::new(input),
+        ahead: None,
+        line: 1,
+        proto: ParseProto {
+            source: source.to_string(),
+            constants: Vec::new(),
+            byte_codes: Vec::new(),
+            lines: Vec::new(),
+            max_stack: 0,
+            call_names: Vec::new(),
+            locvars: Arc::new([]),
+        },
+        locals: Vec::new(),
+        locvars: Vec::new(),
+        loops: Vec::new(),
+    };
+    match p.block() {
+        Token::Eos => (),
+        t => panic!("unexpected token: {t:?}"),
+    }
+    p.end_scope(0);
+
+    // the names are found once here, rather than on each call
+    let mut proto = p.proto;
+    proto.locvars = p.locvars.into();
+    proto.call_names = proto.byte_codes.iter().enumerate()
+        .map(|(pc, code)| match *code {
+            ByteCode::Call(func, _, _) => debug::funcname(&proto, pc, func).map(Arc::from),
+            _ => None,
+        })
+        .collect();
+
+    proto
+}
+
+struct Local {
+    name: String,
+    attrib: Attrib,
+    locvar: usize, // its debug information
+}
+
+// An enclosing loop, for `break`.
+struct Loop {
+    nlocals: usize,     // locals when the loop started
+    breaks: Vec<usize>, // jumps to the end of the loop
+}
+
+// What a prefix expression ends with, see `suffixes`.
+#[derive(Clone, Copy)]
+enum Last {
+    Name,
+    Call,
+    Field(u8), // key constant
+    Index(u8), // key register
+}
+
+struct Parser<R: Read + Seek> {
+    lex: Lex<R>,
+    ahead: Option<(Token, u32)>,
+    line: u32, // of the last token taken by `next`, for the byte codes
+    proto: ParseProto,
+    locals: Vec<Local>, // in scope, each in the register of its index
+    locvars: Vec<LocVar>,
+    loops: Vec<Loop>,
+}
+
+impl<R: Read + Seek> Parser<R> {
+    fn next(&mut self) -> Token {
+        let (t, line) = self.ahead.take().unwrap_or_else(|| (self.lex.next(), self.lex.line()));
+        self.line = line;
+        t
+    }
+
+    fn peek(&mut self) -> &Token {
+        if self.ahead.is_none() {
+            self.ahead = Some((self.lex.next(), self.lex.line()));
+        }
+        &self.ahead.as_ref().unwrap().0
+    }
+
+    fn expect(&mut self, t: Token) {
+        let found = self.next();
+        if found != t {
+            panic!("expected {t:?}, found {found:?}");
+        }
+    }
+
+    fn emit(&mut self, code: ByteCode) -> usize {
+        let top = match code {
+            ByteCode::GetGlobal(dst, _) | ByteCode::LoadConst(dst, _) | ByteCode::LoadNil(dst)
+                | ByteCode::LoadBool(dst, _) | ByteCode::LoadInt(dst, _) | ByteCode::Move(dst, _)
+                | ByteCode::NewTable(dst) | ByteCode::GetField(dst, _, _) | ByteCode::GetTable(dst, _, _) => dst as usize + 1,
+            ByteCode::Call(func, narg, want) => func as usize + 1 + narg.max(want) as usize,
+            _ => 0,
+        };
+        self.proto.max_stack = self.proto.max_stack.max(top);
+        self.proto.byte_codes.push(code);
+        self.proto.lines.push(self.line);
+        self.proto.byte_codes.len() - 1
+    }
+
+    // The index of constant `v`, shared with an equal one of the same type
+    // if there is, as there can be only 256 of them.
+    fn add_const(&mut self, v: Value) -> u8 {
+        let i = match self.proto.constants.iter()
+                .position(|c| mem::discriminant(c) == mem::discriminant(&v) && *c == v) {
+            Some(i) => i,
+            None => {
+                self.proto.constants.push(v);
+                self.proto.constants.len() - 1
+            }
+        };
+        u8::try_from(i).unwrap_or_else(|_| panic!("too many constants"))
+    }
+
+    // the first free register, above the locals
+    fn sp(&self) -> u8 {
+        reg(0, self.locals.len())
+    }
+
+    // Statements up to a token that ends the block, which is returned.
+    fn block(&mut self) -> Token {
+        loop {
+            match self.next() {
+                Token::SemiColon => (),
+                Token::Name(name) => self.name_stat(name),
+                Token::Local => self.local_stat(),
+                Token::Do => self.scoped_block(),
+                Token::While => self.while_stat(),
+                Token::Break => self.break_stat(),
+                Token::Return => return self.return_stat(),
+                t => return t,
+            }
+        }
+    }
+
+    // `block end` in its own scope, closing its `<close>` variables at the end
+    fn scoped_block(&mut self) {
+        let nlocals = self.locals.len();
+        let t = self.block();
+        if t != Token::End {
+            panic!("expected `end`, found {t:?}");
+        }
+        self.close_from(nlocals);
+        self.end_scope(nlocals);
+    }
+
+    // the locals from the `nlocals`-th on go out of scope
+    fn end_scope(&mut self, nlocals: usize) {
+        let pc = self.proto.byte_codes.len();
+        for local in self.locals.drain(nlocals..) {
+            self.locvars[local.locvar].end = pc;
+        }
+    }
+
+    // Close the `<close>` variables from the `nlocals`-th local on, if any.
+    fn close_from(&mut self, nlocals: usize) {
+        if self.locals[nlocals..].iter().any(|l| l.attrib == Attrib::Close) {
+            self.emit(ByteCode::Close(reg(0, nlocals)));
+        }
+    }
+
+    // A call, like `Name(explist)` or `t.f "x"`, or an assignment:
+    // `Name {, Name} = explist`, or to a field, `prefixexp.Name = exp`
+    // or `prefixexp[exp] = exp`.
+    fn name_stat(&mut self, name: String) {
+        if matches!(self.peek(), Token::Assign | Token::Comma) {
+            return self.assign_stat(name);
+        }
+        let dst = self.sp();
+        self.load_name(dst, name);
+        match self.suffixes(dst) {
+            Last::Call => {
+                // no results wanted as a statement
+                if let Some(ByteCode::Call(func, narg, _)) = self.proto.byte_codes.last().copied() {
+                    *self.proto.byte_codes.last_mut().unwrap() = ByteCode::Call(func, narg, 0);
+                }
+            }
+            last @ (Last::Field(_) | Last::Index(_)) if self.peek() == &Token::Assign => {
+                self.next();
+                let src = reg(dst, 2);
+                let t = self.next();
+                self.exp(t, src);
+                match last {
+                    Last::Field(key) => self.emit(ByteCode::SetField(dst, key, src)),
+                    _ => self.emit(ByteCode::SetTable(dst, reg(dst, 1), src)),
+                };
+            }
+            _ => panic!("expected `=` or arguments, found {:?}", self.peek()),
+        }
+    }
+
+    // Suffixes of a prefix expression in `dst`: `.Name`, `[exp]` and
+    // arguments. The last field is left for the caller to read or assign.
+    fn suffixes(&mut self, dst: u8) -> Last {
+        let mut last = Last::Name;
+        loop {
+            match self.peek() {
+                Token::Dot | Token::SqurL | Token::ParL | Token::String(_) | Token::CurlyL => (),
+                _ => return last,
+            }
+            self.get_field(dst, last);
+            last = match self.next() {
+                Token::Dot => match self.next() {
+                    Token::Name(name) => Last::Field(self.add_const(name.as_str().into())),
+                    t => panic!("expected name, found {t:?}"),
+                },
+                Token::SqurL => {
+                    let key = reg(dst, 1);
+                    let t = self.next();
+                    self.exp(t, key);
+                    self.expect(Token::SqurR);
+                    Last::Index(key)
+                }
+                t => {
+                    self.call(dst, t);
+                    Last::Call
+                }
+            };
+        }
+    }
+
+    // read a field left by `suffixes`, into the register of its table
+    fn get_field(&mut self, dst: u8, last: Last) {
+        match last {
+            Last::Field(key) => self.emit(ByteCode::GetField(dst, dst, key)),
+            Last::Index(key) => self.emit(ByteCode::GetTable(dst, dst, key)),
+            Last::Name | Last::Call => return,
+        };
+    }
+
+    // the arguments, from token `t`, of the function in register `func`,
+    // leaving one result there
+    fn call(&mut self, func: u8, t: Token) {
+        let narg = match t {
+            Token::String(s) => {
+                let c = self.add_const(s.into());
+                self.emit(ByteCode::LoadConst(reg(func, 1), c));
+                1
+            }
+            Token::CurlyL => {
+                self.table_constructor(reg(func, 1));
+                1
+            }
+            Token::ParL if self.peek() == &Token::ParR => {
+                self.next();
+                0
+            }
+            Token::ParL => {
+                let n = self.explist(reg(func, 1));
+                self.expect(Token::ParR);
+                n
+            }
+            t => panic!("expected arguments, found {t:?}"),
+        };
+        self.emit(ByteCode::Call(func, reg(0, narg), 1));
+    }
+
+    // `{ [exp] = exp, Name = exp, exp ... }` after the `{`, with `,` or
+    // `;` between fields
+    fn table_constructor(&mut self, dst: u8) {
+        self.emit(ByteCode::NewTable(dst));
+        let (key, value) = (reg(dst, 1), reg(dst, 2));
+        let mut n = 0;
+        loop {
+            match self.next() {
+                Token::CurlyR => return,
+                Token::SqurL => {
+                    let t = self.next();
+                    self.exp(t, key);
+                    self.expect(Token::SqurR);
+                    self.expect(Token::Assign);
+                    let t = self.next();
+                    self.exp(t, value);
+                    self.emit(ByteCode::SetTable(dst, key, value));
+                }
+                Token::Name(name) if self.peek() == &Token::Assign => {
+                    self.next();
+                    let t = self.next();
+                    self.exp(t, value);
+                    let c = self.add_const(name.as_str().into());
+                    self.emit(ByteCode::SetField(dst, c, value));
+                }
+                t => {
+                    self.exp(t, value);
+                    n += 1;
+                    self.exp(Token::Integer(n), key);
+                    self.emit(ByteCode::SetTable(dst, key, value));
+                }
+            }
+            match self.next() {
+                Token::Comma | Token::SemiColon => (),
+                Token::CurlyR => return,
+                t => panic!("expected `}}`, found {t:?}"),
+            }
+        }
+    }
+
+    fn assign_stat(&mut self, first: String) {
+        let mut names = vec![first];
+        while self.peek() == &Token::Comma {
+            self.next();
+            match self.next() {
+                Token::Name(name) => names.push(name),
+                t => panic!("expected name, found {t:?}"),
+            }
+        }
+        self.expect(Token::Assign);
+
+        // the values go to temporary registers first, as `a, b = b, a` swaps
+        let sp = self.sp();
+        let n = self.explist(sp);
+        for i in n..names.len() {
+            self.emit(ByteCode::LoadNil(reg(sp, i)));
+        }
+        for (i, name) in names.into_iter().enumerate() {
+            let src = reg(sp, i);
+            match self.local(&name) {
+                Some(reg) if self.locals[reg as usize].attrib != Attrib::None => {
+                    panic!("attempt to assign to const variable '{name}'");
+                }
+                Some(reg) => {
+                    self.emit(ByteCode::Move(reg, src));
+                }
+                None => {
+                    let c = self.add_const(name.into_bytes().into());
+                    self.emit(ByteCode::SetGlobal(c, src));
+                }
+            }
+        }
+    }
+
+    // `local Name attrib {, Name attrib} [= explist]`
+    fn local_stat(&mut self) {
+        let mut vars = Vec::new();
+        loop {
+            let name = match self.next() {
+                Token::Name(name) => name,
+                t => panic!("expected name, found {t:?}"),
+            };
+            let attrib = self.attrib();
+            vars.push((name, attrib));
+            if self.peek() != &Token::Comma {
+                break;
+            }
+            self.next();
+        }
+        if vars.iter().filter(|(_, attrib)| *attrib == Attrib::Close).count() > 1 {
+            panic!("multiple to-be-closed variables in local list");
+        }
+
+        let sp = self.sp();
+        let n = if self.peek() == &Token::Assign {
+            self.next();
+            self.explist(sp)
+        } else {
+            0
+        };
+        for i in n..vars.len() {
+            self.emit(ByteCode::LoadNil(reg(sp, i)));
+        }
+
+        // the new locals are in scope only after the values
+        for (name, attrib) in vars {
+            let reg = self.sp();
+            if attrib == Attrib::Close {
+                let c = self.add_const(name.as_str().into());
+                self.emit(ByteCode::Tbc(reg, c));
+            }
+            let start = self.proto.byte_codes.len();
+            self.locvars.push(LocVar { name: name.clone(), start, end: usize::MAX });
+            self.locals.push(Local { name, attrib, locvar: self.locvars.len() - 1 });
+        }
+    }
+
+    // `<const>`, `<close>` or nothing
+    fn attrib(&mut self) -> Attrib {
+        if self.peek() != &Token::Less {
+            return Attrib::None;
+        }
+        self.next();
+        let attrib = match self.next() {
+            Token::Name(name) if name == "const" => Attrib::Const,
+            Token::Name(name) if name == "close" => Attrib::Close,
+            Token::Name(name) => panic!("unknown attribute '{name}'"),
+            t => panic!("expected attribute, found {t:?}"),
+        };
+        self.expect(Token::Greater);
+        attrib
+    }
+
+    // `while exp do block end`
+    fn while_stat(&mut self) {
+        let start = self.proto.byte_codes.len();
+        let cond = self.sp();
+        let t = self.next();
+        self.exp(t, cond);
+        self.expect(Token::Do);
+        let test = self.emit(ByteCode::Test(cond, 0));
+
+        self.loops.push(Loop { nlocals: self.locals.len(), breaks: Vec::new() });
+        self.scoped_block();
+        let back = jump(self.proto.byte_codes.len(), start);
+        self.emit(ByteCode::Jump(back));
+
+        let end = self.proto.byte_codes.len();
+        self.proto.byte_codes[test] = ByteCode::Test(cond, jump(test, end));
+        for pc in self.loops.pop().unwrap().breaks {
+            self.proto.byte_codes[pc] = ByteCode::Jump(jump(pc, end));
+        }
+    }
+
+    // `return [explist] [;]`, which ends the block: the token after it
+    // is returned
+    fn return_stat(&mut self) -> Token {
+        let sp = self.sp();
+        let n = match self.peek() {
+            Token::SemiColon | Token::End | Token::Eos => 0,
+            _ => self.explist(sp),
+        };
+        self.emit(ByteCode::Return(sp, reg(0, n)));
+        if self.peek() == &Token::SemiColon {
+            self.next();
+        }
+        self.next()
+    }
+
+    // `break`, closing the variables of the blocks it leaves
+    fn break_stat(&mut self) {
+        let Some(nlocals) = self.loops.last().map(|l| l.nlocals) else {
+            panic!("break outside a loop at line {}", self.line);
+        };
+        self.close_from(nlocals);
+        let jump = self.emit(ByteCode::Jump(0));
+        self.loops.last_mut().unwrap().breaks.push(jump);
+    }
+
+    // Expressions separated by `,` into registers from `dst`. Returns how many.
+    fn explist(&mut self, dst: u8) -> usize {
+        let mut n = 0;
+        loop {
+            let t = self.next();
+            self.exp(t, reg(dst, n));
+            n += 1;
+            if self.peek() != &Token::Comma {
+                return n;
+            }
+            self.next();
+        }
+    }
+
+    // A single value, starting with the token `t`, into register `dst`.
+    fn exp(&mut self, t: Token, dst: u8) {
+        match t {
+            Token::Nil => {
+                self.emit(ByteCode::LoadNil(dst));
+            }
+            Token::True => {
+                self.emit(ByteCode::LoadBool(dst, true));
+            }
+            Token::False => {
+                self.emit(ByteCode::LoadBool(dst, false));
+            }
+            Token::Integer(i) => match i16::try_from(i) {
+                Ok(i) => {
+                    self.emit(ByteCode::LoadInt(dst, i));
+                }
+                Err(_) => {
+                    let c = self.add_const(Value::Integer(i));
+                    self.emit(ByteCode::LoadConst(dst, c));
+                }
+            },
+            Token::Float(f) => {
+                let c = self.add_const(Value::Float(f));
+                self.emit(ByteCode::LoadConst(dst, c));
+            }
+            Token::String(s) => {
+                let c = self.add_const(s.into());
+                self.emit(ByteCode::LoadConst(dst, c));
+            }
+            Token::CurlyL => self.table_constructor(dst),
+            Token::Name(name) => {
+                self.load_name(dst, name);
+                let last = self.suffixes(dst);
+                self.get_field(dst, last);
+            }
             t => panic!("unexpected token: {t:?}"),
         }
     }
 
-    dbg!(&constants);
-    dbg!(&byte_codes);
-    ParseProto { constants, byte_codes }
+    // a local, or else a global
+    fn load_name(&mut self, dst: u8, name: String) {
+        match self.local(&name) {
+            Some(reg) => {
+                self.emit(ByteCode::Move(dst, reg));
+            }
+            None => {
+                let c = self.add_const(name.into_bytes().into());
+                self.emit(ByteCode::GetGlobal(dst, c));
+            }
+        }
+    }
+
+    // the register of the innermost local called `name`
+    fn local(&self, name: &str) -> Option<u8> {
+        self.locals.iter().rposition(|l| l.name == name).map(|i| reg(0, i))
+    }
+}
+
+// Register `base + n`. Registers are numbered by a byte.
+fn reg(base: u8, n: usize) -> u8 {
+    u8::try_from(base as usize + n).unwrap_or_else(|_| panic!("too many registers"))
+}
+
+// The offset of a jump at `pc` to `to`, from the byte code after it.
+fn jump(pc: usize, to: usize) -> i16 {
+    i16::try_from(to as isize - pc as isize - 1).unwrap_or_else(|_| panic!("control structure too long"))
 }
 // ANCHOR_END: load
+
+// ANCHOR: syntax_error
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.start.line, self.span.start.column, self.message)
+    }
+}
+// ANCHOR_END: syntax_error
+
+
+// ANCHOR: parse_ast
+// Parse into a syntax tree, for the grammar `load` compiles. Unlike
+// `load`, errors are returned with their position rather than panic.
+pub fn parse_ast(input: impl Read + Seek) -> Result<Block, SyntaxError> {
+    let mut p = AstParser { lex: Lex::new(input), ahead: None, last: Span::default(), loops: 0 };
+    let (stats, t, span) = p.block()?;
+    if t != Token::Eos {
+        return Err(SyntaxError { message: format!("unexpected token: {t:?}"), span });
+    }
+    Ok(Block { stats, comments: p.lex.take_comments() })
+}
+
+struct AstParser<R: Read + Seek> {
+    lex: Lex<R>,
+    ahead: Option<(Token, Span)>,
+    last: Span,   // of the last token taken by `next`
+    loops: usize, // enclosing loops, for `break`
+}
+
+impl<R: Read + Seek> AstParser<R> {
+    fn next(&mut self) -> Result<(Token, Span), SyntaxError> {
+        let (t, span) = match self.ahead.take() {
+            Some(ahead) => ahead,
+            None => self.lex_next()?,
+        };
+        self.last = span;
+        Ok((t, span))
+    }
+
+    fn peek(&mut self) -> Result<&Token, SyntaxError> {
+        if self.ahead.is_none() {
+            self.ahead = Some(self.lex_next()?);
+        }
+        Ok(&self.ahead.as_ref().unwrap().0)
+    }
+
+    fn lex_next(&mut self) -> Result<(Token, Span), SyntaxError> {
+        match self.lex.try_next() {
+            Ok(t) => Ok((t, self.lex.span())),
+            Err(message) => Err(SyntaxError { message, span: self.lex.span() }),
+        }
+    }
+
+    fn expect(&mut self, t: Token) -> Result<(), SyntaxError> {
+        let (found, span) = self.next()?;
+        if found != t {
+            return Err(SyntaxError { message: format!("expected {t:?}, found {found:?}"), span });
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<(String, Span), SyntaxError> {
+        match self.next()? {
+            (Token::Name(name), span) => Ok((name, span)),
+            (t, span) => Err(SyntaxError { message: format!("expected name, found {t:?}"), span }),
+        }
+    }
+
+    // Statements up to a token that ends the block, which is returned.
+    fn block(&mut self) -> Result<(Vec<Stat>, Token, Span), SyntaxError> {
+        let mut stats = Vec::new();
+        loop {
+            let (t, span) = self.next()?;
+            let stat = match t {
+                Token::SemiColon => continue,
+                Token::Name(name) => self.name_stat(name, span)?,
+                Token::Local => self.local_stat(span)?,
+                Token::Do => Stat::Do(self.end_block()?, span.to(self.last)),
+                Token::While => self.while_stat(span)?,
+                Token::Break if self.loops == 0 => {
+                    return Err(SyntaxError { message: String::from("break outside a loop"), span });
+                }
+                Token::Break => Stat::Break(span),
+                Token::Return => {
+                    stats.push(self.return_stat(span)?);
+                    let (t, span) = self.next()?;
+                    return Ok((stats, t, span));
+                }
+                t => return Ok((stats, t, span)),
+            };
+            stats.push(stat);
+        }
+    }
+
+    // `block end`
+    fn end_block(&mut self) -> Result<Block, SyntaxError> {
+        let (stats, t, span) = self.block()?;
+        if t != Token::End {
+            return Err(SyntaxError { message: format!("expected `end`, found {t:?}"), span });
+        }
+        Ok(Block { stats, comments: Vec::new() })
+    }
+
+    // A call or an assignment, see `Parser::name_stat`.
+    fn name_stat(&mut self, name: String, span: Span) -> Result<Stat, SyntaxError> {
+        if matches!(self.peek()?, Token::Assign | Token::Comma) {
+            let mut targets = vec![Expr::Name(name, span)];
+            while self.peek()? == &Token::Comma {
+                self.next()?;
+                let (name, span) = self.name()?;
+                targets.push(Expr::Name(name, span));
+            }
+            self.expect(Token::Assign)?;
+            let values = self.explist()?;
+            return Ok(Stat::Assign { targets, values, span: span.to(self.last) });
+        }
+        match self.suffixes(Expr::Name(name, span))? {
+            Expr::Call(call) => Ok(Stat::Call(*call)),
+            target @ (Expr::Field(..) | Expr::Index(..)) if self.peek()? == &Token::Assign => {
+                self.next()?;
+                let value = self.exp()?;
+                Ok(Stat::Assign { targets: vec![target], values: vec![value], span: span.to(self.last) })
+            }
+            _ => {
+                let (t, span) = self.next()?;
+                Err(SyntaxError { message: format!("expected `=` or arguments, found {t:?}"), span })
+            }
+        }
+    }
+
+    // `local Name attrib {, Name attrib} [= explist]`
+    fn local_stat(&mut self, span: Span) -> Result<Stat, SyntaxError> {
+        let mut names = Vec::new();
+        loop {
+            let (name, span) = self.name()?;
+            let attrib = self.attrib()?;
+            names.push(LocalName { name, attrib, span });
+            if self.peek()? != &Token::Comma {
+                break;
+            }
+            self.next()?;
+        }
+        if names.iter().filter(|n| n.attrib == Attrib::Close).count() > 1 {
+            let message = String::from("multiple to-be-closed variables in local list");
+            return Err(SyntaxError { message, span: span.to(self.last) });
+        }
+
+        let values = if self.peek()? == &Token::Assign {
+            self.next()?;
+            self.explist()?
+        } else {
+            Vec::new()
+        };
+        Ok(Stat::Local { names, values, span: span.to(self.last) })
+    }
+
+    // `<const>`, `<close>` or nothing
+    fn attrib(&mut self) -> Result<Attrib, SyntaxError> {
+        if self.peek()? != &Token::Less {
+            return Ok(Attrib::None);
+        }
+        self.next()?;
+        let attrib = match self.next()? {
+            (Token::Name(name), _) if name == "const" => Attrib::Const,
+            (Token::Name(name), _) if name == "close" => Attrib::Close,
+            (Token::Name(name), span) => {
+                return Err(SyntaxError { message: format!("unknown attribute '{name}'"), span });
+            }
+            (t, span) => return Err(SyntaxError { message: format!("expected attribute, found {t:?}"), span }),
+        };
+        self.expect(Token::Greater)?;
+        Ok(attrib)
+    }
+
+    // `while exp do block end`
+    fn while_stat(&mut self, span: Span) -> Result<Stat, SyntaxError> {
+        let cond = self.exp()?;
+        self.expect(Token::Do)?;
+        self.loops += 1;
+        let body = self.end_block()?;
+        self.loops -= 1;
+        Ok(Stat::While { cond, body, span: span.to(self.last) })
+    }
+
+    // `return [explist] [;]`
+    fn return_stat(&mut self, span: Span) -> Result<Stat, SyntaxError> {
+        let values = match self.peek()? {
+            Token::SemiColon | Token::End | Token::Eos => Vec::new(),
+            _ => self.explist()?,
+        };
+        let span = span.to(self.last);
+        if self.peek()? == &Token::SemiColon {
+            self.next()?;
+        }
+        Ok(Stat::Return(values, span))
+    }
+
+    // `.Name`, `[exp]` and arguments after a prefix expression
+    fn suffixes(&mut self, mut prefix: Expr) -> Result<Expr, SyntaxError> {
+        loop {
+            prefix = match self.peek()? {
+                Token::Dot => {
+                    self.next()?;
+                    let (name, end) = self.name()?;
+                    let span = prefix.span().to(end);
+                    Expr::Field(Box::new(prefix), name, span)
+                }
+                Token::SqurL => {
+                    self.next()?;
+                    let key = self.exp()?;
+                    self.expect(Token::SqurR)?;
+                    let span = prefix.span().to(self.last);
+                    Expr::Index(Box::new(prefix), Box::new(key), span)
+                }
+                Token::ParL | Token::String(_) | Token::CurlyL => Expr::Call(Box::new(self.call(prefix)?)),
+                _ => return Ok(prefix),
+            };
+        }
+    }
+
+    // the arguments of `func`: `(explist)`, a string or a table
+    fn call(&mut self, func: Expr) -> Result<FuncCall, SyntaxError> {
+        let (args, parens) = match self.next()? {
+            (Token::String(s), span) => (vec![Expr::String(s, span)], false),
+            (Token::CurlyL, span) => (vec![self.table(span)?], false),
+            (Token::ParL, _) if self.peek()? == &Token::ParR => {
+                self.next()?;
+                (Vec::new(), true)
+            }
+            (Token::ParL, _) => {
+                let args = self.explist()?;
+                self.expect(Token::ParR)?;
+                (args, true)
+            }
+            (t, span) => return Err(SyntaxError { message: format!("expected arguments, found {t:?}"), span }),
+        };
+        let span = func.span().to(self.last);
+        Ok(FuncCall { func, args, parens, span })
+    }
+
+    // a table constructor after its `{`, at `start`
+    fn table(&mut self, start: Span) -> Result<Expr, SyntaxError> {
+        let mut fields = Vec::new();
+        loop {
+            let field = match self.next()? {
+                (Token::CurlyR, end) => return Ok(Expr::Table(fields, start.to(end))),
+                (Token::SqurL, _) => {
+                    let key = self.exp()?;
+                    self.expect(Token::SqurR)?;
+                    self.expect(Token::Assign)?;
+                    Field::Keyed(key, self.exp()?)
+                }
+                (Token::Name(name), span) if self.peek()? == &Token::Assign => {
+                    self.next()?;
+                    Field::Named(name, span, self.exp()?)
+                }
+                (t, span) => Field::Item(self.exp_from(t, span)?),
+            };
+            fields.push(field);
+            match self.next()? {
+                (Token::Comma | Token::SemiColon, _) => (),
+                (Token::CurlyR, end) => return Ok(Expr::Table(fields, start.to(end))),
+                (t, span) => return Err(SyntaxError { message: format!("expected `}}`, found {t:?}"), span }),
+            }
+        }
+    }
+
+    // expressions separated by `,`
+    fn explist(&mut self) -> Result<Vec<Expr>, SyntaxError> {
+        let mut exprs = vec![self.exp()?];
+        while self.peek()? == &Token::Comma {
+            self.next()?;
+            exprs.push(self.exp()?);
+        }
+        Ok(exprs)
+    }
+
+    fn exp(&mut self) -> Result<Expr, SyntaxError> {
+        let (t, span) = self.next()?;
+        self.exp_from(t, span)
+    }
+
+    // an expression starting with the token `t`
+    fn exp_from(&mut self, t: Token, span: Span) -> Result<Expr, SyntaxError> {
+        Ok(match t {
+            Token::Nil => Expr::Nil(span),
+            Token::True => Expr::Boolean(true, span),
+            Token::False => Expr::Boolean(false, span),
+            Token::Integer(i) => Expr::Integer(i, span),
+            Token::Float(f) => Expr::Float(f, span),
+            Token::String(s) => Expr::String(s, span),
+            Token::CurlyL => self.table(span)?,
+            Token::Name(name) => self.suffixes(Expr::Name(name, span))?,
+            t => return Err(SyntaxError { message: format!("unexpected token: {t:?}"), span }),
+        })
+    }
+}
+// ANCHOR_END: parse_ast
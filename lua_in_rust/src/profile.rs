@@ -0,0 +1,185 @@
+// Instrumenting profiler, built on the VM hooks.
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::debug::{CallInfo, HookEvent};
+use crate::vm::ExeState;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stat {
+    pub time: Duration,
+    pub instructions: u64,
+}
+
+// ANCHOR: profiler
+// The hook runs before every instruction, so it only adds to the stats
+// of the current stack, function and line by their ids. Their names are
+// looked up on call and line events, and strings are built only for the
+// output.
+#[derive(Debug)]
+pub struct Profiler {
+    names: Vec<String>, // of functions, e.g. "a.lua:main", and lines, "a.lua:3"
+    ids: HashMap<String, usize>,
+    stack_ids: HashMap<Vec<usize>, usize>,
+    stack_frames: Vec<Vec<usize>>, // the function ids of each stack id
+    stacks: Vec<Stat>,            // by stack id
+    functions: HashMap<usize, Stat>,
+    lines: HashMap<usize, Stat>,
+    last: Instant,
+    // where the time since `last` is spent: function ids from the
+    // outermost, and for each of them the line it is at
+    stack: Vec<usize>,
+    line_stack: Vec<Option<usize>>,
+    stack_id: Option<usize>,
+}
+
+impl Profiler {
+    // Install a profiler on `state`. It records until the hook is removed.
+    pub fn attach(state: &mut ExeState) -> Arc<Mutex<Profiler>> {
+        let profiler = Arc::new(Mutex::new(Profiler {
+            names: Vec::new(),
+            ids: HashMap::new(),
+            stack_ids: HashMap::new(),
+            stack_frames: Vec::new(),
+            stacks: Vec::new(),
+            functions: HashMap::new(),
+            lines: HashMap::new(),
+            last: Instant::now(),
+            stack: Vec::new(),
+            line_stack: Vec::new(),
+            stack_id: None,
+        }));
+
+        let p = profiler.clone();
+        state.sethook(Box::new(move |state, event| {
+            p.lock().unwrap().on_event(state, event);
+        }), "crl", 1);
+        profiler
+    }
+
+    fn on_event(&mut self, state: &ExeState, event: HookEvent) {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        self.add(|stat| stat.time += elapsed);
+
+        match event {
+            HookEvent::Call => {
+                let frames = state.frames();
+                if frames.len() != self.stack.len() + 1 {
+                    // attached while running: start over from the frames
+                    self.stack.clear();
+                    self.line_stack.clear();
+                    for ci in &frames[..frames.len() - 1] {
+                        let id = self.intern(frame_name(ci));
+                        self.stack.push(id);
+                        self.line_stack.push(None);
+                    }
+                }
+                let id = self.intern(frame_name(frames.last().unwrap()));
+                self.stack.push(id);
+                // time in a Rust function is also on the line calling it
+                self.line_stack.push(self.line_stack.last().copied().flatten());
+                self.update_stack();
+            }
+            HookEvent::Return => {
+                self.stack.pop();
+                self.line_stack.pop();
+                self.update_stack();
+            }
+            HookEvent::Line(line) => {
+                let source = &state.frames().last().unwrap().source;
+                let id = self.intern(format!("{source}:{line}"));
+                if let Some(top) = self.line_stack.last_mut() {
+                    *top = Some(id);
+                }
+            }
+            HookEvent::Count => self.add(|stat| stat.instructions += 1),
+        }
+    }
+
+    // change the stats of the current stack, function and line
+    fn add(&mut self, f: impl Fn(&mut Stat)) {
+        let Some(stack_id) = self.stack_id else {
+            return;
+        };
+        f(&mut self.stacks[stack_id]);
+        f(self.functions.entry(*self.stack.last().unwrap()).or_default());
+        if let Some(line) = self.line_stack.last().copied().flatten() {
+            f(self.lines.entry(line).or_default());
+        }
+    }
+
+    fn intern(&mut self, name: String) -> usize {
+        if let Some(&id) = self.ids.get(&name) {
+            return id;
+        }
+        self.names.push(name.clone());
+        self.ids.insert(name, self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    fn update_stack(&mut self) {
+        if self.stack.is_empty() {
+            self.stack_id = None;
+            return;
+        }
+        let id = match self.stack_ids.get(&self.stack[..]) {
+            Some(&id) => id,
+            None => {
+                self.stack_frames.push(self.stack.clone());
+                self.stacks.push(Stat::default());
+                self.stack_ids.insert(self.stack.clone(), self.stacks.len() - 1);
+                self.stacks.len() - 1
+            }
+        };
+        self.stack_id = Some(id);
+    }
+// ANCHOR_END: profiler
+
+// ANCHOR: report
+    // Write the folded stacks, one "stack microseconds" per line, which
+    // is the input format of flamegraph.pl and inferno.
+    pub fn write_folded(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut stacks: Vec<_> = self.stack_frames.iter()
+            .map(|ids| ids.iter().map(|&id| self.names[id].as_str()).collect::<Vec<_>>().join(";"))
+            .zip(&self.stacks)
+            .collect();
+        stacks.sort_by(|a, b| a.0.cmp(&b.0));
+        for (stack, stat) in stacks {
+            writeln!(out, "{stack} {}", stat.time.as_micros())?;
+        }
+        Ok(())
+    }
+
+    // Write the `n` most expensive functions and lines.
+    pub fn write_report(&self, out: &mut impl Write, n: usize) -> io::Result<()> {
+        write_top(out, "function", self.named(&self.functions), n)?;
+        writeln!(out)?;
+        write_top(out, "line", self.named(&self.lines), n)
+    }
+
+    fn named<'a>(&'a self, stats: &'a HashMap<usize, Stat>) -> Vec<(&'a str, &'a Stat)> {
+        stats.iter().map(|(&id, stat)| (self.names[id].as_str(), stat)).collect()
+    }
+}
+
+fn write_top(out: &mut impl Write, what: &str, mut stats: Vec<(&str, &Stat)>, n: usize) -> io::Result<()> {
+    stats.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(b.0)));
+
+    writeln!(out, "{:>12} {:>12}  {what}", "time(us)", "instructions")?;
+    for (name, stat) in stats.into_iter().take(n) {
+        writeln!(out, "{:>12} {:>12}  {name}", stat.time.as_micros(), stat.instructions)?;
+    }
+    Ok(())
+}
+// ANCHOR_END: report
+
+fn frame_name(ci: &CallInfo) -> String {
+    match (ci.what, &ci.name) {
+        ("main", _) => format!("{}:main", ci.source),
+        (_, Some(name)) => name.to_string(),
+        (_, None) => String::from("?"),
+    }
+}
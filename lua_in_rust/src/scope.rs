@@ -0,0 +1,156 @@
+// Name resolution on the syntax tree: which local variable, if any, each
+// name refers to, by the scope rules `parse::load` follows. For the
+// linter and the language server.
+use crate::ast::{Attrib, Block, Expr, Field, FuncCall, Stat};
+use crate::lex::{Pos, Span};
+
+// ANCHOR: scopes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Local {
+    pub name: String,
+    pub attrib: Attrib,
+    pub span: Span,             // of the name in its `local` statement
+    pub visible: Span,          // from after its statement to the end of its block
+    pub shadows: Option<usize>, // the local of the same name it hides
+}
+
+// A name used in an expression or assigned to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Name {
+    pub name: String,
+    pub span: Span,
+    pub local: Option<usize>, // or else a global
+    pub write: bool,          // an assignment target
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scopes {
+    pub locals: Vec<Local>, // in the order of declaration
+    pub names: Vec<Name>,   // in source order
+}
+// ANCHOR_END: scopes
+
+pub fn resolve(block: &Block) -> Scopes {
+    let mut r = Resolver { scopes: Scopes::default(), visible: Vec::new() };
+    r.block(block, Pos { line: u32::MAX, column: u32::MAX });
+    r.scopes.names.sort_by_key(|n| n.span.start);
+    r.scopes
+}
+
+impl Scopes {
+    // the local or global name at `pos`, including local declarations
+    pub fn name_at(&self, pos: Pos) -> Option<(&str, Option<usize>)> {
+        if let Some(n) = self.names.iter().find(|n| n.span.contains(pos)) {
+            return Some((&n.name, n.local));
+        }
+        let i = self.locals.iter().position(|l| l.span.contains(pos))?;
+        Some((&self.locals[i].name, Some(i)))
+    }
+
+    // uses of the local `i`, or of the global `name` if `i` is None
+    pub fn uses<'a>(&'a self, name: &'a str, local: Option<usize>) -> impl Iterator<Item = &'a Name> {
+        self.names.iter().filter(move |n| n.local == local && (local.is_some() || n.name == name))
+    }
+
+    // the locals in scope at `pos`, the innermost of each name
+    pub fn visible_at(&self, pos: Pos) -> Vec<&Local> {
+        let mut visible: Vec<&Local> = Vec::new();
+        for local in self.locals.iter().filter(|l| l.visible.start <= pos && pos < l.visible.end) {
+            visible.retain(|l| l.name != local.name);
+            visible.push(local);
+        }
+        visible
+    }
+}
+
+struct Resolver {
+    scopes: Scopes,
+    visible: Vec<usize>, // locals in scope, the innermost last
+}
+
+impl Resolver {
+    // `block`, which ends at `end`
+    fn block(&mut self, block: &Block, end: Pos) {
+        let nvisible = self.visible.len();
+        for stat in &block.stats {
+            self.stat(stat, end);
+        }
+        self.visible.truncate(nvisible);
+    }
+
+    fn stat(&mut self, stat: &Stat, end: Pos) {
+        match stat {
+            Stat::Call(call) => self.call(call),
+            Stat::Assign { targets, values, .. } => {
+                values.iter().for_each(|e| self.expr(e));
+                for target in targets {
+                    match target {
+                        Expr::Name(name, span) => self.name(name, *span, true),
+                        e => self.expr(e),
+                    }
+                }
+            }
+            Stat::Local { names, values, span } => {
+                // the new locals are in scope only after the values
+                values.iter().for_each(|e| self.expr(e));
+                for name in names {
+                    let shadows = self.lookup(&name.name);
+                    self.scopes.locals.push(Local {
+                        name: name.name.clone(),
+                        attrib: name.attrib,
+                        span: name.span,
+                        visible: Span { start: span.end, end },
+                        shadows,
+                    });
+                    self.visible.push(self.scopes.locals.len() - 1);
+                }
+            }
+            Stat::Do(body, span) => self.block(body, span.end),
+            Stat::While { cond, body, span } => {
+                self.expr(cond);
+                self.block(body, span.end);
+            }
+            Stat::Break(_) => (),
+            Stat::Return(values, _) => values.iter().for_each(|e| self.expr(e)),
+        }
+    }
+
+    fn call(&mut self, call: &FuncCall) {
+        self.expr(&call.func);
+        call.args.iter().for_each(|e| self.expr(e));
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Name(name, span) => self.name(name, *span, false),
+            Expr::Field(prefix, _, _) => self.expr(prefix),
+            Expr::Index(prefix, key, _) => {
+                self.expr(prefix);
+                self.expr(key);
+            }
+            Expr::Call(call) => self.call(call),
+            Expr::Table(fields, _) => {
+                for field in fields {
+                    match field {
+                        Field::Item(v) | Field::Named(_, _, v) => self.expr(v),
+                        Field::Keyed(k, v) => {
+                            self.expr(k);
+                            self.expr(v);
+                        }
+                    }
+                }
+            }
+            Expr::Nil(_) | Expr::Boolean(..) | Expr::Integer(..) | Expr::Float(..) | Expr::String(..) => (),
+        }
+    }
+
+    fn name(&mut self, name: &str, span: Span, write: bool) {
+        let local = self.lookup(name);
+        self.scopes.names.push(Name { name: name.to_string(), span, local, write });
+    }
+
+    // the innermost local called `name`
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.visible.iter().rev().copied().find(|&i| self.scopes.locals[i].name == name)
+    }
+}
@@ -0,0 +1,583 @@
+// Conversion between Rust types and Lua values with serde, so a struct
+// can be given to scripts as a table and a table read back as a struct.
+//
+// Structs and maps are tables with keys, sequences and tuples are tables
+// with keys 1..n, None and () are nil, and enums are the variant name or
+// a table `{Variant = value}`.
+use std::fmt;
+use ::serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use ::serde::ser::{self, Serialize};
+use crate::value::{Table, Value};
+
+pub fn to_value<T: Serialize + ?Sized>(v: &T) -> Result<Value, Error> {
+    v.serialize(Serializer)
+}
+
+pub fn from_value<T: DeserializeOwned>(v: &Value) -> Result<T, Error> {
+    T::deserialize(Deserializer(v))
+}
+
+// ANCHOR: error
+// The message, and where in the value: `window.size[2]` for the second
+// element of the field `size` of the field `window`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub message: String,
+    path: Vec<String>, // innermost first, as the error goes up
+}
+
+impl Error {
+    fn new(message: String) -> Self {
+        Error { message, path: Vec::new() }
+    }
+
+    fn at_field(mut self, name: &str) -> Self {
+        self.path.push(format!(".{name}"));
+        self
+    }
+
+    fn at_index(mut self, i: usize) -> Self {
+        self.path.push(format!("[{i}]"));
+        self
+    }
+
+    fn at_key(self, key: &Value) -> Self {
+        match key {
+            Value::String(s) => self.at_field(&String::from_utf8_lossy(s)),
+            Value::Integer(i) => self.at_index(*i as usize),
+            k => self.at_field(&format!("{k:?}")),
+        }
+    }
+
+    pub fn path(&self) -> String {
+        let path: String = self.path.iter().rev().map(String::as_str).collect();
+        path.strip_prefix('.').unwrap_or(&path).to_string()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::new(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::new(msg.to_string())
+    }
+}
+// ANCHOR_END: error
+
+// ANCHOR: ser
+struct Serializer;
+
+fn integer<T: TryInto<i64> + fmt::Display + Copy>(v: T) -> Result<Value, Error> {
+    v.try_into()
+        .map(Value::Integer)
+        .map_err(|_| Error::new(format!("integer {v} does not fit in a Lua integer")))
+}
+
+// `{name = value}`
+fn variant(name: &str, value: Value) -> Value {
+    let mut t = Table::new();
+    t.set(name.into(), value);
+    t.into()
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SerializeSeq;
+    type SerializeTuple = SerializeSeq;
+    type SerializeTupleStruct = SerializeSeq;
+    type SerializeTupleVariant = SerializeSeq;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeMap;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Boolean(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        integer(v)
+    }
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        integer(v)
+    }
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        integer(v)
+    }
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        integer(v)
+    }
+    fn serialize_i128(self, v: i128) -> Result<Value, Error> {
+        integer(v)
+    }
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        integer(v)
+    }
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        integer(v)
+    }
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        integer(v)
+    }
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        integer(v)
+    }
+    fn serialize_u128(self, v: u128) -> Result<Value, Error> {
+        integer(v)
+    }
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(Value::Float(v as f64))
+    }
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::Float(v))
+    }
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(v.to_string().as_str().into())
+    }
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(v.into())
+    }
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Nil)
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, v: &T) -> Result<Value, Error> {
+        v.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Nil)
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Value, Error> {
+        Ok(Value::Nil)
+    }
+    fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str) -> Result<Value, Error> {
+        Ok(variant.into())
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, v: &T) -> Result<Value, Error> {
+        v.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32, variant: &'static str, v: &T)
+            -> Result<Value, Error> {
+        let v = v.serialize(Serializer).map_err(|e| e.at_field(variant))?;
+        Ok(self::variant(variant, v))
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<SerializeSeq, Error> {
+        Ok(SerializeSeq { table: Table::new(), variant: None })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SerializeSeq, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<SerializeSeq, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, variant: &'static str, _: usize)
+            -> Result<SerializeSeq, Error> {
+        Ok(SerializeSeq { table: Table::new(), variant: Some(variant) })
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<SerializeMap, Error> {
+        Ok(SerializeMap { table: Table::new(), key: None, variant: None })
+    }
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<SerializeMap, Error> {
+        Ok(SerializeMap { table: Table::new(), key: None, variant: None })
+    }
+    fn serialize_struct_variant(self, _: &'static str, _: u32, variant: &'static str, _: usize)
+            -> Result<SerializeMap, Error> {
+        Ok(SerializeMap { table: Table::new(), key: None, variant: Some(variant) })
+    }
+}
+
+struct SerializeSeq {
+    table: Table,
+    variant: Option<&'static str>,
+}
+
+impl SerializeSeq {
+    fn push<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        let i = self.table.len() + 1;
+        let v = v.serialize(Serializer).map_err(|e| e.at_index(i))?;
+        if let Value::Nil = v {
+            return Err(Error::new(String::from("nil in a sequence")).at_index(i));
+        }
+        self.table.set(Value::Integer(i as i64), v);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        Ok(match self.variant {
+            Some(name) => variant(name, self.table.into()),
+            None => self.table.into(),
+        })
+    }
+}
+
+impl ser::SerializeSeq for SerializeSeq {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        self.push(v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeSeq {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        self.push(v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeSeq {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        self.push(v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeSeq {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        let variant = self.variant.unwrap_or_default();
+        self.push(v).map_err(|e| e.at_field(variant))
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+struct SerializeMap {
+    table: Table,
+    key: Option<Value>, // between serialize_key and serialize_value
+    variant: Option<&'static str>,
+}
+
+impl SerializeMap {
+    fn insert(&mut self, key: Value, v: Value) -> Result<(), Error> {
+        match key {
+            Value::Nil => Err(Error::new(String::from("map key is nil"))),
+            Value::Float(f) if f.is_nan() => Err(Error::new(String::from("map key is NaN"))),
+            key => {
+                self.table.set(key, v);
+                Ok(())
+            }
+        }
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, name: &'static str, v: &T) -> Result<(), Error> {
+        let v = v.serialize(Serializer).map_err(|e| e.at_field(name))?;
+        self.insert(name.into(), v)
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        Ok(match self.variant {
+            Some(name) => variant(name, self.table.into()),
+            None => self.table.into(),
+        })
+    }
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(Serializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        let key = self.key.take().unwrap_or(Value::Nil);
+        let v = v.serialize(Serializer).map_err(|e| e.at_key(&key))?;
+        self.insert(key, v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, name: &'static str, v: &T) -> Result<(), Error> {
+        self.field(name, v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, name: &'static str, v: &T) -> Result<(), Error> {
+        let variant = self.variant.unwrap_or_default();
+        self.field(name, v).map_err(|e| e.at_field(variant))
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+// ANCHOR_END: ser
+
+// ANCHOR: de
+struct Deserializer<'a>(&'a Value);
+
+impl Deserializer<'_> {
+    fn invalid_type(&self, exp: &dyn de::Expected) -> Error {
+        let unexp = match self.0 {
+            Value::Nil => de::Unexpected::Unit,
+            Value::Boolean(b) => de::Unexpected::Bool(*b),
+            Value::Integer(i) => de::Unexpected::Signed(*i),
+            Value::Float(f) => de::Unexpected::Float(*f),
+            Value::String(s) => de::Unexpected::Bytes(s),
+            Value::Table(_) => de::Unexpected::Map,
+            Value::Function(_) => de::Unexpected::Other("function"),
+            Value::LightUserData(_) => de::Unexpected::Other("userdata"),
+        };
+        de::Error::invalid_type(unexp, exp)
+    }
+}
+
+// Entries of a table: the array part, then the others.
+fn entries(t: &Table) -> Vec<(Value, Value)> {
+    t.array.iter().enumerate()
+        .map(|(i, v)| (Value::Integer(i as i64 + 1), v.clone()))
+        .chain(t.map.iter().map(|(k, v)| (k.clone(), v.clone())))
+        .collect()
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Nil => visitor.visit_unit(),
+            Value::Boolean(b) => visitor.visit_bool(*b),
+            Value::Integer(i) => visitor.visit_i64(*i),
+            Value::Float(f) => visitor.visit_f64(*f),
+            Value::String(s) => match std::str::from_utf8(s) {
+                Ok(s) => visitor.visit_str(s),
+                Err(_) => visitor.visit_bytes(s),
+            },
+            Value::Table(t) => {
+                let t = t.borrow();
+                if t.map.is_empty() && !t.array.is_empty() {
+                    visitor.visit_seq(Seq { items: t.array.clone().into_iter(), i: 0 })
+                } else {
+                    visitor.visit_map(Map { entries: entries(&t).into_iter(), value: None })
+                }
+            }
+            Value::Function(_) | Value::LightUserData(_) => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    // integral floats are accepted for integers, as in Lua
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match *self.0 {
+            Value::Float(f) if f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 => {
+                visitor.visit_i64(f as i64)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // the empty table is also an empty sequence
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Table(t) if t.borrow().map.is_empty() => {
+                visitor.visit_seq(Seq { items: t.borrow().array.clone().into_iter(), i: 0 })
+            }
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _: &'static str, _: usize, visitor: V)
+            -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Table(t) => visitor.visit_map(Map { entries: entries(&t.borrow()).into_iter(), value: None }),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _: &'static str, _: &'static [&'static str], visitor: V)
+            -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    // "Variant" or {Variant = value}
+    fn deserialize_enum<V: Visitor<'de>>(self, _: &'static str, _: &'static [&'static str], visitor: V)
+            -> Result<V::Value, Error> {
+        match self.0 {
+            Value::String(s) => {
+                let name = String::from_utf8_lossy(s).into_owned();
+                visitor.visit_enum(name.into_deserializer())
+            }
+            Value::Table(t) => {
+                let mut entries = entries(&t.borrow());
+                match (entries.pop(), entries.is_empty()) {
+                    (Some((Value::String(name), value)), true) => {
+                        let name = String::from_utf8_lossy(&name).into_owned();
+                        visitor.visit_enum(Enum { name, value })
+                    }
+                    _ => Err(de::Error::custom("expected a table with a single variant name as key")),
+                }
+            }
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    ::serde::forward_to_deserialize_any! {
+        bool i128 u128 f32 f64 char str string bytes byte_buf unit unit_struct identifier ignored_any
+    }
+}
+
+struct Seq {
+    items: std::vec::IntoIter<Value>,
+    i: usize,
+}
+
+impl<'de> SeqAccess<'de> for Seq {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        let Some(v) = self.items.next() else {
+            return Ok(None);
+        };
+        self.i += 1;
+        let i = self.i;
+        seed.deserialize(Deserializer(&v)).map(Some).map_err(|e| e.at_index(i))
+    }
+}
+
+struct Map {
+    entries: std::vec::IntoIter<(Value, Value)>,
+    value: Option<(Value, Value)>, // the key again, for errors
+}
+
+impl<'de> MapAccess<'de> for Map {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        let Some((k, v)) = self.entries.next() else {
+            return Ok(None);
+        };
+        let key = seed.deserialize(Deserializer(&k))?;
+        self.value = Some((k, v));
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (k, v) = self.value.take().expect("next_value before next_key");
+        seed.deserialize(Deserializer(&v)).map_err(|e| e.at_key(&k))
+    }
+}
+
+struct Enum {
+    name: String,
+    value: Value,
+}
+
+impl<'de> de::EnumAccess<'de> for Enum {
+    type Error = Error;
+    type Variant = Variant;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Variant), Error> {
+        let v = seed.deserialize(self.name.as_str().into_deserializer())?;
+        Ok((v, Variant { name: self.name, value: self.value }))
+    }
+}
+
+struct Variant {
+    name: String,
+    value: Value,
+}
+
+impl<'de> de::VariantAccess<'de> for Variant {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            Value::Nil => Ok(()),
+            _ => Err(de::Error::custom(format!("expected no value for variant {}", self.name))),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(Deserializer(&self.value)).map_err(|e| e.at_field(&self.name))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(Deserializer(&self.value), visitor).map_err(|e| e.at_field(&self.name))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(Deserializer(&self.value), visitor).map_err(|e| e.at_field(&self.name))
+    }
+}
+// ANCHOR_END: de
@@ -0,0 +1,322 @@
+// "string" library. So far only the binary packing functions, with
+// the format language of Lua 5.4's `string.pack`.
+use crate::value::{Table, Value};
+use crate::vm::ExeState;
+
+const MAXINTSIZE: usize = 16; // max size of an integer in formats
+const NATIVE_ALIGN: usize = 8; // default max alignment for '!'
+const MAXSIZE: usize = i64::MAX as usize;
+
+pub fn lib() -> Table {
+    let mut t = Table::new();
+    t.set("pack".into(), Value::Function(str_pack));
+    t.set("packsize".into(), Value::Function(str_packsize));
+    t.set("unpack".into(), Value::Function(str_unpack));
+    t
+}
+
+// ANCHOR: format
+// Kinds of format options.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum KOption {
+    Int,       // signed integer
+    Uint,      // unsigned integer
+    Float,     // f32
+    Number,    // Lua float, f64
+    Double,    // f64
+    Char,      // fixed-size string
+    String,    // string preceded by its length
+    Zstr,      // zero-terminated string
+    Padding,   // one padding byte
+    PaddAlign, // padding to the alignment of the next option
+    Nop,       // no data: spaces and endianness/alignment settings
+}
+
+// State while reading a format string.
+struct Header<'a> {
+    fmt: &'a [u8],
+    little: bool,
+    maxalign: usize,
+}
+
+impl<'a> Header<'a> {
+    fn new(fmt: &'a [u8]) -> Self {
+        Header { fmt, little: cfg!(target_endian = "little"), maxalign: 1 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.fmt.is_empty()
+    }
+
+    // optional size following an option, or the default
+    fn getnum(&mut self, default: usize) -> Option<usize> {
+        let n = self.fmt.iter().take_while(|b| b.is_ascii_digit()).count();
+        if n == 0 {
+            return if default == 0 { None } else { Some(default) };
+        }
+        let mut a: usize = 0;
+        for &b in &self.fmt[..n] {
+            a = a.saturating_mul(10).saturating_add((b - b'0') as usize);
+        }
+        self.fmt = &self.fmt[n..];
+        Some(a)
+    }
+
+    fn getnumlimit(&mut self, default: usize) -> usize {
+        let sz = self.getnum(default).unwrap();
+        if sz == 0 || sz > MAXINTSIZE {
+            panic!("integral size ({sz}) out of limits [1,{MAXINTSIZE}]");
+        }
+        sz
+    }
+
+    // Read one option and its size.
+    fn getoption(&mut self) -> (KOption, usize) {
+        let opt = self.fmt[0];
+        self.fmt = &self.fmt[1..];
+        match opt {
+            b'b' => (KOption::Int, 1),
+            b'B' => (KOption::Uint, 1),
+            b'h' => (KOption::Int, 2),
+            b'H' => (KOption::Uint, 2),
+            b'l' | b'j' => (KOption::Int, 8),
+            b'L' | b'J' | b'T' => (KOption::Uint, 8),
+            b'f' => (KOption::Float, 4),
+            b'n' => (KOption::Number, 8),
+            b'd' => (KOption::Double, 8),
+            b'i' => (KOption::Int, self.getnumlimit(4)),
+            b'I' => (KOption::Uint, self.getnumlimit(4)),
+            b's' => (KOption::String, self.getnumlimit(8)),
+            b'c' => match self.getnum(0) {
+                Some(size) => (KOption::Char, size),
+                None => panic!("missing size for format option 'c'"),
+            },
+            b'z' => (KOption::Zstr, 0),
+            b'x' => (KOption::Padding, 1),
+            b'X' => (KOption::PaddAlign, 0),
+            b' ' => (KOption::Nop, 0),
+            b'<' => {
+                self.little = true;
+                (KOption::Nop, 0)
+            }
+            b'>' => {
+                self.little = false;
+                (KOption::Nop, 0)
+            }
+            b'=' => {
+                self.little = cfg!(target_endian = "little");
+                (KOption::Nop, 0)
+            }
+            b'!' => {
+                self.maxalign = self.getnumlimit(NATIVE_ALIGN);
+                (KOption::Nop, 0)
+            }
+            _ => panic!("invalid format option '{}'", opt as char),
+        }
+    }
+
+    // Read one option, its size, and the padding needed before it
+    // to be aligned, given the `total` size so far.
+    fn getdetails(&mut self, total: usize, fname: &str) -> (KOption, usize, usize) {
+        let (opt, size) = self.getoption();
+        let mut align = size;
+        if opt == KOption::PaddAlign {
+            let next = if self.is_empty() { None } else { Some(self.getoption()) };
+            match next {
+                Some((next, size)) if next != KOption::Char && size != 0 => align = size,
+                _ => panic!("bad argument #1 to '{fname}' (invalid next option for option 'X')"),
+            }
+        }
+        if align <= 1 || opt == KOption::Char {
+            return (opt, size, 0);
+        }
+        let align = align.min(self.maxalign);
+        if !align.is_power_of_two() {
+            panic!("bad argument #1 to '{fname}' (format asks for alignment not power of 2)");
+        }
+        (opt, size, (align - (total & (align - 1))) & (align - 1))
+    }
+}
+// ANCHOR_END: format
+
+// ANCHOR: int
+// Integers of `size` bytes, which beyond 8 are extended with the sign.
+fn packint(buf: &mut Vec<u8>, n: u64, little: bool, size: usize, neg: bool) {
+    let mut bytes: Vec<u8> = (0..size)
+        .map(|i| if i < 8 { (n >> (8 * i)) as u8 } else if neg { 0xFF } else { 0 })
+        .collect();
+    if !little {
+        bytes.reverse();
+    }
+    buf.extend(bytes);
+}
+
+fn unpackint(s: &[u8], little: bool, signed: bool) -> i64 {
+    let size = s.len();
+    let byte = |i: usize| if little { s[i] } else { s[size - 1 - i] };
+    let limit = size.min(8);
+    let mut res: u64 = 0;
+    for i in (0..limit).rev() {
+        res = (res << 8) | byte(i) as u64;
+    }
+    if size < 8 {
+        if signed {
+            let mask = 1u64 << (size * 8 - 1);
+            res = (res ^ mask).wrapping_sub(mask); // sign extension
+        }
+    } else if size > 8 {
+        // the extra bytes must be all sign
+        let ext = if !signed || (res as i64) >= 0 { 0 } else { 0xFF };
+        if (limit..size).any(|i| byte(i) != ext) {
+            panic!("{size}-byte integer does not fit into Lua Integer");
+        }
+    }
+    res as i64
+}
+// ANCHOR_END: int
+
+// ANCHOR: pack
+// string.pack(fmt, v1, v2, ...)
+fn str_pack(state: &mut ExeState) -> i32 {
+    let fmt = state.check_string(1, "pack").to_vec();
+    let mut h = Header::new(&fmt);
+    let mut buf = Vec::new();
+    let mut arg = 1;
+    while !h.is_empty() {
+        let (opt, size, ntoalign) = h.getdetails(buf.len(), "pack");
+        buf.resize(buf.len() + ntoalign, 0);
+        arg += 1;
+        match opt {
+            KOption::Int => {
+                let n = state.check_integer(arg, "pack");
+                if size < 8 {
+                    let lim = 1i64 << (size * 8 - 1);
+                    state.arg_check(-lim <= n && n < lim, arg, "pack", "integer overflow");
+                }
+                packint(&mut buf, n as u64, h.little, size, n < 0);
+            }
+            KOption::Uint => {
+                let n = state.check_integer(arg, "pack");
+                if size < 8 {
+                    state.arg_check((n as u64) < 1 << (size * 8), arg, "pack", "unsigned overflow");
+                }
+                packint(&mut buf, n as u64, h.little, size, false);
+            }
+            KOption::Float | KOption::Number | KOption::Double => {
+                let f = state.check_number(arg, "pack");
+                let bytes = match (opt, h.little) {
+                    (KOption::Float, true) => (f as f32).to_le_bytes().to_vec(),
+                    (KOption::Float, false) => (f as f32).to_be_bytes().to_vec(),
+                    (_, true) => f.to_le_bytes().to_vec(),
+                    (_, false) => f.to_be_bytes().to_vec(),
+                };
+                buf.extend(bytes);
+            }
+            KOption::Char => {
+                let s = state.check_string(arg, "pack");
+                state.arg_check(s.len() <= size, arg, "pack", "string longer than given size");
+                buf.extend_from_slice(s);
+                buf.resize(buf.len() + size - s.len(), 0);
+            }
+            KOption::String => {
+                let s = state.check_string(arg, "pack");
+                state.arg_check(size >= 8 || (s.len() as u64) < 1 << (size * 8),
+                    arg, "pack", "string length does not fit in given size");
+                packint(&mut buf, s.len() as u64, h.little, size, false);
+                buf.extend_from_slice(s);
+            }
+            KOption::Zstr => {
+                let s = state.check_string(arg, "pack");
+                state.arg_check(!s.contains(&0), arg, "pack", "string contains zeros");
+                buf.extend_from_slice(s);
+                buf.push(0);
+            }
+            KOption::Padding => {
+                buf.push(0);
+                arg -= 1;
+            }
+            KOption::PaddAlign | KOption::Nop => arg -= 1,
+        }
+    }
+    state.push(buf.into());
+    1
+}
+
+// string.packsize(fmt): the size of what string.pack(fmt, ...) returns
+fn str_packsize(state: &mut ExeState) -> i32 {
+    let fmt = state.check_string(1, "packsize").to_vec();
+    let mut h = Header::new(&fmt);
+    let mut total: usize = 0;
+    while !h.is_empty() {
+        let (opt, size, ntoalign) = h.getdetails(total, "packsize");
+        state.arg_check(opt != KOption::String && opt != KOption::Zstr, 1, "packsize", "variable-length format");
+        let size = size + ntoalign;
+        state.arg_check(total <= MAXSIZE - size, 1, "packsize", "format result too large");
+        total += size;
+    }
+    state.push(Value::Integer(total as i64));
+    1
+}
+
+// string.unpack(fmt, s [, pos]): the values, and the position after them
+fn str_unpack(state: &mut ExeState) -> i32 {
+    let fmt = state.check_string(1, "unpack").to_vec();
+    let data = state.check_string(2, "unpack").to_vec();
+    let ld = data.len();
+    let pos = match state.opt_integer(3, "unpack", 1) {
+        p if p > 0 => p as usize - 1,
+        p if p < 0 && p.unsigned_abs() <= ld as u64 => ld - p.unsigned_abs() as usize,
+        _ => 0,
+    };
+    state.arg_check(pos <= ld, 3, "unpack", "initial position out of string");
+
+    let mut h = Header::new(&fmt);
+    let mut pos = pos;
+    let mut n = 0;
+    while !h.is_empty() {
+        let (opt, size, ntoalign) = h.getdetails(pos, "unpack");
+        state.arg_check(ntoalign + size <= ld - pos, 2, "unpack", "data string too short");
+        pos += ntoalign;
+        let field = &data[pos..pos + size];
+        let v = match opt {
+            KOption::Int | KOption::Uint => {
+                Value::Integer(unpackint(field, h.little, opt == KOption::Int))
+            }
+            KOption::Float => {
+                let bytes = field.try_into().unwrap();
+                let f = if h.little { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) };
+                Value::Float(f as f64)
+            }
+            KOption::Number | KOption::Double => {
+                let bytes = field.try_into().unwrap();
+                Value::Float(if h.little { f64::from_le_bytes(bytes) } else { f64::from_be_bytes(bytes) })
+            }
+            KOption::Char => Value::from(field),
+            KOption::String => {
+                let len = unpackint(field, h.little, false) as u64;
+                state.arg_check(len <= (ld - pos - size) as u64, 2, "unpack", "data string too short");
+                let start = pos + size;
+                pos += len as usize;
+                Value::from(&data[start..start + len as usize])
+            }
+            KOption::Zstr => {
+                let Some(len) = data[pos..].iter().position(|&b| b == 0) else {
+                    panic!("bad argument #2 to 'unpack' (unfinished string for format 'z')");
+                };
+                let s = data[pos..pos + len].to_vec();
+                pos += len + 1;
+                Value::from(s)
+            }
+            KOption::Padding | KOption::PaddAlign | KOption::Nop => {
+                pos += size;
+                continue;
+            }
+        };
+        state.push(v);
+        pos += size;
+        n += 1;
+    }
+    state.push(Value::Integer(pos as i64 + 1));
+    n + 1
+}
+// ANCHOR_END: pack
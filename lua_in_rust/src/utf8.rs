@@ -0,0 +1,241 @@
+// "utf8" library, and UTF-8 coding as Lua does it: sequences of up to
+// 6 bytes for values up to 2^31, unless strict.
+use crate::value::{Table, Value};
+use crate::vm::ExeState;
+
+pub const CHARPATTERN: &[u8] = b"[\x00-\x7F\xC2-\xFD][\x80-\xBF]*";
+
+const MAXUNICODE: u32 = 0x10FFFF;
+const MAXUTF: u32 = 0x7FFFFFFF;
+
+// ANCHOR: coding
+pub fn encode(mut x: u32, buf: &mut Vec<u8>) {
+    if x < 0x80 {
+        buf.push(x as u8);
+        return;
+    }
+    let mut bytes = Vec::new();
+    let mut mfb = 0x3f; // the max value that fits in the first byte
+    loop {
+        bytes.push(0x80 | (x & 0x3f) as u8);
+        x >>= 6;
+        mfb >>= 1;
+        if x <= mfb {
+            break;
+        }
+    }
+    bytes.push(((!mfb << 1) | x) as u8);
+    buf.extend(bytes.iter().rev());
+}
+
+// Decode the sequence at the start of `s`, into the code and its length.
+// Strict decoding rejects surrogates and values beyond Unicode.
+pub fn decode(s: &[u8], strict: bool) -> Option<(u32, usize)> {
+    const LIMITS: [u32; 6] = [!0, 0x80, 0x800, 0x10000, 0x200000, 0x4000000];
+
+    let c = *s.first()? as u32;
+    if c < 0x80 {
+        return Some((c, 1));
+    }
+    let n = match c { // number of continuation bytes
+        0xC0..=0xDF => 1,
+        0xE0..=0xEF => 2,
+        0xF0..=0xF7 => 3,
+        0xF8..=0xFB => 4,
+        0xFC..=0xFD => 5,
+        _ => return None,
+    };
+    let mut res = c & (0x7F >> (n + 1));
+    for i in 1..=n {
+        let b = *s.get(i)? as u32;
+        if !is_cont(b as u8) {
+            return None;
+        }
+        res = (res << 6) | (b & 0x3F);
+    }
+    if res < LIMITS[n] { // overlong
+        return None;
+    }
+    if strict && (res > MAXUNICODE || (0xD800..=0xDFFF).contains(&res)) {
+        return None;
+    }
+    Some((res, n + 1))
+}
+
+fn is_cont(b: u8) -> bool {
+    b & 0xC0 == 0x80
+}
+// ANCHOR_END: coding
+
+// ANCHOR: lib
+pub fn lib() -> Table {
+    let mut t = Table::new();
+    t.set("char".into(), Value::Function(utf8_char));
+    t.set("charpattern".into(), CHARPATTERN.into());
+    t.set("codes".into(), Value::Function(utf8_codes));
+    t.set("codepoint".into(), Value::Function(utf8_codepoint));
+    t.set("len".into(), Value::Function(utf8_len));
+    t.set("offset".into(), Value::Function(utf8_offset));
+    t
+}
+
+// relative string position: negative means back from the end
+fn posrelat(pos: i64, len: usize) -> i64 {
+    if pos >= 0 {
+        pos
+    } else if pos.unsigned_abs() > len as u64 {
+        0
+    } else {
+        len as i64 + pos + 1
+    }
+}
+
+// utf8.char(...)
+fn utf8_char(state: &mut ExeState) -> i32 {
+    let mut s = Vec::new();
+    for i in 1..=state.arg_count() {
+        let code = state.check_integer(i, "char");
+        state.arg_check(0 <= code && code <= MAXUTF as i64, i, "char", "value out of range");
+        encode(code as u32, &mut s);
+    }
+    state.push(s.into());
+    1
+}
+
+// utf8.codes(s [, lax]): the iterator function, s and 0, for the generic for
+fn utf8_codes(state: &mut ExeState) -> i32 {
+    let s = state.check_string(1, "codes").to_vec();
+    state.arg_check(s.first().is_none_or(|&b| !is_cont(b)), 1, "codes", "invalid UTF-8 code");
+    let lax = state.arg(2).is_truthy();
+    state.push(Value::Function(if lax { iter_lax } else { iter_strict }));
+    state.push(s.into());
+    state.push(Value::Integer(0));
+    3
+}
+
+fn iter_strict(state: &mut ExeState) -> i32 {
+    iter_codes(state, true)
+}
+
+fn iter_lax(state: &mut ExeState) -> i32 {
+    iter_codes(state, false)
+}
+
+fn iter_codes(state: &mut ExeState, strict: bool) -> i32 {
+    let s = state.check_string(1, "for iterator").to_vec();
+    let mut n = state.check_integer(2, "for iterator") as usize;
+    // skip the continuation bytes of the last character
+    while n < s.len() && is_cont(s[n]) {
+        n += 1;
+    }
+    if n >= s.len() {
+        return 0;
+    }
+    match decode(&s[n..], strict) {
+        Some((code, len)) if s.get(n + len).is_none_or(|&b| !is_cont(b)) => {
+            state.push(Value::Integer(n as i64 + 1));
+            state.push(Value::Integer(code as i64));
+            2
+        }
+        _ => panic!("invalid UTF-8 code"),
+    }
+}
+
+// utf8.codepoint(s [, i [, j [, lax]]])
+fn utf8_codepoint(state: &mut ExeState) -> i32 {
+    let s = state.check_string(1, "codepoint").to_vec();
+    let posi = posrelat(state.opt_integer(2, "codepoint", 1), s.len());
+    let pose = posrelat(state.opt_integer(3, "codepoint", posi), s.len());
+    let strict = !state.arg(4).is_truthy();
+    state.arg_check(posi >= 1, 2, "codepoint", "out of bounds");
+    state.arg_check(pose <= s.len() as i64, 3, "codepoint", "out of bounds");
+    if posi > pose {
+        return 0;
+    }
+
+    let mut n = 0;
+    let mut pos = posi as usize - 1;
+    while pos < pose as usize {
+        let Some((code, len)) = decode(&s[pos..], strict) else {
+            panic!("invalid UTF-8 code");
+        };
+        state.push(Value::Integer(code as i64));
+        pos += len;
+        n += 1;
+    }
+    n
+}
+
+// utf8.len(s [, i [, j [, lax]]]): the number of characters, or
+// nil and the position of the first invalid byte
+fn utf8_len(state: &mut ExeState) -> i32 {
+    let s = state.check_string(1, "len").to_vec();
+    let posi = posrelat(state.opt_integer(2, "len", 1), s.len());
+    let posj = posrelat(state.opt_integer(3, "len", -1), s.len());
+    let strict = !state.arg(4).is_truthy();
+    state.arg_check(1 <= posi && posi - 1 <= s.len() as i64, 2, "len", "initial position out of bounds");
+    state.arg_check(posj <= s.len() as i64, 3, "len", "final position out of bounds");
+
+    let mut n = 0;
+    let mut pos = posi as usize - 1;
+    while (pos as i64) < posj {
+        match decode(&s[pos..], strict) {
+            Some((_, len)) => pos += len,
+            None => {
+                state.push(Value::Nil);
+                state.push(Value::Integer(pos as i64 + 1));
+                return 2;
+            }
+        }
+        n += 1;
+    }
+    state.push(Value::Integer(n));
+    1
+}
+
+// utf8.offset(s, n [, i]): the position where the n-th character,
+// counting from position i, starts
+fn utf8_offset(state: &mut ExeState) -> i32 {
+    let s = state.check_string(1, "offset").to_vec();
+    let len = s.len();
+    let mut n = state.check_integer(2, "offset");
+    let default = if n >= 0 { 1 } else { len as i64 + 1 };
+    let posi = posrelat(state.opt_integer(3, "offset", default), len);
+    state.arg_check(1 <= posi && posi - 1 <= len as i64, 3, "offset", "position out of bounds");
+
+    let is_cont_at = |p: usize| p < len && is_cont(s[p]);
+    let mut posi = posi as usize - 1;
+    if n == 0 {
+        // the start of the character containing byte i
+        while posi > 0 && is_cont_at(posi) {
+            posi -= 1;
+        }
+        state.push(Value::Integer(posi as i64 + 1));
+        return 1;
+    }
+
+    if is_cont_at(posi) {
+        panic!("initial position is a continuation byte");
+    }
+    if n < 0 {
+        while n < 0 && posi > 0 {
+            posi -= 1;
+            while posi > 0 && is_cont_at(posi) {
+                posi -= 1;
+            }
+            n += 1;
+        }
+    } else {
+        n -= 1;
+        while n > 0 && posi < len {
+            posi += 1;
+            while is_cont_at(posi) {
+                posi += 1;
+            }
+            n -= 1;
+        }
+    }
+    state.push(if n == 0 { Value::Integer(posi as i64 + 1) } else { Value::Nil });
+    1
+}
+// ANCHOR_END: lib
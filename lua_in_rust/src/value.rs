@@ -1,4 +1,7 @@
+use std::collections::HashMap;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use crate::vm::ExeState;
 
 #[derive(Clone)]
@@ -7,8 +10,10 @@ pub enum Value {
     Boolean(bool),
     Integer(i64),
     Float(f64),
-    String(String),
+    String(Arc<[u8]>), // Lua strings are bytes, not always UTF-8, and shared
+    Table(Arc<RefLock<Table>>),
     Function(fn (&mut ExeState) -> i32),
+    LightUserData(usize), // an address, compared by value, like `json.null`
 }
 
 impl fmt::Debug for Value {
@@ -18,8 +23,223 @@ impl fmt::Debug for Value {
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Integer(i) => write!(f, "{}", i),
             Value::Float(n) => write!(f, "{}", n),
-            Value::String(s) => write!(f, "{s}"),
+            Value::String(s) => write!(f, "{}", String::from_utf8_lossy(s)),
+            Value::Table(t) => write!(f, "table: {:?}", Arc::as_ptr(t)),
             Value::Function(_) => write!(f, "function"),
+            Value::LightUserData(p) => write!(f, "userdata: {:#x}", p),
         }
     }
 }
+
+impl Value {
+    // result of Lua's "type" function
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Boolean(_) => "boolean",
+            Value::Integer(_) | Value::Float(_) => "number",
+            Value::String(_) => "string",
+            Value::Table(_) => "table",
+            Value::Function(_) => "function",
+            Value::LightUserData(_) => "userdata",
+        }
+    }
+
+    // only nil and false are false in conditions
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    // the field `event` of the metatable, like "__close"
+    pub fn metamethod(&self, event: &str) -> Option<Value> {
+        let Value::Table(t) = self else {
+            return None;
+        };
+        let mt = t.borrow().metatable.clone()?;
+        let mm = mt.borrow().get(&event.into());
+        if let Value::Nil = mm { None } else { Some(mm) }
+    }
+}
+
+// ANCHOR: from
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.as_bytes().into())
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(s: &[u8]) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(s: Vec<u8>) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Integer(i)
+    }
+}
+
+impl From<Table> for Value {
+    fn from(t: Table) -> Self {
+        Value::Table(Arc::new(RefLock::new(t)))
+    }
+}
+// ANCHOR_END: from
+
+// ANCHOR: eq
+// As Lua's raw equality: integers and floats with the same value are
+// equal, tables and functions only to themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Integer(i), Value::Float(f)) | (Value::Float(f), Value::Integer(i)) => *i as f64 == *f,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Table(a), Value::Table(b)) => Arc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => std::ptr::fn_addr_eq(*a, *b),
+            (Value::LightUserData(a), Value::LightUserData(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Value::Nil => (),
+            Value::Boolean(b) => b.hash(state),
+            Value::Integer(i) => i.hash(state),
+            // so that equal integers and floats have the same hash
+            Value::Float(f) => match float_to_int(*f) {
+                Some(i) => i.hash(state),
+                None => f.to_bits().hash(state),
+            },
+            Value::String(s) => s.hash(state),
+            Value::Table(t) => Arc::as_ptr(t).hash(state),
+            Value::Function(f) => (*f as usize).hash(state),
+            Value::LightUserData(p) => p.hash(state),
+        }
+    }
+}
+
+fn float_to_int(f: f64) -> Option<i64> {
+    let i = f as i64;
+    if i as f64 == f { Some(i) } else { None }
+}
+// ANCHOR_END: eq
+
+// ANCHOR: table
+// Lua table, with an array part for keys 1..n and a hash part for the rest.
+#[derive(Debug, Default)]
+pub struct Table {
+    pub array: Vec<Value>,
+    pub map: HashMap<Value, Value>,
+    pub metatable: Option<Arc<RefLock<Table>>>,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &Value) -> Value {
+        if let Some(i) = self.array_index(key) {
+            return self.array[i].clone();
+        }
+        self.map.get(key).cloned().unwrap_or(Value::Nil)
+    }
+
+    pub fn set(&mut self, key: Value, value: Value) {
+        match key {
+            Value::Nil => panic!("table index is nil"),
+            Value::Float(f) if f.is_nan() => panic!("table index is NaN"),
+            _ => (),
+        }
+        if let Some(i) = self.array_index(&key) {
+            self.array[i] = value;
+            while let Some(Value::Nil) = self.array.last() {
+                self.array.pop();
+            }
+        } else if self.is_next_index(&key) {
+            if let Value::Nil = value {
+                return;
+            }
+            self.array.push(value);
+            // move the following keys from the hash part
+            loop {
+                let next = Value::Integer(self.array.len() as i64 + 1);
+                match self.map.remove(&next) {
+                    Some(v) => self.array.push(v),
+                    None => break,
+                }
+            }
+        } else if let Value::Nil = value {
+            self.map.remove(&key);
+        } else {
+            self.map.insert(key, value);
+        }
+    }
+
+    // the border, as Lua's length operator `#`
+    pub fn len(&self) -> usize {
+        self.array.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.array.is_empty() && self.map.is_empty()
+    }
+
+    fn array_index(&self, key: &Value) -> Option<usize> {
+        let i = match *key {
+            Value::Integer(i) => i,
+            Value::Float(f) => float_to_int(f)?,
+            _ => return None,
+        };
+        if i >= 1 && (i as usize) <= self.array.len() { Some(i as usize - 1) } else { None }
+    }
+
+    fn is_next_index(&self, key: &Value) -> bool {
+        let next = self.array.len() as i64 + 1;
+        match *key {
+            Value::Integer(i) => i == next,
+            Value::Float(f) => float_to_int(f) == Some(next),
+            _ => false,
+        }
+    }
+}
+// ANCHOR_END: table
+
+// ANCHOR: reflock
+// Shared and mutable like `RefCell`, but with a lock so that values, and
+// the state holding them, can move to another thread. Lua errors are
+// panics, so a lock held when one happens is not poisoned for the code
+// that catches it.
+#[derive(Debug, Default)]
+pub struct RefLock<T>(RwLock<T>);
+
+impl<T> RefLock<T> {
+    pub fn new(v: T) -> Self {
+        RefLock(RwLock::new(v))
+    }
+
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, T> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+// ANCHOR_END: reflock
@@ -1,76 +1,517 @@
-use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
 use crate::bytecode::ByteCode;
-use crate::value::Value;
-use crate::parse::ParseProto;
+use crate::debug::{self, CallInfo, Hook, HookEvent, HookMask};
+use crate::json;
+use crate::string;
+use crate::utf8;
+use crate::value::{Table, Value};
+use crate::parse::{LocVar, ParseProto};
 
 // ANCHOR: print
 // "print" function in Lua's std-lib.
-// It supports only 1 argument and assumes the argument is at index:1 on stack.
+// The arguments are on the stack after the function, separated by tabs in output.
+// Strings are written as they are, which may be not UTF-8.
 fn lib_print(state: &mut ExeState) -> i32 {
-    println!("{:?}", state.stack[1]);
+    for (i, v) in state.stack[state.base + 1..].iter().enumerate() {
+        if i > 0 {
+            state.stdout.write_all(b"\t").unwrap();
+        }
+        match v {
+            Value::String(s) => state.stdout.write_all(s).unwrap(),
+            v => write!(state.stdout, "{v:?}").unwrap(),
+        }
+    }
+    state.stdout.write_all(b"\n").unwrap();
     0
 }
 // ANCHOR_END: print
 
+// assert(v [, message]): error if v is false or nil, else all arguments
+fn lib_assert(state: &mut ExeState) -> i32 {
+    if state.arg_count() == 0 {
+        panic!("bad argument #1 to 'assert' (value expected)");
+    }
+    if !state.arg(1).is_truthy() {
+        match state.arg(2) {
+            Value::Nil => panic!("assertion failed!"),
+            Value::String(s) => panic!("{}", String::from_utf8_lossy(s)),
+            v => panic!("{v:?}"),
+        }
+    }
+    state.arg_count() as i32
+}
+
+fn global_key(name: &Value) -> Cow<'_, str> {
+    match name {
+        Value::String(key) => String::from_utf8_lossy(key),
+        _ => panic!("invalid global key: {name:?}"),
+    }
+}
+
 // ANCHOR: state
 pub struct ExeState {
     globals: HashMap<String, Value>,
     stack: Vec::<Value>,
+    base: usize, // of the running Rust function, where the function is
+    tbc: Vec<usize>, // to-be-closed registers, innermost last
+    frames: Vec::<CallInfo>,
+    lua_frames: Vec<LuaFrame>, // of the running chunks, for their locals
+    hook: Option<Hook>,
+    hook_mask: HookMask,
+    hook_count: u32, // instructions left before the next count event
+    stdout: Box<dyn Write + Send>,
 }
+
+// Where the locals of a running chunk are.
+struct LuaFrame {
+    level: usize, // the index in `frames`
+    base: usize,
+    pc: usize, // of the current byte code
+    locvars: Arc<[LocVar]>,
+}
+
+// Each state can be owned by a different thread, as in a worker pool.
+// Values are not shared between states: see `channel` to pass them.
+const _: () = {
+    const fn is_send<T: Send>() {}
+    is_send::<ExeState>()
+};
 // ANCHOR_END: state
 
 // ANCHOR: new
+impl Default for ExeState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ExeState {
     pub fn new() -> Self {
         let mut globals = HashMap::new();
         globals.insert(String::from("print"), Value::Function(lib_print));
+        globals.insert(String::from("assert"), Value::Function(lib_assert));
+        globals.insert(String::from("json"), json::lib().into());
+        globals.insert(String::from("string"), string::lib().into());
+        globals.insert(String::from("utf8"), utf8::lib().into());
+        globals.insert(String::from("debug"), debug::lib().into());
 
         ExeState {
             globals,
             stack: Vec::new(),
+            base: 0,
+            tbc: Vec::new(),
+            frames: Vec::new(),
+            lua_frames: Vec::new(),
+            hook: None,
+            hook_mask: HookMask::default(),
+            hook_count: 0,
+            stdout: Box::new(io::stdout()),
         }
     }
 // ANCHOR_END: new
 
+    // where "print" writes to, stdout by default
+    pub fn set_stdout(&mut self, stdout: Box<dyn Write + Send>) {
+        self.stdout = stdout;
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        self.globals.get(name)
+    }
+
+    pub fn set_global(&mut self, name: &str, v: Value) {
+        self.globals.insert(name.to_string(), v);
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.globals.iter()
+    }
+
 // ANCHOR: execute
-    pub fn execute(&mut self, proto: &ParseProto) {
-        for code in proto.byte_codes.iter() {
-            match *code {
+    // Run a chunk, returning the values of its `return`, if any.
+    pub fn execute(&mut self, proto: &ParseProto) -> Vec<Value> {
+        self.frames.push(CallInfo::main(proto));
+
+        // The registers of the chunk, from `base`, are allocated once.
+        let base = self.stack.len();
+        self.stack.resize(base + proto.max_stack, Value::Nil);
+        self.lua_frames.push(LuaFrame {
+            level: self.frames.len() - 1,
+            base,
+            pc: 0,
+            locvars: proto.locvars.clone(),
+        });
+        self.call_hook(HookEvent::Call);
+
+        // On errors, close the pending to-be-closed variables with the
+        // error message, and then go on with the error.
+        let (nframes, rust_base) = (self.frames.len(), self.base);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.run(proto, base)));
+        let results = result.unwrap_or_else(|err| {
+            self.frames.truncate(nframes);
+            self.lua_frames.retain(|f| f.level < nframes);
+            self.base = rust_base;
+            let msg = match (err.downcast_ref::<String>(), err.downcast_ref::<&str>()) {
+                (Some(s), _) => Value::from(s.as_str()),
+                (_, Some(s)) => Value::from(*s),
+                _ => Value::Nil,
+            };
+            self.close(base, msg);
+            self.stack.truncate(base);
+            panic::resume_unwind(err);
+        });
+        self.close(base, Value::Nil);
+        self.stack.truncate(base);
+
+        self.call_hook(HookEvent::Return);
+        self.lua_frames.pop();
+        self.frames.pop();
+        results
+    }
+
+    // Registers are `base + r` and the stack is always `top` long,
+    // except during calls.
+    fn run(&mut self, proto: &ParseProto, base: usize) -> Vec<Value> {
+        let top = base + proto.max_stack;
+        let mut pc = 0;
+        let mut last_pc = 0;
+        while pc < proto.byte_codes.len() {
+            if pc < last_pc {
+                // a line event again after jumping back, even on the same line
+                self.frames.last_mut().unwrap().currentline = None;
+            }
+            last_pc = pc;
+            self.lua_frames.last_mut().unwrap().pc = pc;
+            self.trace_exec(proto.lines[pc]);
+
+            match proto.byte_codes[pc] {
                 ByteCode::GetGlobal(dst, name) => {
-                    let name = &proto.constants[name as usize];
-                    if let Value::String(key) = name {
-                        let v = self.globals.get(key).unwrap_or(&Value::Nil).clone();
-                        self.set_stack(dst, v);
-                    } else {
-                        panic!("invalid global key: {name:?}");
+                    let key = global_key(&proto.constants[name as usize]);
+                    let v = self.globals.get(key.as_ref()).cloned().unwrap_or(Value::Nil);
+                    self.stack[base + dst as usize] = v;
+                }
+                ByteCode::SetGlobal(name, src) => {
+                    let key = global_key(&proto.constants[name as usize]);
+                    let v = self.stack[base + src as usize].clone();
+                    match self.globals.get_mut(key.as_ref()) {
+                        Some(old) => *old = v,
+                        None => {
+                            self.globals.insert(key.into_owned(), v);
+                        }
                     }
                 }
                 ByteCode::LoadConst(dst, c) => {
-                    let v = proto.constants[c as usize].clone();
-                    self.set_stack(dst, v);
+                    self.stack[base + dst as usize] = proto.constants[c as usize].clone();
                 }
-                ByteCode::Call(func, _) => {
-                    let func = &self.stack[func as usize];
-                    if let Value::Function(f) = func {
-                        f(self);
-                    } else {
-                        panic!("invalid function: {func:?}");
+                ByteCode::LoadNil(dst) => self.stack[base + dst as usize] = Value::Nil,
+                ByteCode::LoadBool(dst, b) => self.stack[base + dst as usize] = Value::Boolean(b),
+                ByteCode::LoadInt(dst, i) => self.stack[base + dst as usize] = Value::Integer(i as i64),
+                ByteCode::Move(dst, src) => {
+                    self.stack[base + dst as usize] = self.stack[base + src as usize].clone();
+                }
+                ByteCode::Call(func, narg, want) => {
+                    // the registers above the arguments are free during the call
+                    let func = base + func as usize;
+                    self.stack.truncate(func + 1 + narg as usize);
+                    let n = self.call_at(func, proto.call_names[pc].clone());
+
+                    // move the results to where the function was
+                    let first = self.stack.len() - n;
+                    for i in 0..want as usize {
+                        self.stack[func + i] = if i < n {
+                            std::mem::replace(&mut self.stack[first + i], Value::Nil)
+                        } else {
+                            Value::Nil
+                        };
                     }
+                    self.stack.resize(top, Value::Nil);
+                }
+                // from the next byte code, which may be the first one
+                ByteCode::Jump(jmp) => {
+                    pc = (pc as isize + 1 + jmp as isize) as usize;
+                    continue;
+                }
+                ByteCode::Test(src, jmp) => {
+                    if !self.stack[base + src as usize].is_truthy() {
+                        pc = (pc as isize + 1 + jmp as isize) as usize;
+                        continue;
+                    }
+                }
+                ByteCode::Tbc(reg, name) => {
+                    let reg = base + reg as usize;
+                    match &self.stack[reg] {
+                        Value::Nil | Value::Boolean(false) => (), // nothing to close
+                        v if v.metamethod("__close").is_some() => self.tbc.push(reg),
+                        _ => panic!("variable '{:?}' got a non-closable value", proto.constants[name as usize]),
+                    }
+                }
+                ByteCode::Close(reg) => self.close(base + reg as usize, Value::Nil),
+                ByteCode::Return(first, n) => {
+                    let first = base + first as usize;
+                    return self.stack[first..first + n as usize].to_vec();
+                }
+
+                ByteCode::NewTable(dst) => self.stack[base + dst as usize] = Table::new().into(),
+                ByteCode::GetField(dst, t, key) => {
+                    let v = self.index(&self.stack[base + t as usize], &proto.constants[key as usize]);
+                    self.stack[base + dst as usize] = v;
+                }
+                ByteCode::GetTable(dst, t, key) => {
+                    let v = self.index(&self.stack[base + t as usize], &self.stack[base + key as usize]);
+                    self.stack[base + dst as usize] = v;
+                }
+                ByteCode::SetField(t, key, src) => {
+                    let key = proto.constants[key as usize].clone();
+                    self.set_index(base + t as usize, key, base + src as usize);
+                }
+                ByteCode::SetTable(t, key, src) => {
+                    let key = self.stack[base + key as usize].clone();
+                    self.set_index(base + t as usize, key, base + src as usize);
                 }
             }
+            pc += 1;
+        }
+        Vec::new()
+    }
+
+    // `t[key]`, without metamethods yet
+    fn index(&self, t: &Value, key: &Value) -> Value {
+        match t {
+            Value::Table(t) => t.borrow().get(key),
+            v => panic!("attempt to index a {} value", v.type_name()),
+        }
+    }
+
+    // `t[key] = v`, with the table and value on the stack
+    fn set_index(&mut self, t: usize, key: Value, src: usize) {
+        let v = self.stack[src].clone();
+        match &self.stack[t] {
+            Value::Table(t) => t.borrow_mut().set(key, v),
+            v => panic!("attempt to index a {} value", v.type_name()),
         }
     }
 // ANCHOR_END: execute
 
-// ANCHOR: set_stack
-    fn set_stack(&mut self, dst: u8, v: Value) {
-        let dst = dst as usize;
-        match dst.cmp(&self.stack.len()) {
-            Ordering::Equal => self.stack.push(v),
-            Ordering::Less => self.stack[dst] = v,
-            Ordering::Greater => panic!("fail in set_stack"),
+// ANCHOR: close
+    // Call `__close(v, err)` for the to-be-closed variables from stack
+    // index `level` up, in the reverse order of their declaration.
+    fn close(&mut self, level: usize, err: Value) {
+        while let Some(&reg) = self.tbc.last() {
+            if reg < level {
+                break;
+            }
+            self.tbc.pop();
+            let v = self.stack[reg].clone();
+            let mm = v.metamethod("__close").unwrap_or(Value::Nil);
+            self.call_function(mm, vec![v, err.clone()], Some(Arc::from("__close")));
+        }
+    }
+// ANCHOR_END: close
+
+// ANCHOR: call
+    // Call a function from Rust and return its results.
+    pub fn call(&mut self, func: Value, args: Vec<Value>) -> Vec<Value> {
+        self.call_function(func, args, None)
+    }
+
+    fn call_function(&mut self, func: Value, args: Vec<Value>, name: Option<Arc<str>>) -> Vec<Value> {
+        let func_index = self.stack.len();
+        self.stack.push(func);
+        self.stack.extend(args);
+        let n = self.call_at(func_index, name);
+        let results = self.stack.split_off(self.stack.len() - n);
+        self.stack.truncate(func_index);
+        results
+    }
+
+    // Call the function at `func`, with the arguments after it up to the
+    // top of the stack, where it sees them from `base`. The results are
+    // left on the top, and their number is returned.
+    fn call_at(&mut self, func: usize, name: Option<Arc<str>>) -> usize {
+        let f = match self.stack[func] {
+            Value::Function(f) => f,
+            ref v => panic!("attempt to call a {} value", v.type_name()),
+        };
+        let saved_base = std::mem::replace(&mut self.base, func);
+
+        self.frames.push(CallInfo::rust(name));
+        self.call_hook(HookEvent::Call);
+        let n = f(self) as usize;
+        self.call_hook(HookEvent::Return);
+        self.frames.pop();
+
+        self.base = saved_base;
+        n
+    }
+// ANCHOR_END: call
+
+// ANCHOR: args
+    // For Rust functions: arguments are on the stack after the function,
+    // and results are pushed, returning how many.
+    pub fn arg_count(&self) -> usize {
+        self.stack.len() - self.base - 1
+    }
+
+    // the i-th argument, from 1, or nil if absent
+    pub fn arg(&self, i: usize) -> &Value {
+        self.stack.get(self.base + i).unwrap_or(&Value::Nil)
+    }
+
+    pub fn push(&mut self, v: Value) {
+        self.stack.push(v);
+    }
+
+    pub fn arg_check(&self, cond: bool, i: usize, fname: &str, msg: &str) {
+        if !cond {
+            panic!("bad argument #{i} to '{fname}' ({msg})");
+        }
+    }
+
+    fn arg_error(&self, i: usize, fname: &str, expected: &str) -> ! {
+        let got = match self.arg(i) {
+            Value::Nil if i > self.arg_count() => "no value",
+            v => v.type_name(),
+        };
+        panic!("bad argument #{i} to '{fname}' ({expected} expected, got {got})");
+    }
+
+    pub fn check_string(&self, i: usize, fname: &str) -> &[u8] {
+        match self.arg(i) {
+            Value::String(s) => s,
+            _ => self.arg_error(i, fname, "string"),
+        }
+    }
+
+    pub fn check_integer(&self, i: usize, fname: &str) -> i64 {
+        match *self.arg(i) {
+            Value::Integer(n) => n,
+            Value::Float(f) if f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 => f as i64,
+            Value::Float(_) => panic!("bad argument #{i} to '{fname}' (number has no integer representation)"),
+            _ => self.arg_error(i, fname, "number"),
+        }
+    }
+
+    pub fn check_number(&self, i: usize, fname: &str) -> f64 {
+        match *self.arg(i) {
+            Value::Integer(n) => n as f64,
+            Value::Float(f) => f,
+            _ => self.arg_error(i, fname, "number"),
+        }
+    }
+
+    pub fn check_function(&self, i: usize, fname: &str) -> fn(&mut ExeState) -> i32 {
+        match *self.arg(i) {
+            Value::Function(f) => f,
+            _ => self.arg_error(i, fname, "function"),
+        }
+    }
+
+    pub fn opt_integer(&self, i: usize, fname: &str, default: i64) -> i64 {
+        match self.arg(i) {
+            Value::Nil => default,
+            _ => self.check_integer(i, fname),
         }
     }
-// ANCHOR_END: set_stack
+// ANCHOR_END: args
+
+// ANCHOR: hook
+    // "debug.sethook". `mask` is any combination of 'c', 'r' and 'l',
+    // and a non-zero `count` calls the hook every `count` instructions.
+    pub fn sethook(&mut self, hook: Hook, mask: &str, count: u32) {
+        self.hook_mask = HookMask::new(mask, count);
+        self.hook_count = count;
+        self.hook = Some(hook);
+    }
+
+    // "debug.sethook" without arguments, which turns off the hook.
+    pub fn clear_hook(&mut self) {
+        self.hook = None;
+        self.hook_mask = HookMask::default();
+    }
+
+    // "debug.gethook": the mask and count of the current hook.
+    pub fn gethook(&self) -> Option<(String, u32)> {
+        self.hook.as_ref().map(|_| (self.hook_mask.mask(), self.hook_mask.count))
+    }
+
+    fn call_hook(&mut self, event: HookEvent) {
+        let enabled = match event {
+            HookEvent::Call => self.hook_mask.call,
+            HookEvent::Return => self.hook_mask.ret,
+            HookEvent::Line(_) => self.hook_mask.line,
+            HookEvent::Count => self.hook_mask.count > 0,
+        };
+        if !enabled {
+            return;
+        }
+        // the hook is taken out while running, so it is not called recursively
+        if let Some(mut hook) = self.hook.take() {
+            hook(self, event);
+            if self.hook.is_none() && self.hook_mask != HookMask::default() {
+                self.hook = Some(hook);
+            }
+        }
+    }
+
+    // called before each instruction of a Lua function
+    fn trace_exec(&mut self, line: u32) {
+        let ci = self.frames.last_mut().unwrap();
+        let newline = ci.currentline != Some(line);
+        ci.currentline = Some(line);
+
+        if self.hook_mask.count > 0 {
+            self.hook_count -= 1;
+            if self.hook_count == 0 {
+                self.hook_count = self.hook_mask.count;
+                self.call_hook(HookEvent::Count);
+            }
+        }
+        if newline {
+            self.call_hook(HookEvent::Line(line));
+        }
+    }
+// ANCHOR_END: hook
+
+// ANCHOR: getinfo
+    // "debug.getinfo": level 0 is the running function, 1 is its caller...
+    pub fn getinfo(&self, level: usize) -> Option<&CallInfo> {
+        self.frames.iter().rev().nth(level)
+    }
+
+    pub fn frames(&self) -> &[CallInfo] {
+        &self.frames
+    }
+
+    // "debug.getlocal": the name and value of the n-th local, from 1, in
+    // scope in the function at `level`. Rust functions have none.
+    pub fn getlocal(&self, level: usize, n: usize) -> Option<(&str, &Value)> {
+        let (name, reg) = self.local_register(level, n)?;
+        Some((name, &self.stack[reg]))
+    }
+
+    // "debug.setlocal": assign the n-th local at `level`, and return its name.
+    pub fn setlocal(&mut self, level: usize, n: usize, v: Value) -> Option<String> {
+        let (name, reg) = self.local_register(level, n)?;
+        let name = name.to_string();
+        self.stack[reg] = v;
+        Some(name)
+    }
+
+    fn local_register(&self, level: usize, n: usize) -> Option<(&str, usize)> {
+        let index = self.frames.len().checked_sub(level + 1)?;
+        let frame = self.lua_frames.iter().rev().find(|f| f.level == index)?;
+        let i = n.checked_sub(1)?;
+        let var = frame.locvars.iter().filter(|v| v.start <= frame.pc && frame.pc < v.end).nth(i)?;
+        Some((&var.name, frame.base + i))
+    }
+
+    // "debug.traceback"
+    pub fn traceback(&self, msg: Option<&str>) -> String {
+        debug::traceback(self, msg)
+    }
+// ANCHOR_END: getinfo
 }
@@ -0,0 +1,7 @@
+-- UTF-8 source and escapes
+print "héllo, wörld! 你好"
+print("\u{48}\u{E9}\x6C\108o", 'tab\there', "quote \" and \\")
+print "line one\
+line two"
+print "skip \z
+       spaces"
@@ -0,0 +1,164 @@
+use std::io::{Cursor, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use lua_rs::parse;
+use lua_rs::value::{RefLock, Table, Value};
+use lua_rs::vm::ExeState;
+
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// resource(name): a value whose __close prints "close <name>" and the
+// error, if any
+fn resource(state: &mut ExeState) -> i32 {
+    let mut mt = Table::new();
+    mt.set("__close".into(), Value::Function(close_resource));
+    let mut t = Table::new();
+    t.set("name".into(), state.arg(1).clone());
+    t.metatable = Some(Arc::new(RefLock::new(mt)));
+    state.push(t.into());
+    1
+}
+
+fn close_resource(state: &mut ExeState) -> i32 {
+    let Value::Table(t) = state.arg(1) else {
+        panic!("not a resource");
+    };
+    let mut args = vec![Value::from("close"), t.borrow().get(&"name".into())];
+    if let Value::String(_) = state.arg(2) {
+        args.push(state.arg(2).clone());
+    }
+    let print = state.get_global("print").cloned().unwrap();
+    state.call(print, args);
+    0
+}
+
+fn fail(_: &mut ExeState) -> i32 {
+    panic!("failed");
+}
+
+fn state() -> (ExeState, Output) {
+    let out = Output::default();
+    let mut state = ExeState::new();
+    state.set_stdout(Box::new(out.clone()));
+    state.set_global("resource", Value::Function(resource));
+    state.set_global("fail", Value::Function(fail));
+    (state, out)
+}
+
+fn run(src: &str) -> String {
+    let (mut state, out) = state();
+    state.execute(&parse::load(Cursor::new(src), "test"));
+    let s = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+    s
+}
+
+#[test]
+fn test_locals() {
+    let src = r#"
+        local a, b = "a", "b"
+        a, b = b, a
+        print(a, b)
+        local c <const> = 1
+        do
+            local a = "inner"
+            print(a, c)
+        end
+        print(a)
+        g = a
+        print(g)
+    "#;
+    assert_eq!(run(src), "b\ta\ninner\t1\nb\nb\n");
+}
+
+#[test]
+fn test_close_at_block_end() {
+    let src = r#"
+        local x <close> = resource "outer"
+        do
+            local a <close> = resource "a"
+            local b <close> = resource "b"
+            local n <close> = nil
+            print "in block"
+        end
+        print "after block"
+    "#;
+    assert_eq!(run(src), "in block\nclose\tb\nclose\ta\nafter block\nclose\touter\n");
+}
+
+#[test]
+fn test_close_on_break() {
+    let src = r#"
+        while true do
+            local r <close> = resource "loop"
+            do
+                local inner <close> = resource "inner"
+                break
+            end
+            print "not reached"
+        end
+        print "done"
+    "#;
+    assert_eq!(run(src), "close\tinner\nclose\tloop\ndone\n");
+}
+
+#[test]
+fn test_close_on_error() {
+    let src = r#"
+        local a <close> = resource "a"
+        do
+            local b <close> = resource "b"
+            fail()
+        end
+    "#;
+    let (mut state, out) = state();
+    let proto = parse::load(Cursor::new(src), "test");
+    let result = panic::catch_unwind(AssertUnwindSafe(|| state.execute(&proto)));
+    assert!(result.is_err());
+    assert_eq!(String::from_utf8(out.0.lock().unwrap().clone()).unwrap(), "close\tb\tfailed\nclose\ta\tfailed\n");
+}
+
+#[test]
+#[should_panic(expected = "variable 'x' got a non-closable value")]
+fn test_non_closable() {
+    run(r#"local x <close> = "file""#);
+}
+
+#[test]
+#[should_panic(expected = "attempt to assign to const variable 'x'")]
+fn test_assign_const() {
+    parse::load(Cursor::new("local x <const> = 1\nx = 2"), "test");
+}
+
+#[test]
+#[should_panic(expected = "attempt to assign to const variable 'f'")]
+fn test_assign_close() {
+    parse::load(Cursor::new("local f <close> = nil\ndo f = 2 end"), "test");
+}
+
+#[test]
+#[should_panic(expected = "unknown attribute 'static'")]
+fn test_unknown_attribute() {
+    parse::load(Cursor::new("local x <static> = 1"), "test");
+}
+
+#[test]
+#[should_panic(expected = "multiple to-be-closed variables in local list")]
+fn test_multiple_close() {
+    parse::load(Cursor::new("local a <close>, b <close>"), "test");
+}
+
+#[test]
+#[should_panic(expected = "break outside a loop at line 2")]
+fn test_break_outside_loop() {
+    parse::load(Cursor::new("print 'x'\nbreak"), "test");
+}
@@ -0,0 +1,83 @@
+// Replay recorded DAP sessions against the `lua-dap` binary.
+use std::fs;
+use std::io::{BufReader, Write};
+use std::process::{Command, Stdio};
+use serde_json::Value;
+use lua_rs::dap::{read_message, write_message};
+
+fn replay(name: &str) {
+    let dir = env!("CARGO_MANIFEST_DIR");
+    let requests = fs::read_to_string(format!("{dir}/tests/dap/{name}.in")).unwrap();
+    let expected = fs::read_to_string(format!("{dir}/tests/dap/{name}.out")).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lua-dap"))
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // send the requests one by one like a client does, waiting for the
+    // response, and for the next stop when execution is resumed
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut messages = Vec::new();
+    for line in requests.lines() {
+        let req: Value = serde_json::from_str(line).unwrap();
+        write_message(&mut stdin, &req).unwrap();
+        stdin.flush().unwrap();
+
+        let resumes = matches!(req["command"].as_str(),
+            Some("configurationDone" | "continue" | "next" | "stepIn" | "stepOut"));
+        let mut responded = false;
+        let mut stopped = !resumes;
+        while !(responded && stopped) {
+            let msg = read_message(&mut stdout).unwrap().unwrap();
+            responded |= msg["request_seq"] == req["seq"];
+            stopped |= matches!(msg["event"].as_str(), Some("stopped" | "terminated"));
+            messages.push(msg);
+        }
+    }
+    drop(stdin);
+    while let Some(msg) = read_message(&mut stdout).unwrap() {
+        messages.push(msg);
+    }
+    assert!(child.wait().unwrap().success());
+
+    let expected: Vec<Value> = expected.lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    // run with DAP_RECORD=1 and --nocapture to print a new recording
+    if std::env::var("DAP_RECORD").is_ok() {
+        for msg in &messages {
+            println!("{msg}");
+        }
+    }
+    assert_eq!(messages, expected);
+}
+
+#[test]
+fn test_breakpoint() {
+    replay("breakpoint");
+}
+
+#[test]
+fn test_step() {
+    replay("step");
+}
+
+#[test]
+fn test_locals() {
+    replay("locals");
+}
+
+#[test]
+fn test_syntax_error() {
+    replay("syntax_error");
+}
+
+#[test]
+fn test_runtime_error() {
+    replay("runtime_error");
+}
@@ -0,0 +1,7 @@
+local greeting = "hello"
+local t = {1, 2, x = "y"}
+do
+    local greeting = "inner"
+    print(greeting)
+end
+print(greeting)
@@ -0,0 +1,192 @@
+use std::io::{self, Cursor};
+use std::sync::{Arc, Mutex};
+use lua_rs::debug::HookEvent;
+use lua_rs::parse;
+use lua_rs::value::Value;
+use lua_rs::vm::ExeState;
+
+const SCRIPT: &str = "\
+local a = 1
+print(a)
+while a do
+  a = nil
+end
+trace = debug.traceback(\"here\")
+";
+
+fn run(src: &str, state: &mut ExeState) {
+    let proto = parse::load(Cursor::new(src), "script.lua");
+    state.set_stdout(Box::new(io::sink()));
+    state.execute(&proto);
+}
+
+#[test]
+fn test_hook_events() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut state = ExeState::new();
+    let log = events.clone();
+    state.sethook(Box::new(move |state, event| {
+        let name = state.getinfo(0).unwrap().name.as_deref().unwrap_or("main").to_string();
+        log.lock().unwrap().push((event, name));
+    }), "crl", 0);
+    run(SCRIPT, &mut state);
+
+    let events = events.lock().unwrap();
+    let main = |event| (event, String::from("main"));
+    let print = |event| (event, String::from("print"));
+    let traceback = |event| (event, String::from("traceback"));
+    assert_eq!(*events, vec![
+        main(HookEvent::Call),
+        main(HookEvent::Line(1)),
+        main(HookEvent::Line(2)),
+        print(HookEvent::Call),
+        print(HookEvent::Return),
+        main(HookEvent::Line(3)),
+        main(HookEvent::Line(4)),
+        main(HookEvent::Line(5)), // the jump back
+        main(HookEvent::Line(3)), // again after it
+        main(HookEvent::Line(6)),
+        traceback(HookEvent::Call),
+        traceback(HookEvent::Return),
+        main(HookEvent::Return),
+    ]);
+}
+
+#[test]
+fn test_count_hook() {
+    let count = Arc::new(Mutex::new(0));
+    let mut state = ExeState::new();
+    let n = count.clone();
+    state.sethook(Box::new(move |_, event| {
+        assert_eq!(event, HookEvent::Count);
+        *n.lock().unwrap() += 1;
+    }), "", 2);
+    run("local a = 1\nlocal b = 2\nlocal c = 3\nlocal d = 4\n", &mut state);
+    assert_eq!(*count.lock().unwrap(), 2);
+}
+
+#[test]
+fn test_traceback() {
+    let mut state = ExeState::new();
+    run(SCRIPT, &mut state);
+    assert_eq!(state.get_global("trace"), Some(&Value::from("here\nstack traceback:\n\tscript.lua:6: in main chunk")));
+
+    // from a hook, in a call of a Rust function
+    let trace = Arc::new(Mutex::new(String::new()));
+    let mut state = ExeState::new();
+    let t = trace.clone();
+    state.sethook(Box::new(move |state, _| {
+        *t.lock().unwrap() = state.traceback(Some("in print"));
+        state.clear_hook();
+    }), "c", 0);
+    run("\nprint 'x'\n", &mut state);
+    // the first call is of the main chunk
+    assert_eq!(*trace.lock().unwrap(), "in print\nstack traceback:\n\tscript.lua: in main chunk");
+}
+
+#[test]
+fn test_traceback_in_call() {
+    let trace = Arc::new(Mutex::new(String::new()));
+    let mut state = ExeState::new();
+    let t = trace.clone();
+    state.sethook(Box::new(move |state, event| {
+        if event == HookEvent::Call && state.getinfo(0).unwrap().what == "Rust" {
+            *t.lock().unwrap() = state.traceback(None);
+        }
+    }), "c", 0);
+    run("local t = {}\n\nstring.packsize(\"i4\")\n", &mut state);
+    assert_eq!(*trace.lock().unwrap(), "stack traceback:\n\t[Rust]: in function 'packsize'\n\tscript.lua:3: in main chunk");
+}
+
+#[test]
+fn test_locals() {
+    let src = "\
+local a, b = 1, 2
+do local c = 3 end
+local d <const> = 4
+names = {debug.getlocal(1, 1), debug.getlocal(1, 3), debug.getlocal(1, 4)}
+debug.setlocal(1, 2, \"set\")
+b_is = b
+info = debug.getinfo(1)
+";
+    let mut state = ExeState::new();
+    run(src, &mut state);
+    let names = state.get_global("names").unwrap().clone();
+    let Value::Table(names) = names else { panic!("not a table") };
+    let names = names.borrow();
+    assert_eq!(names.get(&Value::Integer(1)), Value::from("a"));
+    assert_eq!(names.get(&Value::Integer(2)), Value::from("d"));
+    assert_eq!(names.get(&Value::Integer(3)), Value::Nil); // `c` is out of scope
+    assert_eq!(state.get_global("b_is"), Some(&Value::from("set")));
+
+    let Some(Value::Table(info)) = state.get_global("info") else { panic!("not a table") };
+    let info = info.borrow();
+    assert_eq!(info.get(&"what".into()), Value::from("main"));
+    assert_eq!(info.get(&"source".into()), Value::from("script.lua"));
+    assert_eq!(info.get(&"currentline".into()), Value::Integer(7));
+
+    // from Rust, during a hook
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut state = ExeState::new();
+    let s = seen.clone();
+    state.sethook(Box::new(move |state, event| {
+        if event == HookEvent::Line(3) {
+            let locals: Vec<_> = (1..).map_while(|n| state.getlocal(0, n))
+                .map(|(name, v)| (name.to_string(), v.clone()))
+                .collect();
+            *s.lock().unwrap() = locals;
+            assert_eq!(state.setlocal(0, 1, Value::Integer(10)), Some(String::from("x")));
+            assert_eq!(state.setlocal(0, 3, Value::Nil), None);
+        }
+    }), "l", 0);
+    run("local x = 1\nlocal y = 'y'\nresult = x\n", &mut state);
+    assert_eq!(*seen.lock().unwrap(), vec![
+        (String::from("x"), Value::Integer(1)),
+        (String::from("y"), Value::from("y")),
+    ]);
+    assert_eq!(state.get_global("result"), Some(&Value::Integer(10)));
+}
+
+// the output of `print`
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_sethook_from_lua() {
+    let src = "\
+debug.sethook(print, \"l\")
+local a = 1
+debug.sethook()
+local b = 2
+off = {debug.gethook()}
+debug.sethook(assert, \"cr\", 10)
+mask = debug.gethook() -- a call gives one value, so just the mask
+";
+    let output = Output::default();
+    let mut state = ExeState::new();
+    state.set_stdout(Box::new(output.clone()));
+    state.execute(&parse::load(Cursor::new(src), "script.lua"));
+
+    assert_eq!(String::from_utf8_lossy(&output.0.lock().unwrap()), "line\t2\nline\t3\n");
+    let Some(Value::Table(off)) = state.get_global("off") else { panic!("not a table") };
+    assert!(off.borrow().is_empty());
+    assert_eq!(state.get_global("mask"), Some(&Value::from("cr")));
+    assert_eq!(state.gethook(), Some((String::from("cr"), 10)));
+}
+
+#[test]
+#[should_panic(expected = "bad argument #1 to 'getupvalue' (function expected, got number)")]
+fn test_getupvalue_checks_function() {
+    run("debug.getupvalue(1, 1)", &mut ExeState::new());
+}
@@ -0,0 +1,12 @@
+-- greetings for everyone
+print "hello, world!"   -- the classic
+
+print('hello', 'again')
+print(  )
+
+
+
+--[[ a long comment
+     spanning lines ]]
+print "it's" print "done"
+--[==[ another ]] one ]==]
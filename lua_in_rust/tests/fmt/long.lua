@@ -0,0 +1,3 @@
+print("a fairly long string which does not fit", "together with another long string")
+print "a single string that is much too long to fit into the configured line width"
+print("short", "call") -- fits
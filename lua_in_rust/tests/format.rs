@@ -0,0 +1,153 @@
+// Idempotency and round trips of the formatter over a corpus of scripts.
+use std::fs;
+use std::io::Cursor;
+use lua_rs::ast::{Block, Expr, Field, FuncCall, Stat};
+use lua_rs::format::{self, Options, Quote};
+use lua_rs::parse;
+
+fn corpus() -> Vec<(String, String)> {
+    let dir = env!("CARGO_MANIFEST_DIR");
+    let mut files = Vec::new();
+    for sub in ["test_lua", "tests/fmt", "tests/lua"] {
+        for entry in fs::read_dir(format!("{dir}/{sub}")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "lua") {
+                let src = fs::read_to_string(&path).unwrap();
+                // files of the test suite may need syntax the parser does
+                // not have yet, see EXPECTED_FAILURES in tests/conformance.rs
+                if sub == "tests/lua" && parse::parse_ast(Cursor::new(&src)).is_err() {
+                    continue;
+                }
+                files.push((path.display().to_string(), src));
+            }
+        }
+    }
+    assert!(!files.is_empty());
+    files
+}
+
+fn options() -> Vec<Options> {
+    vec![
+        Options::default(),
+        Options { indent: 2, quote: Quote::Single, line_width: 40 },
+        Options { indent: 0, quote: Quote::Double, line_width: 10 },
+    ]
+}
+
+fn parse(src: &str) -> Block {
+    parse::parse_ast(Cursor::new(src)).unwrap()
+}
+
+// The code without positions and comments, with `f "x"` and `f("x")`
+// the same, as the formatter may add parentheses.
+fn code(block: &Block) -> String {
+    let mut out = String::new();
+    for stat in &block.stats {
+        match stat {
+            Stat::Call(call) => out += &call_code(call),
+            Stat::Assign { targets, values, .. } => out += &format!("{} = {}", list(targets), list(values)),
+            Stat::Local { names, values, .. } => {
+                let names: Vec<_> = names.iter().map(|n| format!("{}{:?}", n.name, n.attrib)).collect();
+                out += &format!("local {} = {}", names.join(", "), list(values));
+            }
+            Stat::Do(body, _) => out += &format!("do {} end", code(body)),
+            Stat::While { cond, body, .. } => out += &format!("while {} do {} end", expr(cond), code(body)),
+            Stat::Break(_) => out += "break",
+            Stat::Return(values, _) => out += &format!("return {}", list(values)),
+        }
+        out.push(';');
+    }
+    out
+}
+
+fn call_code(call: &FuncCall) -> String {
+    format!("{}({})", expr(&call.func), list(&call.args))
+}
+
+fn list(exprs: &[Expr]) -> String {
+    exprs.iter().map(expr).collect::<Vec<_>>().join(", ")
+}
+
+fn expr(e: &Expr) -> String {
+    match e {
+        Expr::Nil(_) => String::from("nil"),
+        Expr::Boolean(b, _) => b.to_string(),
+        Expr::Integer(i, _) => format!("{i}"),
+        Expr::Float(f, _) => format!("{f:?}"),
+        Expr::String(s, _) => format!("{s:?}"),
+        Expr::Name(name, _) => name.clone(),
+        Expr::Field(prefix, name, _) => format!("{}.{name}", expr(prefix)),
+        Expr::Index(prefix, key, _) => format!("{}[{}]", expr(prefix), expr(key)),
+        Expr::Call(call) => call_code(call),
+        Expr::Table(fields, _) => {
+            let fields: Vec<_> = fields.iter()
+                .map(|f| match f {
+                    Field::Item(v) => expr(v),
+                    Field::Named(name, _, v) => format!("{name} = {}", expr(v)),
+                    Field::Keyed(k, v) => format!("[{}] = {}", expr(k), expr(v)),
+                })
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+    }
+}
+
+#[test]
+fn test_idempotent() {
+    for (path, src) in corpus() {
+        for opts in options() {
+            let once = format::format(&parse(&src), &opts);
+            let twice = format::format(&parse(&once), &opts);
+            assert_eq!(once, twice, "{path} with {opts:?}");
+        }
+    }
+}
+
+#[test]
+fn test_preserves_code_and_comments() {
+    for (path, src) in corpus() {
+        let block = parse(&src);
+        for opts in options() {
+            let formatted = parse(&format::format(&block, &opts));
+            assert_eq!(code(&block), code(&formatted), "{path} with {opts:?}");
+            let comments: Vec<_> = block.comments.iter().map(|c| c.text.trim_end()).collect();
+            let formatted_comments: Vec<_> = formatted.comments.iter().map(|c| c.text.as_str()).collect();
+            assert_eq!(comments, formatted_comments, "{path} with {opts:?}");
+        }
+    }
+}
+
+#[test]
+fn test_format() {
+    let src = "print  'a' print(\"b\", 'c') -- x\n\n\n--[[ y ]]\nprint()";
+    let out = format::format(&parse(src), &Options::default());
+    assert_eq!(out, "print \"a\"\nprint(\"b\", \"c\") -- x\n\n--[[ y ]]\nprint()\n");
+}
+
+#[test]
+fn test_format_statements() {
+    let src = "local a<const>,b=1,{2;x=3,['y']=4.0}\nwhile a do -- loop\nb.x[1]=nil break end\ndo return end";
+    let out = format::format(&parse(src), &Options::default());
+    assert_eq!(out, "local a <const>, b = 1, {2, x = 3, [\"y\"] = 4.0}\nwhile a do\n    -- loop\n    b.x[1] = nil\n    break\nend\ndo\n    return\nend\n");
+
+    let src = "t = {'a fairly long string', {nested = 'table'}}";
+    let opts = Options { indent: 2, quote: Quote::Single, line_width: 30 };
+    let out = format::format(&parse(src), &opts);
+    assert_eq!(out, "t = {\n  'a fairly long string',\n  {nested = 'table'},\n}\n");
+}
+
+#[test]
+fn test_parse_errors() {
+    for (src, message) in [
+        ("break", "break outside a loop"),
+        ("local a <close>, b <close>", "multiple to-be-closed variables in local list"),
+        ("local a <static> = 1", "unknown attribute 'static'"),
+        ("t.x", "expected `=` or arguments, found Eos"),
+        ("return 1 print 'x'", "unexpected token: Name(\"print\")"),
+        ("while x do", "expected `end`, found Eos"),
+        ("t = {1 2}", "expected `}`, found Integer(2)"),
+    ] {
+        let err = parse::parse_ast(Cursor::new(src)).unwrap_err();
+        assert_eq!(err.message, message, "{src}");
+    }
+}
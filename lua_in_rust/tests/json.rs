@@ -0,0 +1,155 @@
+use lua_rs::json;
+use lua_rs::value::{Table, Value};
+use lua_rs::vm::ExeState;
+
+fn call(name: &str, args: Vec<Value>) -> Vec<Value> {
+    let mut state = ExeState::new();
+    let Some(Value::Table(lib)) = state.get_global("json").cloned() else {
+        panic!("no json library");
+    };
+    let func = lib.borrow().get(&name.into());
+    state.call(func, args)
+}
+
+fn encode(v: Value, opts: &[(&str, Value)]) -> String {
+    let mut t = Table::new();
+    for (k, v) in opts {
+        t.set((*k).into(), v.clone());
+    }
+    match &call("encode", vec![v, t.into()])[..] {
+        [Value::String(s)] => String::from_utf8(s.to_vec()).unwrap(),
+        r => panic!("encode returned {r:?}"),
+    }
+}
+
+fn decode(s: &str) -> Value {
+    call("decode", vec![s.into()]).remove(0)
+}
+
+fn table(entries: Vec<(Value, Value)>) -> Value {
+    let mut t = Table::new();
+    for (k, v) in entries {
+        t.set(k, v);
+    }
+    t.into()
+}
+
+fn i(i: i64) -> Value {
+    Value::Integer(i)
+}
+
+fn sorted() -> [(&'static str, Value); 1] {
+    [("sort_keys", Value::Boolean(true))]
+}
+
+#[test]
+fn test_encode_values() {
+    assert_eq!(encode(Value::Nil, &[]), "null");
+    assert_eq!(encode(Value::Boolean(true), &[]), "true");
+    assert_eq!(encode(i(-3), &[]), "-3");
+    assert_eq!(encode(Value::Float(1.0), &[]), "1.0");
+    assert_eq!(encode(Value::Float(0.25), &[]), "0.25");
+    assert_eq!(encode("a \"q\" \\ é\n\x01".into(), &[]), r#""a \"q\" \\ é\n\u0001""#);
+    assert_eq!(encode(json::null(), &[]), "null");
+}
+
+#[test]
+fn test_encode_tables() {
+    let array = table(vec![(i(1), i(10)), (i(2), "x".into()), (i(3), json::null())]);
+    assert_eq!(encode(array, &[]), r#"[10,"x",null]"#);
+    assert_eq!(encode(Table::new().into(), &[]), "{}");
+
+    let object = table(vec![
+        ("b".into(), i(2)),
+        ("a".into(), Value::Boolean(false)),
+        (i(1), "one".into()),
+        (i(5), "five".into()),
+    ]);
+    assert_eq!(encode(object, &sorted()), r#"{"1":"one","5":"five","a":false,"b":2}"#);
+}
+
+#[test]
+fn test_encode_pretty() {
+    let v = table(vec![
+        ("list".into(), table(vec![(i(1), i(1)), (i(2), table(vec![]))])),
+        ("name".into(), "x".into()),
+    ]);
+    let opts = [("sort_keys", Value::Boolean(true)), ("pretty", Value::Boolean(true))];
+    assert_eq!(encode(v.clone(), &opts), "{\n  \"list\": [\n    1,\n    {}\n  ],\n  \"name\": \"x\"\n}");
+    let opts = [("sort_keys", Value::Boolean(true)), ("indent", i(4))];
+    assert_eq!(encode(v, &opts), "{\n    \"list\": [\n        1,\n        {}\n    ],\n    \"name\": \"x\"\n}");
+}
+
+#[test]
+fn test_shared_table_is_not_a_cycle() {
+    let shared = table(vec![(i(1), i(1))]);
+    let v = table(vec![("a".into(), shared.clone()), ("b".into(), shared)]);
+    assert_eq!(encode(v, &sorted()), r#"{"a":[1],"b":[1]}"#);
+}
+
+#[test]
+#[should_panic(expected = "bad argument #1 to 'encode' (cannot encode cyclic table)")]
+fn test_encode_cycle() {
+    let v = table(vec![]);
+    let Value::Table(t) = &v else { unreachable!() };
+    t.borrow_mut().set("self".into(), v.clone());
+    encode(v, &[]);
+}
+
+#[test]
+#[should_panic(expected = "cannot encode function")]
+fn test_encode_function() {
+    let print = ExeState::new().get_global("print").cloned().unwrap();
+    encode(table(vec![(i(1), print)]), &[]);
+}
+
+#[test]
+#[should_panic(expected = "bad argument #1 to 'encode' (duplicate key \"1\")")]
+fn test_encode_duplicate_key() {
+    encode(table(vec![(i(1), "a".into()), ("1".into(), "b".into())]), &[]);
+}
+
+#[test]
+fn test_null() {
+    assert_eq!(json::null().type_name(), "userdata");
+    assert_ne!(json::null(), Value::Nil);
+    let print = ExeState::new().get_global("print").cloned().unwrap();
+    assert_ne!(json::null(), print);
+}
+
+#[test]
+#[should_panic(expected = "cannot encode number inf")]
+fn test_encode_infinity() {
+    encode(Value::Float(f64::INFINITY), &[]);
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(decode("12"), i(12));
+    assert_eq!(decode("1.5e1"), Value::Float(15.0));
+    assert_eq!(decode("2.0"), Value::Float(2.0));
+    assert_eq!(decode(r#""é\n""#), "é\n".into());
+    assert_eq!(decode("null"), json::null());
+
+    let Value::Table(t) = decode(r#"{"a": [1, null, true], "b": {}}"#) else {
+        panic!("not a table");
+    };
+    let t = t.borrow();
+    let Value::Table(a) = t.get(&"a".into()) else {
+        panic!("not a table");
+    };
+    assert_eq!(a.borrow().array, vec![i(1), json::null(), Value::Boolean(true)]);
+    assert!(matches!(t.get(&"b".into()), Value::Table(b) if b.borrow().is_empty()));
+}
+
+#[test]
+fn test_round_trip() {
+    let s = r#"{"a":[1,2.5,"x",null],"b":{"c":false},"d":"\"q\""}"#;
+    assert_eq!(encode(decode(s), &sorted()), s);
+}
+
+#[test]
+#[should_panic(expected = "bad argument #1 to 'decode' (expected value at line 1 column 5)")]
+fn test_decode_error() {
+    decode("[1, }");
+}
@@ -0,0 +1,44 @@
+// Limits of the byte code format, which must be parse errors rather
+// than wrong code.
+use std::io::Cursor;
+use lua_rs::parse;
+
+fn load(src: &str) {
+    parse::load(Cursor::new(src), "test");
+}
+
+#[test]
+#[should_panic(expected = "too many constants")]
+fn test_too_many_constants() {
+    let src: String = (0..300).map(|i| format!("print \"s{i}\"\n")).collect();
+    load(&src);
+}
+
+#[test]
+fn test_shared_constants() {
+    // equal constants take one slot
+    load(&"print \"s\"\n".repeat(1000));
+    let proto = parse::load(Cursor::new("print(1.0, 1, 1.0, 1)"), "test");
+    assert_eq!(proto.constants.len(), 2); // `print` and 1.0
+}
+
+#[test]
+#[should_panic(expected = "control structure too long")]
+fn test_long_loop() {
+    let src = format!("while false do\n{}end\n", "x = 1\n".repeat(20000));
+    load(&src);
+}
+
+#[test]
+#[should_panic(expected = "too many registers")]
+fn test_too_many_locals() {
+    let src: String = (0..300).map(|i| format!("local v{i}\n")).collect();
+    load(&src);
+}
+
+#[test]
+#[should_panic(expected = "too many registers")]
+fn test_too_many_arguments() {
+    let src = format!("print({})", vec!["nil"; 300].join(", "));
+    load(&src);
+}
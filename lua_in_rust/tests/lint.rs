@@ -0,0 +1,82 @@
+use lua_rs::lint::{self, Diagnostic};
+use lua_rs::lex::{Pos, Span};
+
+#[test]
+fn test_undefined_globals() {
+    let src = "print \"a\"\nprnt \"b\"\nlog(\"c\")\n";
+    let diags = lint::lint(src, &[String::from("log")]);
+    assert_eq!(diags, vec![Diagnostic {
+        code: "undefined-global",
+        message: String::from("undefined global `prnt`"),
+        span: Span { start: Pos { line: 2, column: 1 }, end: Pos { line: 2, column: 5 } },
+    }]);
+    assert_eq!(diags[0].to_human("a.lua"), "a.lua:2:1: undefined-global: undefined global `prnt`");
+    assert_eq!(diags[0].to_json("a.lua")["end_column"], 5);
+}
+
+#[test]
+fn test_syntax_error() {
+    let diags = lint::lint("print \"a\" )", &[]);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].code, "syntax-error");
+}
+
+fn codes(src: &str) -> Vec<(&'static str, String, u32)> {
+    lint::lint(src, &[]).into_iter().map(|d| (d.code, d.message, d.span.start.line)).collect()
+}
+
+#[test]
+fn test_globals_assigned_and_locals() {
+    assert!(codes("x = 1\nprint(x)\nlocal y = x\nprint(y)\n").is_empty());
+    assert_eq!(codes("local t = {v = z}\nprint(t.v)\n"), vec![
+        ("undefined-global", String::from("undefined global `z`"), 1),
+    ]);
+}
+
+#[test]
+fn test_unused_and_shadowed_locals() {
+    let src = "local a, _b = 1, 2\nlocal c <close> = nil\nlocal d = 1\ndo\n  local d = 2\n  print(d)\nend\nd = 3\n";
+    assert_eq!(codes(src), vec![
+        ("unused-local", String::from("unused local `a`"), 1),
+        ("unused-local", String::from("unused local `d`"), 3),
+        ("shadowed-local", String::from("local `d` shadows the one on line 3"), 5),
+    ]);
+}
+
+#[test]
+fn test_unreachable_code() {
+    let src = "while x do\n  break\n  print 'a'\nend\ndo return end\nprint 'b'\nprint 'c'\n";
+    let diags: Vec<_> = lint::lint(src, &[String::from("x")]).into_iter().map(|d| (d.code, d.span)).collect();
+    assert_eq!(diags, vec![
+        ("unreachable-code", Span { start: Pos { line: 3, column: 3 }, end: Pos { line: 3, column: 12 } }),
+        ("unreachable-code", Span { start: Pos { line: 6, column: 1 }, end: Pos { line: 7, column: 10 } }),
+    ]);
+    assert_eq!(codes("while true do\n  do print 'a' end\nend\nprint 'b'\n")[0].0, "unreachable-code");
+    assert!(codes("while true do\n  do break end\nend\nprint 'b'\n").is_empty());
+    assert!(codes("while true do\n  while true do break end\n  break\nend\nprint 'b'\n").is_empty());
+}
+
+#[test]
+fn test_readonly_assign() {
+    let src = "local a <const> = 1\na = 2\nprint = nil\nstring.pack = nil\njson['null'] = 1\nlocal string = {}\nstring.pack = 1\n";
+    let diags: Vec<_> = codes(src).into_iter().filter(|d| d.0 == "readonly-assign").collect();
+    assert_eq!(diags, vec![
+        ("readonly-assign", String::from("attempt to assign to const variable 'a'"), 2),
+        ("readonly-assign", String::from("assignment to builtin `print`"), 3),
+        ("readonly-assign", String::from("assignment to a field of builtin `string`"), 4),
+        ("readonly-assign", String::from("assignment to a field of builtin `json`"), 5),
+    ]);
+}
+
+#[test]
+fn test_arg_counts() {
+    let src = "assert()\njson.decode('1', 2)\nstring.unpack('i4')\nutf8.offset('a', 1, 1, 1)\nassert(1, 2, 3)\nstring.pack('i4', 1)\n";
+    assert_eq!(codes(src), vec![
+        ("wrong-arg-count", String::from("`assert` takes at least 1 argument, got 0"), 1),
+        ("wrong-arg-count", String::from("`json.decode` takes 1 argument, got 2"), 2),
+        ("wrong-arg-count", String::from("`string.unpack` takes 2 to 3 arguments, got 1"), 3),
+        ("wrong-arg-count", String::from("`utf8.offset` takes 2 to 3 arguments, got 4"), 4),
+    ]);
+    // a local of the same name is not the builtin
+    assert!(codes("local assert = print\nassert()\n").is_empty());
+}
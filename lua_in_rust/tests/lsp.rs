@@ -0,0 +1,157 @@
+use std::io::Cursor;
+use serde_json::json;
+use lua_rs::lex::{Pos, Span};
+use lua_rs::{lsp, parse};
+
+fn span(line: u32, start: u32, end: u32) -> Span {
+    Span { start: Pos { line, column: start }, end: Pos { line, column: end } }
+}
+
+#[test]
+fn test_diagnostics() {
+    assert!(lsp::diagnostics("print \"hello\"\n").is_empty());
+
+    let diags = lsp::diagnostics("print \"hello\"\nprint print\n");
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0]["range"], json!({
+        "start": {"line": 1, "character": 6},
+        "end": {"line": 1, "character": 11},
+    }));
+
+    let diags = lsp::diagnostics("print \"hello\n");
+    assert_eq!(diags[0]["message"], "unfinished literal string");
+}
+
+#[test]
+fn test_references_and_hover() {
+    let text = "print \"a\"\n  foo \"b\"\nprint \"c\"\n";
+    let block = parse::parse_ast(Cursor::new(text)).unwrap();
+
+    let refs = lsp::references(&block, Pos { line: 3, column: 2 });
+    assert_eq!(refs, vec![span(1, 1, 6), span(3, 1, 6)]);
+
+    let (text, range) = lsp::hover(&block, Pos { line: 2, column: 3 }).unwrap();
+    assert_eq!(text, "global `foo`");
+    assert_eq!(range, span(2, 3, 6));
+    assert_eq!(lsp::hover(&block, Pos { line: 2, column: 7 }).unwrap().0, "string, 1 bytes");
+}
+
+#[test]
+fn test_completion() {
+    let block = parse::parse_ast(Cursor::new("foo \"x\"")).unwrap();
+    let items = lsp::completion("foo \"x\"", Some(&block), Pos { line: 1, column: 1 });
+    assert_eq!(items, vec![
+        json!({"label": "assert", "kind": 3}),
+        json!({"label": "debug", "kind": 9}),
+        json!({"label": "foo", "kind": 6}),
+        json!({"label": "json", "kind": 9}),
+        json!({"label": "print", "kind": 3}),
+        json!({"label": "string", "kind": 9}),
+        json!({"label": "utf8", "kind": 9}),
+    ]);
+}
+
+#[test]
+fn test_no_errors_on_scripts() {
+    let dir = env!("CARGO_MANIFEST_DIR");
+    for sub in ["test_lua", "tests/lua", "tests/fmt"] {
+        for entry in std::fs::read_dir(format!("{dir}/{sub}")).unwrap() {
+            let path = entry.unwrap().path();
+            let text = std::fs::read_to_string(&path).unwrap();
+            // files of the test suite may need syntax the parser does not
+            // have yet, see EXPECTED_FAILURES in tests/conformance.rs
+            if sub == "tests/lua" && parse::parse_ast(Cursor::new(&text)).is_err() {
+                continue;
+            }
+            assert_eq!(lsp::diagnostics(&text), Vec::<serde_json::Value>::new(), "{}", path.display());
+        }
+    }
+}
+
+#[test]
+fn test_utf16_columns() {
+    // the emoji is 4 bytes and 2 UTF-16 code units
+    let diags = lsp::diagnostics("print \"\u{1F600}\" )");
+    assert_eq!(diags[0]["range"], json!({
+        "start": {"line": 0, "character": 11},
+        "end": {"line": 0, "character": 12},
+    }));
+    let line = "x = \"\u{e9}\u{1F600}\" y";
+    assert_eq!(lsp::utf16_column(line, 12), 8);
+    assert_eq!(lsp::byte_column(line, 8), 12);
+    assert_eq!(lsp::byte_column(line, 100), line.len() as u32 + 1);
+}
+
+const SCOPES: &str = "local x = 1\ndo\n  local x = x\n  print(x)\nend\nprint(x)\ng = x\nprint(g)\n";
+
+#[test]
+fn test_definition() {
+    let block = parse::parse_ast(Cursor::new(SCOPES)).unwrap();
+    assert_eq!(lsp::definition(&block, Pos { line: 4, column: 9 }), Some(span(3, 9, 10)));
+    assert_eq!(lsp::definition(&block, Pos { line: 3, column: 13 }), Some(span(1, 7, 8)));
+    assert_eq!(lsp::definition(&block, Pos { line: 6, column: 7 }), Some(span(1, 7, 8)));
+    assert_eq!(lsp::definition(&block, Pos { line: 8, column: 7 }), Some(span(7, 1, 2)));
+    assert_eq!(lsp::definition(&block, Pos { line: 8, column: 1 }), None); // print
+
+    let refs = lsp::references(&block, Pos { line: 1, column: 7 });
+    assert_eq!(refs, vec![span(1, 7, 8), span(3, 13, 14), span(6, 7, 8), span(7, 5, 6)]);
+    let (text, _) = lsp::hover(&block, Pos { line: 4, column: 9 }).unwrap();
+    assert_eq!(text, "local `x`, declared on line 3");
+}
+
+#[test]
+fn test_scoped_completion() {
+    let block = parse::parse_ast(Cursor::new(SCOPES)).unwrap();
+    let labels = |pos| -> Vec<String> {
+        lsp::completion(SCOPES, Some(&block), pos).iter().map(|i| i["label"].as_str().unwrap().to_string()).collect()
+    };
+    assert!(!labels(Pos { line: 1, column: 1 }).contains(&String::from("x")));
+    assert!(labels(Pos { line: 4, column: 3 }).contains(&String::from("x")));
+    assert!(labels(Pos { line: 1, column: 1 }).contains(&String::from("g")));
+
+    let text = "do local inner = 1 print(inner) end\n";
+    let block = parse::parse_ast(Cursor::new(text)).unwrap();
+    let items = lsp::completion(text, Some(&block), Pos { line: 2, column: 1 });
+    assert!(!items.contains(&json!({"label": "inner", "kind": 6})));
+    let items = lsp::completion(text, Some(&block), Pos { line: 1, column: 26 });
+    assert!(items.contains(&json!({"label": "inner", "kind": 6})));
+}
+
+#[test]
+fn test_field_completion() {
+    // the document does not parse while the name is typed
+    let text = "local s = string.\nprint(json.en";
+    let labels = |pos| -> Vec<(String, u64)> {
+        lsp::completion(text, None, pos).iter()
+            .map(|i| (i["label"].as_str().unwrap().to_string(), i["kind"].as_u64().unwrap()))
+            .collect()
+    };
+    let field = |name: &str, kind| (String::from(name), kind);
+    assert_eq!(labels(Pos { line: 1, column: 18 }), vec![
+        field("pack", 3),
+        field("packsize", 3),
+        field("unpack", 3),
+    ]);
+    assert_eq!(labels(Pos { line: 2, column: 14 }), vec![
+        field("decode", 3),
+        field("encode", 3),
+        field("null", 5),
+    ]);
+    assert!(labels(Pos { line: 1, column: 11 }).contains(&field("string", 9)));
+
+    // not after a local of the same name
+    let text = "local string = {}\nprint(string.)";
+    let block = parse::parse_ast(Cursor::new("local string = {}\nprint(string)")).unwrap();
+    assert!(lsp::completion(text, Some(&block), Pos { line: 2, column: 14 }).is_empty());
+}
+
+#[test]
+fn test_field_hover() {
+    let text = "local n = string.packsize(\"i4\")\nprint(utf8.charpattern)\n";
+    let block = parse::parse_ast(Cursor::new(text)).unwrap();
+    let (text, range) = lsp::hover(&block, Pos { line: 1, column: 20 }).unwrap();
+    assert!(text.starts_with("string.packsize(fmt)\n\n"), "{text}");
+    assert_eq!(range, span(1, 11, 26));
+    let (text, _) = lsp::hover(&block, Pos { line: 2, column: 13 }).unwrap();
+    assert!(text.starts_with("utf8.charpattern\n\n"), "{text}");
+}
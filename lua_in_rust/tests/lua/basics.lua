@@ -0,0 +1,41 @@
+-- What the interpreter supports so far: statements, locals, loops,
+-- to-be-closed variables, and calls of the standard library.
+print "testing basics"
+
+-- semicolons and blocks
+;;; do ;;; end ;
+do local a = 1; assert(a) end
+
+-- assert returns its arguments
+local x = assert("x", "message")
+assert(x)
+
+-- locals, shadowing and multiple assignment
+local p, q = nil, "q"
+p, q = q, p
+assert(p)
+do
+  local p = false
+  local r <const> = "r"
+  assert(r)
+end
+assert(p)
+
+-- while and break
+local go = true
+while go do
+  go = false
+end
+while true do
+  local inner <close> = nil
+  break
+end
+
+-- globals
+g = "global"
+assert(g)
+g = nil
+
+-- numbers
+local i, f = 9007199254740993, 1.5e300
+assert(i) assert(f)
@@ -0,0 +1,87 @@
+-- Adapted from the Lua 5.4 test suite: nextvar.lua
+print('testing tables, next, and for')
+
+local function checkerror (msg, f, ...)
+  local s, err = pcall(f, ...)
+  assert(not s and string.find(err, msg))
+end
+
+local a = {}
+
+-- make sure table has lots of space in hash part
+for i=1,100 do a[i.."+"] = true end
+for i=1,100 do a[i.."+"] = undef end
+-- fill hash part with numeric indices testing size operator
+for i=1,100 do
+  a[i] = true
+  assert(#a == i)
+end
+
+do   -- rehash moving elements from array to hash
+  local a = {}
+  for i = 1, 100 do a[i] = i end
+  for i = 1, 100 do a[i] = nil end
+  a.x = 1
+  assert(next(a) == "x")
+end
+
+-- testing ipairs
+local x = 0
+for k,v in ipairs{10,20,30;x=12} do
+  x = x + 1
+  assert(k == x and v == x * 10)
+end
+
+for _ in ipairs{x=12, y=24} do assert(nil) end
+
+-- test for 'false' x ipair
+x = false
+local i = 0
+for k,v in ipairs{true,false,true,false} do
+  i = i + 1
+  x = not x
+  assert(x == v)
+end
+assert(i == 4)
+
+-- iterator function is always the same
+assert(type(ipairs{}) == 'function' and ipairs{} == ipairs{})
+
+-- testing next x GC of deleted keys
+do
+  local t = {}
+  for i = 1, 10 do t[i] = i end
+  local k, v = next(t)
+  assert(k == 1 and v == 1)
+end
+
+assert(#{} == 0)
+assert(#{nil} == 0)
+assert(#{nil, nil} == 0)
+assert(#{1, 2, 3, nil, nil} == 3)
+
+-- testing table.insert and table.remove
+local a = {}
+table.insert(a, 10); table.insert(a, 2, 20);
+table.insert(a, 1, -1); table.insert(a, 40);
+table.insert(a, #a+1, 50)
+table.insert(a, 2, -2)
+assert(a[2] ~= undef)
+assert(a["2"] == undef)
+assert(table.remove(a,1) == -1)
+assert(table.remove(a,1) == -2)
+assert(table.remove(a,1) == 10)
+assert(table.remove(a,1) == 20)
+assert(table.remove(a,1) == 40)
+assert(table.remove(a,1) == 50)
+assert(table.remove(a,1) == nil)
+
+-- testing numeric for
+local a = 0
+for i = 1, 10 do a = a + i end
+assert(a == 55)
+a = 0
+for i = 10, 1, -1 do a = a + 1 end
+assert(a == 10)
+
+print"OK"
@@ -0,0 +1,22 @@
+-- Table constructors, fields and indexing.
+print "testing tables"
+
+local t = {1, 2; 3, name = "t", ["key"] = "value", nested = {deep = true}}
+assert(t[1]) assert(t[3]) assert(t.name) assert(t.key) assert(t["name"])
+assert(t.nested.deep)
+
+t.nested.deep = false
+t.added = {}
+t[4] = "four"
+assert(t.added) assert(t[4])
+local k = "name"
+assert(t[k])
+
+-- calls through fields, with a table argument
+assert(string.pack)
+assert(string.packsize("i4"))
+local s = string.pack("z", "abc")
+assert(s)
+assert{}
+
+return t
@@ -0,0 +1,69 @@
+use std::fs;
+use std::io::{self, Cursor};
+use std::process::Command;
+use lua_rs::parse;
+use lua_rs::profile::Profiler;
+use lua_rs::vm::ExeState;
+
+const SCRIPT: &str = "\
+print \"x\"
+local a = 1
+while a do
+  assert(a)
+  a = nil
+end
+";
+
+// the stacks of the folded lines, checking that each ends with a time
+fn stacks(folded: &str) -> Vec<&str> {
+    folded.lines()
+        .map(|line| {
+            let (stack, micros) = line.rsplit_once(' ').unwrap();
+            assert!(micros.parse::<u64>().is_ok(), "{line}");
+            stack
+        })
+        .collect()
+}
+
+#[test]
+fn test_folded_stacks() {
+    let proto = parse::load(Cursor::new(SCRIPT), "script.lua");
+    let mut state = ExeState::new();
+    state.set_stdout(Box::new(io::sink()));
+    let profiler = Profiler::attach(&mut state);
+    state.execute(&proto);
+    state.clear_hook();
+
+    let mut folded = Vec::new();
+    profiler.lock().unwrap().write_folded(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    assert_eq!(stacks(&folded), [
+        "script.lua:main",
+        "script.lua:main;assert",
+        "script.lua:main;print",
+    ]);
+
+    let mut report = Vec::new();
+    profiler.lock().unwrap().write_report(&mut report, 10).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.contains("  script.lua:main\n"), "{report}");
+    assert!(report.contains("  script.lua:4\n"), "{report}");
+}
+
+#[test]
+fn test_profile_output() {
+    let dir = env!("CARGO_TARGET_TMPDIR");
+    let (script, output) = (format!("{dir}/profiled.lua"), format!("{dir}/profiled.folded"));
+    fs::write(&script, SCRIPT).unwrap();
+    let _ = fs::remove_file(&output);
+
+    let status = Command::new(env!("CARGO_BIN_EXE_lua-rs"))
+        .arg(format!("--profile={output}"))
+        .arg(&script)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+    let folded = fs::read_to_string(&output).unwrap();
+    assert_eq!(stacks(&folded).len(), 3, "{folded}");
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use serde::{Deserialize, Serialize};
+use lua_rs::parse;
+use lua_rs::serde::{from_value, to_value};
+use lua_rs::value::{Table, Value};
+use lua_rs::vm::ExeState;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Window {
+    title: String,
+    size: Vec<u32>,
+    fullscreen: bool,
+    scale: f64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Difficulty {
+    Easy,
+    Custom { lives: u8 },
+    Seed(i64),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    window: Window,
+    difficulty: Difficulty,
+    save: Option<String>,
+    keys: BTreeMap<String, String>,
+}
+
+fn config() -> Config {
+    Config {
+        name: "game".into(),
+        window: Window { title: "Game".into(), size: vec![800, 600], fullscreen: false, scale: 1.5 },
+        difficulty: Difficulty::Custom { lives: 3 },
+        save: None,
+        keys: [("jump".into(), "space".into()), ("fire".into(), "x".into())].into(),
+    }
+}
+
+fn field(v: &Value, name: &str) -> Value {
+    let Value::Table(t) = v else {
+        panic!("not a table: {v:?}");
+    };
+    let v = t.borrow().get(&name.into());
+    v
+}
+
+fn table(entries: &[(&str, Value)]) -> Value {
+    let mut t = Table::new();
+    for (k, v) in entries {
+        t.set((*k).into(), v.clone());
+    }
+    t.into()
+}
+
+#[test]
+fn test_round_trip() {
+    let v = to_value(&config()).unwrap();
+    assert_eq!(from_value::<Config>(&v).unwrap(), config());
+}
+
+#[test]
+fn test_to_table() {
+    let v = to_value(&config()).unwrap();
+    assert_eq!(field(&v, "name"), Value::from("game"));
+    assert_eq!(field(&v, "save"), Value::Nil);
+    let window = field(&v, "window");
+    assert_eq!(field(&window, "scale"), Value::Float(1.5));
+    let Value::Table(size) = field(&window, "size") else {
+        panic!("size is not a table");
+    };
+    assert_eq!(size.borrow().array, vec![Value::Integer(800), Value::Integer(600)]);
+    assert_eq!(field(&field(&field(&v, "difficulty"), "Custom"), "lives"), Value::Integer(3));
+    assert_eq!(field(&field(&v, "keys"), "jump"), Value::from("space"));
+}
+
+// the table goes through a script and comes back
+#[test]
+fn test_through_state() {
+    let mut state = ExeState::new();
+    state.set_global("config", to_value(&config()).unwrap());
+    state.execute(&parse::load(Cursor::new("local c = config\nsaved = c"), "test"));
+    let saved = state.get_global("saved").cloned().unwrap();
+    assert_eq!(from_value::<Config>(&saved).unwrap(), config());
+}
+
+#[test]
+fn test_enums() {
+    assert_eq!(to_value(&Difficulty::Easy).unwrap(), Value::from("Easy"));
+    assert_eq!(from_value::<Difficulty>(&"Easy".into()).unwrap(), Difficulty::Easy);
+    let seed = table(&[("Seed", Value::Integer(42))]);
+    assert_eq!(from_value::<Difficulty>(&seed).unwrap(), Difficulty::Seed(42));
+    let err = from_value::<Difficulty>(&"Hard".into()).unwrap_err();
+    assert!(err.to_string().contains("unknown variant `Hard`"), "{err}");
+}
+
+#[test]
+fn test_integral_float() {
+    let v = table(&[("title", "t".into()), ("size", table(&[])), ("fullscreen", Value::Boolean(true)),
+        ("scale", Value::Integer(2))]);
+    let w: Window = from_value(&v).unwrap();
+    assert_eq!(w.scale, 2.0);
+    assert_eq!(from_value::<u32>(&Value::Float(3.0)).unwrap(), 3);
+}
+
+#[test]
+fn test_error_path() {
+    let mut v = to_value(&config()).unwrap();
+    let Value::Table(size) = field(&field(&v, "window"), "size") else {
+        panic!("size is not a table");
+    };
+    size.borrow_mut().set(Value::Integer(2), "wide".into());
+    let err = from_value::<Config>(&v).unwrap_err();
+    assert_eq!(err.path(), "window.size[2]");
+    assert!(err.to_string().starts_with("window.size[2]: invalid type"), "{err}");
+
+    v = to_value(&config()).unwrap();
+    let Value::Table(window) = field(&v, "window") else {
+        panic!("window is not a table");
+    };
+    window.borrow_mut().set("fullscreen".into(), Value::Integer(1));
+    let err = from_value::<Config>(&v).unwrap_err();
+    assert_eq!(err.path(), "window.fullscreen");
+}
+
+#[test]
+fn test_missing_field() {
+    let v = table(&[("window", table(&[("title", "t".into())]))]);
+    let err = from_value::<Config>(&v).unwrap_err();
+    assert_eq!(err.to_string(), "window: missing field `size`");
+}
+
+#[test]
+fn test_to_value_errors() {
+    let err = to_value(&vec![Some(1), None]).unwrap_err();
+    assert_eq!(err.to_string(), "[2]: nil in a sequence");
+
+    let mut m = BTreeMap::new();
+    m.insert("big", u64::MAX);
+    let err = to_value(&m).unwrap_err();
+    assert_eq!(err.to_string(), "big: integer 18446744073709551615 does not fit in a Lua integer");
+}
+
+#[test]
+fn test_function_is_error() {
+    let v = table(&[("name", Value::Function(|_| 0))]);
+    let err = from_value::<BTreeMap<String, String>>(&v).unwrap_err();
+    assert_eq!(err.path(), "name");
+}
@@ -0,0 +1,134 @@
+use lua_rs::value::Value;
+use lua_rs::vm::ExeState;
+
+fn call(name: &str, args: Vec<Value>) -> Vec<Value> {
+    let mut state = ExeState::new();
+    let Some(Value::Table(string)) = state.get_global("string").cloned() else {
+        panic!("no string library");
+    };
+    let func = string.borrow().get(&name.into());
+    state.call(func, args)
+}
+
+fn s(s: &[u8]) -> Value {
+    Value::from(s)
+}
+
+fn i(i: i64) -> Value {
+    Value::Integer(i)
+}
+
+fn pack(fmt: &str, mut args: Vec<Value>) -> Vec<u8> {
+    args.insert(0, fmt.into());
+    match &call("pack", args)[..] {
+        [Value::String(s)] => s.to_vec(),
+        r => panic!("pack returned {r:?}"),
+    }
+}
+
+fn unpack(fmt: &str, data: &[u8]) -> Vec<Value> {
+    call("unpack", vec![fmt.into(), s(data)])
+}
+
+#[test]
+fn test_integers() {
+    assert_eq!(pack("<i4", vec![i(1)]), b"\x01\0\0\0");
+    assert_eq!(pack(">i4", vec![i(1)]), b"\0\0\0\x01");
+    assert_eq!(pack("<h>H", vec![i(-2), i(0xABCD)]), b"\xFE\xFF\xAB\xCD");
+    assert_eq!(pack("bB", vec![i(-1), i(255)]), b"\xFF\xFF");
+    assert_eq!(pack("<i3", vec![i(-0x10000)]), b"\0\0\xFF");
+    assert_eq!(pack("<j", vec![i(i64::MIN)]), b"\0\0\0\0\0\0\0\x80");
+    // sizes beyond 8 bytes are sign extended
+    assert_eq!(pack("<i10", vec![i(-1)]), [0xFF; 10]);
+    assert_eq!(pack(">I9", vec![i(1)]), b"\0\0\0\0\0\0\0\0\x01");
+
+    assert_eq!(unpack("<i4", b"\x01\0\0\0"), vec![i(1), i(5)]);
+    assert_eq!(unpack(">h<H", b"\xFF\xFE\xCD\xAB"), vec![i(-2), i(0xABCD), i(5)]);
+    assert_eq!(unpack("<i3", b"\0\0\xFF"), vec![i(-0x10000), i(4)]);
+    assert_eq!(unpack("<I3", b"\0\0\xFF"), vec![i(0xFF0000), i(4)]);
+    assert_eq!(unpack("<i10", &[0xFF; 10]), vec![i(-1), i(11)]);
+}
+
+#[test]
+fn test_floats() {
+    let data = pack("<fdn", vec![Value::Float(0.5), Value::Float(-2.25), i(3)]);
+    assert_eq!(data.len(), 20);
+    assert_eq!(&data[..4], 0.5f32.to_le_bytes());
+    assert_eq!(&data[12..], 3.0f64.to_le_bytes());
+    assert_eq!(unpack("<fdn", &data), vec![Value::Float(0.5), Value::Float(-2.25), Value::Float(3.0), i(21)]);
+    assert_eq!(pack(">d", vec![Value::Float(1.0)]), 1.0f64.to_be_bytes());
+}
+
+#[test]
+fn test_strings() {
+    assert_eq!(pack("z", vec![s(b"hi")]), b"hi\0");
+    assert_eq!(pack("<s2", vec![s(b"abc")]), b"\x03\0abc");
+    assert_eq!(pack("c5", vec![s(b"ab")]), b"ab\0\0\0");
+    assert_eq!(pack("s1", vec![s(b"\xFF\0")]), b"\x02\xFF\0");
+
+    assert_eq!(unpack("zB", b"hi\0\x07"), vec![s(b"hi"), i(7), i(5)]);
+    assert_eq!(unpack("<s2c2", b"\x03\0abcde"), vec![s(b"abc"), s(b"de"), i(8)]);
+}
+
+#[test]
+fn test_alignment() {
+    // no alignment unless asked with '!'
+    assert_eq!(pack("<bi4", vec![i(1), i(2)]), b"\x01\x02\0\0\0");
+    assert_eq!(pack("<!bi4", vec![i(1), i(2)]), b"\x01\0\0\0\x02\0\0\0");
+    assert_eq!(pack("<!2bi4", vec![i(1), i(2)]), b"\x01\0\x02\0\0\0");
+    assert_eq!(pack("<!bXi4", vec![i(1)]), b"\x01\0\0\0");
+    assert_eq!(pack("<bxh", vec![i(1), i(2)]), b"\x01\0\x02\0");
+    assert_eq!(unpack("<!bi4", b"\x01\0\0\0\x02\0\0\0"), vec![i(1), i(2), i(9)]);
+    assert_eq!(call("packsize", vec!["!bi4d".into()]), vec![i(16)]);
+    assert_eq!(call("packsize", vec!["bi4d c3 x".into()]), vec![i(17)]);
+}
+
+#[test]
+fn test_unpack_position() {
+    let data = b"\x01\x02\x03";
+    assert_eq!(call("unpack", vec!["B".into(), s(data), i(2)]), vec![i(2), i(3)]);
+    assert_eq!(call("unpack", vec!["B".into(), s(data), i(-1)]), vec![i(3), i(4)]);
+    assert_eq!(call("unpack", vec!["".into(), s(data), i(4)]), vec![i(4)]);
+}
+
+#[test]
+#[should_panic(expected = "bad argument #2 to 'pack' (integer overflow)")]
+fn test_integer_overflow() {
+    pack("i2", vec![i(40000)]);
+}
+
+#[test]
+#[should_panic(expected = "bad argument #2 to 'unpack' (data string too short)")]
+fn test_data_too_short() {
+    unpack("i4", b"\0\0");
+}
+
+#[test]
+#[should_panic(expected = "9-byte integer does not fit into Lua Integer")]
+fn test_integer_does_not_fit() {
+    unpack("<i9", b"\0\0\0\0\0\0\0\0\x01");
+}
+
+#[test]
+#[should_panic(expected = "integral size (17) out of limits [1,16]")]
+fn test_size_limit() {
+    pack("i17", vec![i(1)]);
+}
+
+#[test]
+#[should_panic(expected = "invalid format option 'y'")]
+fn test_invalid_option() {
+    pack("y", vec![]);
+}
+
+#[test]
+#[should_panic(expected = "bad argument #1 to 'packsize' (variable-length format)")]
+fn test_packsize_variable() {
+    call("packsize", vec!["s4".into()]);
+}
+
+#[test]
+#[should_panic(expected = "format asks for alignment not power of 2")]
+fn test_alignment_power_of_two() {
+    pack("!4i3", vec![i(1)]);
+}
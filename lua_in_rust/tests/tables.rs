@@ -0,0 +1,83 @@
+use std::io::Cursor;
+use lua_rs::parse;
+use lua_rs::value::{Table, Value};
+use lua_rs::vm::ExeState;
+
+fn run(src: &str) -> Vec<Value> {
+    let mut state = ExeState::new();
+    state.execute(&parse::load(Cursor::new(src), "test"))
+}
+
+fn field(t: &Value, key: Value) -> Value {
+    let Value::Table(t) = t else {
+        panic!("not a table: {t:?}");
+    };
+    let v = t.borrow().get(&key);
+    v
+}
+
+#[test]
+fn test_constructor() {
+    let v = run(r#"return {10, 20; name = "n", ["k" ] = "v", 30, inner = {x = 1}}"#);
+    let t = &v[0];
+    for (i, n) in [10, 20, 30].into_iter().enumerate() {
+        assert_eq!(field(t, Value::Integer(i as i64 + 1)), Value::Integer(n));
+    }
+    assert_eq!(field(t, "name".into()), Value::from("n"));
+    assert_eq!(field(t, "k".into()), Value::from("v"));
+    assert_eq!(field(&field(t, "inner".into()), "x".into()), Value::Integer(1));
+    let Value::Table(t) = t else { unreachable!() };
+    assert_eq!(t.borrow().len(), 3);
+}
+
+#[test]
+fn test_fields() {
+    let v = run(r#"
+        local t = {a = {b = {}}}
+        t.a.b.c = "deep"
+        t["x"] = t.a.b.c
+        local k = "y"
+        t[k] = 1
+        t.a.b["c"] = nil
+        return t.x, t[k], t.a.b.c, t.missing
+    "#);
+    assert_eq!(v, vec![Value::from("deep"), Value::Integer(1), Value::Nil, Value::Nil]);
+}
+
+#[test]
+fn test_return() {
+    assert_eq!(run("print 'x'"), vec![]);
+    assert_eq!(run("return"), vec![]);
+    assert_eq!(run("local a, b = 1, 'b'\nreturn a, b;"), vec![Value::Integer(1), Value::from("b")]);
+    assert_eq!(run("while true do return 'loop' end"), vec![Value::from("loop")]);
+}
+
+#[test]
+fn test_globals_are_shared() {
+    let mut state = ExeState::new();
+    let mut t = Table::new();
+    t.set("n".into(), Value::Integer(1));
+    state.set_global("config", t.into());
+    state.execute(&parse::load(Cursor::new("config.n = 2\nconfig.s = 'set'"), "test"));
+    let config = state.get_global("config").cloned().unwrap();
+    assert_eq!(field(&config, "n".into()), Value::Integer(2));
+    assert_eq!(field(&config, "s".into()), Value::from("set"));
+}
+
+#[test]
+#[should_panic(expected = "attempt to index a nil value")]
+fn test_index_nil() {
+    run("return missing.field");
+}
+
+#[test]
+#[should_panic(expected = "attempt to index a string value")]
+fn test_assign_to_string_field() {
+    run("local s = 'x'\ns.field = 1");
+}
+
+#[test]
+#[should_panic(expected = "expected `=` or arguments, found Eos")]
+fn test_field_statement() {
+    parse::load(Cursor::new("t.field"), "test");
+}
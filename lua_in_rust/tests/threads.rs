@@ -0,0 +1,193 @@
+use std::cell::{Cell, RefCell};
+use std::io::{Cursor, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use lua_rs::channel::{self, Data, Receiver, Sender};
+use lua_rs::parse;
+use lua_rs::value::{Table, Value};
+use lua_rs::vm::ExeState;
+
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+// Rust functions are plain fn pointers, so each thread keeps its end
+// of the channel and its loop counter here.
+thread_local! {
+    static SENDER: RefCell<Option<Sender>> = const { RefCell::new(None) };
+    static RECEIVER: RefCell<Option<Receiver>> = const { RefCell::new(None) };
+    static LEFT: Cell<u32> = const { Cell::new(0) };
+}
+
+// send(v)
+fn send(state: &mut ExeState) -> i32 {
+    let v = state.arg(1).clone();
+    SENDER.with(|s| s.borrow().as_ref().unwrap().send(&v)).unwrap();
+    0
+}
+
+// recv(): the next value, or nil when the senders are gone
+fn recv(state: &mut ExeState) -> i32 {
+    let v = RECEIVER.with(|r| r.borrow().as_ref().unwrap().recv());
+    state.push(v.unwrap_or(Value::Nil));
+    1
+}
+
+// more(): true for as many calls as set in LEFT
+fn more(state: &mut ExeState) -> i32 {
+    let left = LEFT.with(|n| n.replace(n.get().saturating_sub(1)));
+    state.push(Value::Boolean(left > 0));
+    1
+}
+
+// pair(a, b): the table {a, b}
+fn pair(state: &mut ExeState) -> i32 {
+    let mut t = Table::new();
+    t.set(Value::Integer(1), state.arg(1).clone());
+    t.set(Value::Integer(2), state.arg(2).clone());
+    state.push(t.into());
+    1
+}
+
+fn state(out: &Output) -> ExeState {
+    let mut state = ExeState::new();
+    state.set_stdout(Box::new(out.clone()));
+    for (name, f) in [("send", send as fn(&mut ExeState) -> i32), ("recv", recv), ("more", more), ("pair", pair)] {
+        state.set_global(name, Value::Function(f));
+    }
+    state
+}
+
+fn run(state: &mut ExeState, src: &str) {
+    state.execute(&parse::load(Cursor::new(src), "test"));
+}
+
+#[test]
+fn test_move_state() {
+    let out = Output::default();
+    let mut state = state(&out);
+    run(&mut state, "x = 'before'");
+    let mut state = thread::spawn(move || {
+        run(&mut state, "print(x)\nx = 'after'");
+        state
+    }).join().unwrap();
+    run(&mut state, "print(x)");
+    assert_eq!(out.text(), "before\nafter\n");
+}
+
+#[test]
+fn test_concurrent_states() {
+    let workers: Vec<_> = (0..8).map(|i| {
+        thread::spawn(move || {
+            let out = Output::default();
+            let mut state = state(&out);
+            state.set_global("id", Value::Integer(i));
+            LEFT.with(|n| n.set(100 + i as u32));
+            run(&mut state, r#"
+                while more() do
+                    local p <const> = pair(id, "x")
+                    print(id, "x")
+                end
+            "#);
+            out.text()
+        })
+    }).collect();
+    for (i, w) in workers.into_iter().enumerate() {
+        let text = w.join().unwrap();
+        let expected = format!("{i}\tx\n").repeat(100 + i);
+        assert_eq!(text, expected);
+    }
+}
+
+// a producer script sends tables to a consumer script in another thread
+#[test]
+fn test_channel_between_states() {
+    let (tx, rx) = channel::channel();
+    let producer = thread::spawn(move || {
+        SENDER.with(|s| *s.borrow_mut() = Some(tx));
+        LEFT.with(|n| n.set(3));
+        let out = Output::default();
+        run(&mut state(&out), r#"
+            while more() do
+                send(pair("job", 1.5))
+            end
+            send "done"
+        "#);
+    });
+    let consumer = thread::spawn(move || {
+        RECEIVER.with(|r| *r.borrow_mut() = Some(rx));
+        let out = Output::default();
+        run(&mut state(&out), r#"
+            local v = recv()
+            while v do
+                print(v)
+                last = v
+                v = recv()
+            end
+        "#);
+        out.text()
+    });
+    producer.join().unwrap();
+    let text = consumer.join().unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[..3].iter().all(|l| l.starts_with("table: ")));
+    assert_eq!(lines[3], "done");
+}
+
+#[test]
+fn test_values_are_copied() {
+    let (tx, rx) = channel::channel();
+    let mut inner = Table::new();
+    inner.set("n".into(), Value::Integer(1));
+    let inner = Value::from(inner);
+    let mut t = Table::new();
+    t.set(Value::Integer(1), "a".into());
+    t.set("inner".into(), inner.clone());
+    let t = Value::from(t);
+    tx.send(&t).unwrap();
+
+    let Value::Table(inner) = inner else { unreachable!() };
+    inner.borrow_mut().set("n".into(), Value::Integer(2));
+
+    let received = thread::spawn(move || Data::from_value(&rx.recv().unwrap()).unwrap()).join().unwrap();
+    let Data::Table(mut entries) = received else {
+        panic!("not a table: {received:?}");
+    };
+    entries.sort_by_key(|(k, _)| format!("{k:?}"));
+    assert_eq!(entries, vec![
+        (Data::Integer(1), Data::String(b"a".to_vec())),
+        (Data::String(b"inner".to_vec()), Data::Table(vec![(Data::String(b"n".to_vec()), Data::Integer(1))])),
+    ]);
+}
+
+#[test]
+fn test_send_errors() {
+    let (tx, rx) = channel::channel();
+    let mut t = Table::new();
+    t.set("f".into(), Value::Function(send));
+    assert_eq!(tx.send(&t.into()), Err(String::from("cannot send a function")));
+
+    let t = Value::from(Table::new());
+    let Value::Table(cell) = &t else { unreachable!() };
+    cell.borrow_mut().set("self".into(), t.clone());
+    assert_eq!(tx.send(&t), Err(String::from("cannot send a table with cycles")));
+    cell.borrow_mut().set("self".into(), Value::Nil);
+
+    drop(rx);
+    assert_eq!(tx.send(&Value::Integer(1)), Err(String::from("receiver is closed")));
+}
@@ -0,0 +1,97 @@
+use lua_rs::value::Value;
+use lua_rs::vm::ExeState;
+
+fn call(name: &str, args: Vec<Value>) -> Vec<Value> {
+    let mut state = ExeState::new();
+    let Some(Value::Table(utf8)) = state.get_global("utf8").cloned() else {
+        panic!("no utf8 library");
+    };
+    let func = utf8.borrow().get(&name.into());
+    state.call(func, args)
+}
+
+fn s(s: &str) -> Value {
+    s.into()
+}
+
+fn i(i: i64) -> Value {
+    Value::Integer(i)
+}
+
+#[test]
+fn test_char() {
+    assert_eq!(call("char", vec![i(72), i(0xE9), i(0x4F60)]), vec![s("Hé你")]);
+    assert_eq!(call("char", vec![i(0x7FFFFFFF)]), vec![Value::from(&b"\xFD\xBF\xBF\xBF\xBF\xBF"[..])]);
+}
+
+#[test]
+#[should_panic(expected = "bad argument #2 to 'char' (value out of range)")]
+fn test_char_out_of_range() {
+    call("char", vec![i(65), i(-1)]);
+}
+
+#[test]
+fn test_len() {
+    assert_eq!(call("len", vec![s("héllo")]), vec![i(5)]);
+    assert_eq!(call("len", vec![s("héllo"), i(4)]), vec![i(3)]);
+    assert_eq!(call("len", vec![s("héllo"), i(3)]), vec![Value::Nil, i(3)]);
+    assert_eq!(call("len", vec![s("héllo"), i(-2)]), vec![i(2)]);
+    assert_eq!(call("len", vec![s("")]), vec![i(0)]);
+    assert_eq!(call("len", vec![Value::from(&b"ab\xffc"[..])]), vec![Value::Nil, i(3)]);
+    // surrogates are only accepted when lax
+    let surrogate = Value::from(&b"\xED\xA0\x80"[..]);
+    assert_eq!(call("len", vec![surrogate.clone()]), vec![Value::Nil, i(1)]);
+    assert_eq!(call("len", vec![surrogate, i(1), i(-1), Value::Boolean(true)]), vec![i(1)]);
+}
+
+#[test]
+fn test_codepoint() {
+    assert_eq!(call("codepoint", vec![s("héllo")]), vec![i(104)]);
+    assert_eq!(call("codepoint", vec![s("héllo"), i(1), i(-1)]),
+        vec![i(104), i(0xE9), i(108), i(108), i(111)]);
+    assert_eq!(call("codepoint", vec![s("héllo"), i(4), i(3)]), vec![]);
+}
+
+#[test]
+#[should_panic(expected = "invalid UTF-8 code")]
+fn test_codepoint_invalid() {
+    call("codepoint", vec![s("héllo"), i(3)]);
+}
+
+#[test]
+fn test_offset() {
+    let text = s("aé你b");
+    assert_eq!(call("offset", vec![text.clone(), i(3)]), vec![i(4)]);
+    assert_eq!(call("offset", vec![text.clone(), i(-1)]), vec![i(7)]);
+    assert_eq!(call("offset", vec![text.clone(), i(0), i(5)]), vec![i(4)]);
+    assert_eq!(call("offset", vec![text.clone(), i(5)]), vec![i(8)]);
+    assert_eq!(call("offset", vec![text, i(6)]), vec![Value::Nil]);
+}
+
+#[test]
+fn test_codes() {
+    let text = s("aé你");
+    let mut state = ExeState::new();
+    let results = call("codes", vec![text]);
+    let [iter, text, mut pos] = <[Value; 3]>::try_from(results).unwrap();
+
+    let mut codes = Vec::new();
+    loop {
+        let r = state.call(iter.clone(), vec![text.clone(), pos]);
+        if r.is_empty() {
+            break;
+        }
+        codes.push((r[0].clone(), r[1].clone()));
+        pos = r[0].clone();
+    }
+    assert_eq!(codes, vec![(i(1), i(97)), (i(2), i(0xE9)), (i(4), i(0x4F60))]);
+}
+
+#[test]
+fn test_charpattern() {
+    assert_eq!(call("char", vec![]), vec![s("")]);
+    let state = ExeState::new();
+    let Some(Value::Table(utf8)) = state.get_global("utf8") else { panic!() };
+    assert_eq!(utf8.borrow().get(&"charpattern".into()),
+        Value::from(&b"[\x00-\x7F\xC2-\xFD][\x80-\xBF]*"[..]));
+}
@@ -0,0 +1,250 @@
+// 响应体：可以在内存中，也可以在发送时从文件、Read 或迭代器中读出
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+#[derive(Default)]
+pub enum Body {
+    #[default]
+    Empty,
+    Bytes(Vec<u8>),
+    File(File, u64), // 文件和它的长度
+    Reader(Box<dyn Read + Send>),
+    Chunks(Box<dyn Iterator<Item = Vec<u8>> + Send>),
+}
+
+impl Body {
+    // 普通文件，长度在打开时确定；目录等返回 InvalidInput
+    pub fn file(file: File) -> io::Result<Body> {
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a regular file"));
+        }
+        Ok(Body::File(file, metadata.len()))
+    }
+
+    // 长度未知，发送时使用 chunked 编码
+    pub fn reader(reader: impl Read + Send + 'static) -> Body {
+        Body::Reader(Box::new(reader))
+    }
+
+    // 每一项作为一块发送
+    pub fn chunks(chunks: impl IntoIterator<Item = Vec<u8>, IntoIter: Send + 'static>) -> Body {
+        Body::Chunks(Box::new(chunks.into_iter()))
+    }
+
+    // 事先知道的长度；Reader 和 Chunks 返回 None
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Body::Empty => Some(0),
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::File(_, len) => Some(*len),
+            Body::Reader(_) | Body::Chunks(_) => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    // 在内存中的内容
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Empty => Some(&[]),
+            Body::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    // 把全部内容写入 `w`；文件最多写出打开时的长度
+    pub fn write_to(self, w: &mut impl Write) -> io::Result<u64> {
+        match self {
+            Body::Empty => Ok(0),
+            Body::Bytes(bytes) => {
+                w.write_all(&bytes)?;
+                Ok(bytes.len() as u64)
+            }
+            Body::File(file, len) => io::copy(&mut file.take(len), w),
+            Body::Reader(mut reader) => io::copy(&mut reader, w),
+            Body::Chunks(chunks) => {
+                let mut n = 0;
+                for chunk in chunks {
+                    w.write_all(&chunk)?;
+                    n += chunk.len() as u64;
+                }
+                Ok(n)
+            }
+        }
+    }
+
+    // 作为 Read 读出，不会一次读入内存
+    pub fn into_reader(self) -> Box<dyn Read + Send> {
+        match self {
+            Body::Empty => Box::new(io::empty()),
+            Body::Bytes(bytes) => Box::new(io::Cursor::new(bytes)),
+            Body::File(file, len) => Box::new(file.take(len)),
+            Body::Reader(reader) => reader,
+            Body::Chunks(chunks) => Box::new(ChunksReader { chunks, chunk: io::Cursor::new(Vec::new()) }),
+        }
+    }
+
+    // 读出全部内容
+    pub fn into_bytes(self) -> io::Result<Vec<u8>> {
+        match self {
+            Body::Bytes(bytes) => Ok(bytes),
+            body => {
+                let mut bytes = Vec::new();
+                body.write_to(&mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+// 依次读出每一块
+struct ChunksReader {
+    chunks: Box<dyn Iterator<Item = Vec<u8>> + Send>,
+    chunk: io::Cursor<Vec<u8>>, // 正在读的块
+}
+
+impl Read for ChunksReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.chunk.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.chunks.next() {
+                Some(chunk) => self.chunk = io::Cursor::new(chunk),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Body::Empty => write!(f, "Empty"),
+            Body::Bytes(bytes) => write!(f, "Bytes({:?})", String::from_utf8_lossy(bytes)),
+            Body::File(_, len) => write!(f, "File({} bytes)", len),
+            Body::Reader(_) => write!(f, "Reader"),
+            Body::Chunks(_) => write!(f, "Chunks"),
+        }
+    }
+}
+
+// 只有内存中的内容可以比较；空的 Bytes 和 Empty 相等
+impl PartialEq for Body {
+    fn eq(&self, other: &Body) -> bool {
+        match (self.as_bytes(), other.as_bytes()) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body::Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for Body {
+    fn from(bytes: &[u8]) -> Body {
+        Body::Bytes(bytes.to_vec())
+    }
+}
+
+impl<const N: usize> From<&[u8; N]> for Body {
+    fn from(bytes: &[u8; N]) -> Body {
+        Body::Bytes(bytes.to_vec())
+    }
+}
+
+impl From<String> for Body {
+    fn from(s: String) -> Body {
+        Body::Bytes(s.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(s: &str) -> Body {
+        Body::Bytes(s.as_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn temp_file(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("http-body-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_len() {
+        assert_eq!(Body::Empty.len(), Some(0));
+        assert!(Body::from("").is_empty());
+        assert_eq!(Body::from("abc").len(), Some(3));
+        assert_eq!(Body::reader(Cursor::new("abc")).len(), None);
+        assert_eq!(Body::chunks(vec![b"a".to_vec()]).len(), None);
+        assert_eq!(Body::from(b"ab").as_bytes(), Some(&b"ab"[..]));
+        assert_eq!(Body::reader(Cursor::new("abc")).as_bytes(), None);
+    }
+
+    #[test]
+    fn test_write_to() {
+        let bodies = [
+            Body::from("hello"),
+            Body::reader(Cursor::new("hello")),
+            Body::chunks(vec![b"he".to_vec(), vec![], b"llo".to_vec()]),
+        ];
+        for body in bodies {
+            let mut out = Vec::new();
+            assert_eq!(body.write_to(&mut out).unwrap(), 5);
+            assert_eq!(out, b"hello");
+        }
+        assert_eq!(Body::Empty.into_bytes().unwrap(), b"");
+    }
+
+    #[test]
+    fn test_into_reader() {
+        let bodies = [
+            Body::Empty,
+            Body::from("hello"),
+            Body::reader(Cursor::new("hello")),
+            Body::chunks(vec![b"he".to_vec(), vec![], b"l".to_vec(), b"lo".to_vec()]),
+        ];
+        for body in bodies {
+            let expected = if body.is_empty() { "" } else { "hello" };
+            let mut out = String::new();
+            body.into_reader().read_to_string(&mut out).unwrap();
+            assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn test_file() {
+        let path = temp_file("file", b"file contents");
+        let body = Body::file(File::open(&path).unwrap()).unwrap();
+        assert_eq!(body.len(), Some(13));
+        assert_eq!(format!("{:?}", body), "File(13 bytes)");
+        assert_eq!(body.into_bytes().unwrap(), b"file contents");
+        std::fs::remove_file(path).unwrap();
+
+        let dir = File::open(std::env::temp_dir()).unwrap();
+        assert_eq!(Body::file(dir).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_eq() {
+        assert_eq!(Body::Empty, Body::from(""));
+        assert_eq!(Body::from("a"), Body::from(b"a".to_vec()));
+        assert_ne!(Body::from("a"), Body::from("b"));
+        assert_ne!(Body::reader(Cursor::new("")), Body::reader(Cursor::new("")));
+    }
+}
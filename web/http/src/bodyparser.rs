@@ -0,0 +1,768 @@
+// 按 Content-Type 解析请求体
+//
+// application/x-www-form-urlencoded 解析为 `QueryMap`；multipart/form-data (RFC 7578)
+// 解析为 `Multipart`；JSON 用 serde 反序列化。
+//
+// 连接上的大小限制由 `Parser::max_body` 在请求体到达之前检查；这里的 max_size
+// 是可以更小的限制，超过时返回 BodyError::TooLarge。
+//
+// multipart 用增量式的 `MultipartDecoder` 解析：用 `Parser::body_parser` 设置后，
+// 解析器在读取请求体时把收到的字节交给它，文件部分超过 memory_threshold 后
+// 一段一段写入临时文件，连接不需要把整个请求体留在内存中。没有这样设置时，
+// `BodyParser::multipart` 用同一个解码器解析已经读入内存的 `msg_body`。
+use crate::headermap::{ContentType, HeaderMap};
+use crate::httpparser::{parse_header, MAX_HEAD};
+use crate::httprequest::HttpRequest;
+use crate::resource::QueryMap;
+use crate::statuscode::StatusCode;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug)]
+pub enum BodyError {
+    UnsupportedMediaType,    // Content-Type 不是期望的类型
+    TooLarge,                // 超过 max_size 或 max_parts
+    Form,                    // 表单不是 UTF-8
+    Json(String),            // serde_json 的错误信息
+    Multipart(&'static str), // multipart 格式错误
+    Io(io::Error),           // 写临时文件出错
+}
+
+impl BodyError {
+    // 应答这个错误时使用的状态码
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            BodyError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            BodyError::TooLarge => StatusCode::CONTENT_TOO_LARGE,
+            BodyError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BodyError::UnsupportedMediaType => write!(f, "unsupported media type"),
+            BodyError::TooLarge => write!(f, "request body too large"),
+            BodyError::Form => write!(f, "form body is not UTF-8"),
+            BodyError::Json(e) => write!(f, "invalid JSON body: {}", e),
+            BodyError::Multipart(e) => write!(f, "invalid multipart body: {}", e),
+            BodyError::Io(e) => write!(f, "failed to store upload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BodyError {}
+
+impl From<io::Error> for BodyError {
+    fn from(e: io::Error) -> BodyError {
+        BodyError::Io(e)
+    }
+}
+
+// 解析的配置
+#[derive(Debug, Clone)]
+pub struct BodyParser {
+    max_size: usize,         // 请求体的最大字节数，在连接的限制之外再检查一次
+    max_parts: usize,        // multipart 的最大部分数
+    memory_threshold: usize, // 更大的文件部分复制到临时文件
+    temp_dir: PathBuf,
+}
+
+impl Default for BodyParser {
+    fn default() -> Self {
+        BodyParser {
+            max_size: 1024 * 1024,
+            max_parts: 100,
+            memory_threshold: 64 * 1024,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+}
+
+impl BodyParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn max_parts(mut self, max_parts: usize) -> Self {
+        self.max_parts = max_parts;
+        self
+    }
+
+    pub fn memory_threshold(mut self, memory_threshold: usize) -> Self {
+        self.memory_threshold = memory_threshold;
+        self
+    }
+
+    pub fn temp_dir(mut self, temp_dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = temp_dir.into();
+        self
+    }
+
+    pub fn form(&self, req: &HttpRequest) -> Result<QueryMap, BodyError> {
+        self.check(req, |mime| mime == "application/x-www-form-urlencoded")?;
+        let body = std::str::from_utf8(&req.msg_body).map_err(|_| BodyError::Form)?;
+        Ok(QueryMap::parse(body))
+    }
+
+    // application/json 或 `+json` 结尾的类型
+    pub fn json<T: DeserializeOwned>(&self, req: &HttpRequest) -> Result<T, BodyError> {
+        self.check(req, |mime| mime == "application/json" || mime.ends_with("+json"))?;
+        serde_json::from_slice(&req.msg_body).map_err(|e| BodyError::Json(e.to_string()))
+    }
+
+    // 解析已经在 `msg_body` 中的请求体
+    pub fn multipart(&self, req: &HttpRequest) -> Result<Multipart, BodyError> {
+        let content_type = self.check(req, |mime| mime == "multipart/form-data")?;
+        let mut decoder = MultipartDecoder::new(self.clone(), &content_type)?;
+        decoder.feed(&req.msg_body)?;
+        decoder.finish()
+    }
+
+    // 检查 Content-Type 和大小；请求体已经读完，所以只能拒绝，不能少读
+    fn check(&self, req: &HttpRequest, mime: impl Fn(&str) -> bool) -> Result<ContentType, BodyError> {
+        let content_type = req
+            .headers
+            .content_type()
+            .filter(|t| mime(&t.mime))
+            .ok_or(BodyError::UnsupportedMediaType)?;
+        if req.msg_body.len() > self.max_size {
+            return Err(BodyError::TooLarge);
+        }
+        Ok(content_type)
+    }
+}
+
+// multipart/form-data 的增量解析：每次 `feed` 传入请求体新到达的字节，
+// `finish` 在请求体结束后返回全部部分
+#[derive(Debug)]
+pub struct MultipartDecoder {
+    parser: BodyParser,
+    delimiter: Vec<u8>, // `CRLF--boundary`
+    buf: Vec<u8>,       // 还没有处理的字节，最多是一个部分的头部
+    state: State,
+    size: usize, // 收到的总字节数
+    parts: Vec<Part>,
+}
+
+#[derive(Debug)]
+enum State {
+    Preamble,                 // 第一个分隔行之前的前言，被忽略
+    BoundaryLine,             // 分隔行剩下的部分：`--`，或者可选的空白和 CRLF
+    Headers,                  // 一个部分的头部和空行
+    Data(Part, Option<File>), // 部分的内容；在临时文件中时，它打开的文件
+    Done,                     // 结束分隔行之后的尾声，被忽略
+}
+
+impl MultipartDecoder {
+    pub fn new(parser: BodyParser, content_type: &ContentType) -> Result<Self, BodyError> {
+        let boundary = content_type
+            .param("boundary")
+            .filter(|b| (1..=70).contains(&b.len()))
+            .ok_or(BodyError::Multipart("missing boundary"))?;
+        Ok(MultipartDecoder {
+            parser,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // 请求体可以直接以分隔行开始，前面补上 CRLF 就和其他分隔行一样
+            buf: b"\r\n".to_vec(),
+            state: State::Preamble,
+            size: 0,
+            parts: Vec::new(),
+        })
+    }
+
+    // 处理新到达的字节；出错后不能再使用
+    pub fn feed(&mut self, data: &[u8]) -> Result<(), BodyError> {
+        self.size += data.len();
+        if self.size > self.parser.max_size {
+            return Err(BodyError::TooLarge);
+        }
+        if matches!(self.state, State::Done) {
+            return Ok(());
+        }
+        self.buf.extend_from_slice(data);
+        let mut pos = 0;
+        let result = self.decode(&mut pos);
+        self.buf.drain(..pos);
+        result
+    }
+
+    // 请求体结束时调用
+    pub fn finish(self) -> Result<Multipart, BodyError> {
+        match self.state {
+            State::Done => Ok(Multipart { parts: self.parts }),
+            State::Preamble => Err(BodyError::Multipart("missing boundary line")),
+            State::BoundaryLine => Err(BodyError::Multipart("unterminated boundary line")),
+            State::Headers | State::Data(..) => Err(BodyError::Multipart("unterminated part")),
+        }
+    }
+
+    // 从 `buf[pos..]` 开始处理，直到需要更多字节；`pos` 之前的字节之后被删除
+    fn decode(&mut self, pos: &mut usize) -> Result<(), BodyError> {
+        // 分隔符可能被分在两次到达的字节中，没有找到时留下可能是它开头的部分
+        let keep = self.delimiter.len() - 1;
+        loop {
+            let rest = &self.buf[*pos..];
+            match &mut self.state {
+                State::Preamble => match find(rest, &self.delimiter) {
+                    Some(i) => {
+                        *pos += i + self.delimiter.len();
+                        self.state = State::BoundaryLine;
+                    }
+                    None => {
+                        *pos += rest.len().saturating_sub(keep);
+                        return Ok(());
+                    }
+                },
+                State::BoundaryLine => {
+                    if rest.len() < 2 {
+                        return Ok(());
+                    }
+                    if rest.starts_with(b"--") {
+                        self.state = State::Done;
+                        *pos = self.buf.len();
+                        return Ok(());
+                    }
+                    let Some(end) = find(rest, b"\r\n") else {
+                        if !rest.iter().all(|&b| b" \t\r".contains(&b)) {
+                            return Err(BodyError::Multipart("invalid boundary line"));
+                        }
+                        return Ok(());
+                    };
+                    if !rest[..end].iter().all(|&b| b == b' ' || b == b'\t') {
+                        return Err(BodyError::Multipart("invalid boundary line"));
+                    }
+                    if self.parts.len() == self.parser.max_parts {
+                        return Err(BodyError::TooLarge);
+                    }
+                    *pos += end + 2;
+                    self.state = State::Headers;
+                }
+                State::Headers => {
+                    // 没有头部时空行紧跟在分隔行之后
+                    let head_end = if rest.starts_with(b"\r\n") { Some(0) } else { find(rest, b"\r\n\r\n") };
+                    let next = find(rest, &self.delimiter);
+                    // 头部在下一个分隔符之前结束
+                    let end = match (head_end, next) {
+                        (Some(end), None) => end,
+                        (Some(end), Some(next)) if end < next => end,
+                        (_, Some(_)) => return Err(BodyError::Multipart("unterminated part headers")),
+                        (None, None) if rest.len() > MAX_HEAD => return Err(BodyError::TooLarge),
+                        (None, None) => return Ok(()),
+                    };
+                    let part = part(&rest[..end])?;
+                    *pos += if end == 0 { 2 } else { end + 4 };
+                    self.state = State::Data(part, None);
+                }
+                State::Data(..) => {
+                    let (end, next) = match find(rest, &self.delimiter) {
+                        Some(i) => (i, Some(i + self.delimiter.len())),
+                        None => (rest.len().saturating_sub(keep), None),
+                    };
+                    let start = *pos;
+                    self.write(start, start + end)?;
+                    let Some(next) = next else {
+                        *pos += end;
+                        return Ok(());
+                    };
+                    *pos += next;
+                    if let State::Data(part, _) = std::mem::replace(&mut self.state, State::BoundaryLine) {
+                        self.parts.push(part);
+                    }
+                }
+                State::Done => return Ok(()),
+            }
+        }
+    }
+
+    // 把 `buf[start..end]` 加到当前部分；文件部分超过 memory_threshold 时
+    // 把已有的内容写入新的临时文件，之后的内容直接写入文件
+    fn write(&mut self, start: usize, end: usize) -> Result<(), BodyError> {
+        let data = &self.buf[start..end];
+        let State::Data(part, file) = &mut self.state else {
+            unreachable!();
+        };
+        let spill = match &part.data {
+            PartData::Memory(bytes) => part.filename.is_some() && bytes.len() + data.len() > self.parser.memory_threshold,
+            PartData::File(_) => false,
+        };
+        if spill {
+            let (temp, opened) = TempFile::create(&self.parser.temp_dir)?;
+            let PartData::Memory(bytes) = std::mem::replace(&mut part.data, PartData::File(temp)) else {
+                unreachable!();
+            };
+            *file = Some(opened);
+            write_file(part, file, &bytes)?;
+        }
+        match &mut part.data {
+            PartData::Memory(bytes) => bytes.extend_from_slice(data),
+            PartData::File(_) => write_file(part, file, data)?,
+        }
+        Ok(())
+    }
+}
+
+fn write_file(part: &mut Part, file: &mut Option<File>, data: &[u8]) -> io::Result<()> {
+    let (PartData::File(temp), Some(file)) = (&mut part.data, file) else {
+        unreachable!();
+    };
+    file.write_all(data)?;
+    temp.len += data.len() as u64;
+    Ok(())
+}
+
+// 一个部分的头部，不含最后的空行
+fn part(head: &[u8]) -> Result<Part, BodyError> {
+    let headers = head
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| parse_header(line.strip_suffix(b"\r").unwrap_or(line)))
+        .collect::<Result<HeaderMap, _>>()
+        .map_err(|_| BodyError::Multipart("invalid part header"))?;
+    let (kind, params) = parse_disposition(headers.get("Content-Disposition").unwrap_or(""));
+    let param = |name: &str| params.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+    let name = param("name")
+        .filter(|_| kind.eq_ignore_ascii_case("form-data"))
+        .ok_or(BodyError::Multipart("missing form-data name"))?;
+    // 客户端可能发送完整路径，只保留文件名
+    let filename = param("filename").map(|f| f.rsplit(['/', '\\']).next().unwrap().to_string());
+    Ok(Part {
+        name,
+        filename,
+        content_type: headers.get("Content-Type").map(String::from),
+        data: PartData::Memory(Vec::new()),
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// `form-data; name="a"; filename="b.txt"`：类型和参数，参数名小写，值去掉引号和转义
+fn parse_disposition(s: &str) -> (String, Vec<(String, String)>) {
+    let (kind, mut rest) = s.split_once(';').unwrap_or((s, ""));
+    let mut params = Vec::new();
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        let Some((name, value)) = rest.split_once('=') else {
+            break;
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let value = value.trim_start();
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let mut out = String::new();
+                let mut end = quoted.len();
+                let mut chars = quoted.char_indices();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => out.extend(chars.next().map(|(_, c)| c)),
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => out.push(c),
+                    }
+                }
+                (out, &quoted[end..])
+            }
+            None => {
+                let end = value.find(';').unwrap_or(value.len());
+                (value[..end].trim_end().to_string(), &value[end..])
+            }
+        };
+        params.push((name, value));
+        rest = next;
+    }
+    (kind.trim().to_string(), params)
+}
+
+// 解析后的 multipart/form-data，按收到的顺序
+#[derive(Debug)]
+pub struct Multipart {
+    parts: Vec<Part>,
+}
+
+impl Multipart {
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    // 第一个同名的部分
+    pub fn get(&self, name: &str) -> Option<&Part> {
+        self.parts.iter().find(|p| p.name == name)
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Part> + 'a {
+        self.parts.iter().filter(move |p| p.name == name)
+    }
+
+    // 普通字段的文本值
+    pub fn text(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(Part::text)
+    }
+
+    // 带文件名的部分
+    pub fn files(&self) -> impl Iterator<Item = &Part> {
+        self.parts.iter().filter(|p| p.filename.is_some())
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Part> {
+        self.parts.iter()
+    }
+}
+
+impl IntoIterator for Multipart {
+    type Item = Part;
+    type IntoIter = std::vec::IntoIter<Part>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.parts.into_iter()
+    }
+}
+
+#[derive(Debug)]
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>, // 只有最后一个路径分量
+    pub content_type: Option<String>,
+    pub data: PartData,
+}
+
+#[derive(Debug)]
+pub enum PartData {
+    Memory(Vec<u8>),
+    File(TempFile),
+}
+
+impl Part {
+    pub fn len(&self) -> u64 {
+        match &self.data {
+            PartData::Memory(bytes) => bytes.len() as u64,
+            PartData::File(file) => file.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 在内存中并且是 UTF-8 时的文本
+    pub fn text(&self) -> Option<&str> {
+        match &self.data {
+            PartData::Memory(bytes) => std::str::from_utf8(bytes).ok(),
+            PartData::File(_) => None,
+        }
+    }
+
+    // 全部内容，在临时文件中时读出
+    pub fn bytes(&self) -> io::Result<Vec<u8>> {
+        match &self.data {
+            PartData::Memory(bytes) => Ok(bytes.clone()),
+            PartData::File(file) => fs::read(file.path()),
+        }
+    }
+}
+
+// 上传的临时文件，drop 时删除，除非已经用 persist 移走
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+    len: u64,
+}
+
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+impl TempFile {
+    // 新的空文件和打开的写入句柄
+    fn create(dir: &Path) -> io::Result<(TempFile, File)> {
+        let n = TEMP_FILES.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("http-upload-{}-{}", std::process::id(), n));
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        Ok((TempFile { path, len: 0 }, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+
+    // 移动到 `to`，之后不再删除；需要和临时目录在同一个文件系统上
+    pub fn persist(mut self, to: impl AsRef<Path>) -> io::Result<()> {
+        fs::rename(&self.path, to)?;
+        self.path = PathBuf::new();
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.path.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::httpparser::{Parsed, Parser};
+    use serde::Deserialize;
+
+    fn request(content_type: &str, body: &[u8]) -> HttpRequest {
+        let mut raw = format!(
+            "POST /upload HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            content_type,
+            body.len()
+        )
+        .into_bytes();
+        raw.extend_from_slice(body);
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(&raw), Parsed::Complete(raw.len()));
+        parser.take().unwrap()
+    }
+
+    const MULTIPART: &str = "preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        hello world\r\n\
+        --XyZ \r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"C:\\\\docs\\\\a \\\"b\\\";.txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        line 1\r\nline 2\r\n\
+        --XyZ\r\n\
+        content-disposition: form-data; name=tags\r\n\
+        \r\n\
+        \r\n\
+        --XyZ--\r\n\
+        epilogue";
+
+    #[test]
+    fn test_form() {
+        let req = request("application/x-www-form-urlencoded", b"a=1&b=x+y&a=%C3%A9");
+        let form = req.form().unwrap();
+        assert_eq!(form.get_all("a").collect::<Vec<_>>(), ["1", "\u{e9}"]);
+        assert_eq!(form.get("b"), Some("x y"));
+
+        let req = request("text/plain", b"a=1");
+        assert!(matches!(req.form(), Err(BodyError::UnsupportedMediaType)));
+        let req = request("application/x-www-form-urlencoded", b"a=\xff");
+        assert!(matches!(req.form(), Err(BodyError::Form)));
+        let req = request("application/x-www-form-urlencoded", b"a=12345");
+        let err = BodyParser::new().max_size(4).form(&req).unwrap_err();
+        assert!(matches!(err, BodyError::TooLarge));
+        assert_eq!(err.status_code(), StatusCode::CONTENT_TOO_LARGE);
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Order {
+        order_id: i32,
+        items: Vec<String>,
+    }
+
+    #[test]
+    fn test_json() {
+        let req = request("application/json; charset=utf-8", br#"{"order_id": 7, "items": ["a"]}"#);
+        let order: Order = req.json().unwrap();
+        assert_eq!(order, Order { order_id: 7, items: vec![String::from("a")] });
+        let req = request("application/merge-patch+json", br#"{"order_id": 8, "items": []}"#);
+        assert_eq!(req.json::<Order>().unwrap().order_id, 8);
+
+        let req = request("application/json", br#"{"order_id": "7"}"#);
+        let err = req.json::<Order>().unwrap_err();
+        assert!(matches!(&err, BodyError::Json(e) if e.contains("invalid type")), "{}", err);
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        let req = request("text/json", b"{}");
+        assert!(matches!(req.json::<Order>(), Err(BodyError::UnsupportedMediaType)));
+    }
+
+    #[test]
+    fn test_multipart() {
+        let mut req = request("multipart/form-data; boundary=\"XyZ\"", MULTIPART.as_bytes());
+        let form = req.multipart().unwrap();
+        assert_eq!(form.len(), 3);
+        assert_eq!(form.text("title"), Some("hello world"));
+        assert_eq!(form.text("tags"), Some(""));
+        let file = form.get("file").unwrap();
+        assert_eq!(file.filename.as_deref(), Some("a \"b\";.txt"));
+        assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(file.bytes().unwrap(), b"line 1\r\nline 2");
+        assert_eq!(form.files().count(), 1);
+        assert!(form.get("title").unwrap().content_type.is_none());
+    }
+
+    #[test]
+    fn test_multipart_to_disk() {
+        let req = request("multipart/form-data; boundary=XyZ", MULTIPART.as_bytes());
+        let form = BodyParser::new().memory_threshold(8).multipart(&req).unwrap();
+        // 只有文件写入磁盘
+        assert_eq!(form.text("title"), Some("hello world"));
+        let file = form.get("file").unwrap();
+        let PartData::File(temp) = &file.data else {
+            panic!("{:?}", file.data);
+        };
+        let path = temp.path().to_path_buf();
+        assert_eq!(fs::read(&path).unwrap(), b"line 1\r\nline 2");
+        assert_eq!(file.len(), 14);
+        assert_eq!(file.bytes().unwrap(), b"line 1\r\nline 2");
+        drop(form);
+        assert!(!path.exists());
+
+        let form = BodyParser::new().memory_threshold(8).multipart(&req).unwrap();
+        let target = std::env::temp_dir().join(format!("bodyparser-persist-{}", std::process::id()));
+        for part in form {
+            if let PartData::File(temp) = part.data {
+                temp.persist(&target).unwrap();
+            }
+        }
+        assert_eq!(fs::read(&target).unwrap(), b"line 1\r\nline 2");
+        fs::remove_file(target).unwrap();
+    }
+
+    // 用 `Parser::body_parser` 解析 `raw`，每次送入 `piece` 个字节，
+    // 读取之前删除交出去的字节；返回请求和缓冲区最大的长度
+    fn stream(raw: &[u8], piece: usize, body_parser: BodyParser) -> (HttpRequest, usize) {
+        let mut parser = Parser::new().body_parser(body_parser);
+        let mut buf = Vec::new();
+        let mut max_buf = 0;
+        for piece in raw.chunks(piece) {
+            parser.discard_body(&mut buf);
+            buf.extend_from_slice(piece);
+            max_buf = max_buf.max(buf.len());
+            if let Parsed::Complete(n) = parser.parse(&buf) {
+                assert_eq!(n, buf.len());
+                return (parser.take().unwrap(), max_buf);
+            }
+        }
+        panic!("incomplete request");
+    }
+
+    fn upload(head: &str, body: &[u8]) -> Vec<u8> {
+        let mut raw = format!("POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=XyZ\r\n{}\r\n", head)
+            .into_bytes();
+        raw.extend_from_slice(body);
+        raw
+    }
+
+    // 分隔符被分在两段中也能找到，结果和一次解析相同
+    #[test]
+    fn test_multipart_pieces() {
+        let raw = upload(&format!("Content-Length: {}\r\n", MULTIPART.len()), MULTIPART.as_bytes());
+        for piece in 1..=MULTIPART.len() / 4 {
+            let (mut req, _) = stream(&raw, piece, BodyParser::new().memory_threshold(8));
+            assert!(req.msg_body.is_empty());
+            let form = req.multipart().unwrap();
+            assert_eq!(form.len(), 3);
+            assert_eq!(form.text("title"), Some("hello world"));
+            assert_eq!(form.text("tags"), Some(""));
+            let file = form.get("file").unwrap();
+            assert!(matches!(file.data, PartData::File(_)), "{}", piece);
+            assert_eq!(file.bytes().unwrap(), b"line 1\r\nline 2");
+        }
+
+        let (mut req, _) = stream(&upload("Content-Length: 4\r\n", b"--b\r"), 1, BodyParser::new());
+        assert!(matches!(req.multipart(), Err(BodyError::Multipart("missing boundary line"))));
+        let (mut req, _) = stream(&upload("Content-Length: 7\r\n", b"--XyZ\r\n"), 2, BodyParser::new());
+        assert!(matches!(req.multipart(), Err(BodyError::Multipart("unterminated part"))));
+    }
+
+    // 比 memory_threshold 大得多的文件一段一段地写入临时文件，连接的缓冲区
+    // 和内存中的部分都不会变大；chunked 的请求体也一样
+    #[test]
+    fn test_multipart_streamed_to_disk() {
+        let data: Vec<u8> = (0..=255).cycle().take(200_000).collect();
+        let mut body = b"--XyZ\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nbig file\r\n\
+            --XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"data.bin\"\r\n\r\n"
+            .to_vec();
+        body.extend_from_slice(&data);
+        body.extend_from_slice(b"\r\n--XyZ--\r\n");
+        let body_parser = BodyParser::new().memory_threshold(1024);
+
+        let mut chunked = Vec::new();
+        for chunk in body.chunks(3000) {
+            chunked.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+            chunked.extend_from_slice(chunk);
+            chunked.extend_from_slice(b"\r\n");
+        }
+        chunked.extend_from_slice(b"0\r\n\r\n");
+        for raw in [
+            upload(&format!("Content-Length: {}\r\n", body.len()), &body),
+            upload("Transfer-Encoding: chunked\r\n", &chunked),
+        ] {
+            let (mut req, max_buf) = stream(&raw, 4096, body_parser.clone());
+            assert!(max_buf < 2 * 4096 + 200, "{}", max_buf);
+            let form = req.multipart().unwrap();
+            assert_eq!(form.text("note"), Some("big file"));
+            let file = form.get("file").unwrap();
+            let PartData::File(temp) = &file.data else {
+                panic!("{:?}", file.data);
+            };
+            assert_eq!(temp.len(), data.len() as u64);
+            assert_eq!(fs::read(temp.path()).unwrap(), data);
+        }
+
+        // 超过 max_size 时不再写入，结果是错误
+        let raw = upload(&format!("Content-Length: {}\r\n", body.len()), &body);
+        let (mut req, _) = stream(&raw, 4096, body_parser.max_size(100_000));
+        assert!(matches!(req.multipart(), Err(BodyError::TooLarge)));
+    }
+
+    #[test]
+    fn test_multipart_errors() {
+        let multipart = |content_type: &str, body: &str| request(content_type, body.as_bytes()).multipart();
+        let error = |content_type: &str, body: &str| match multipart(content_type, body) {
+            Err(BodyError::Multipart(e)) => e,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(error("multipart/form-data", MULTIPART), "missing boundary");
+        assert_eq!(error("multipart/form-data; boundary=other", MULTIPART), "missing boundary line");
+        let part = "--b\r\nContent-Disposition: form-data; name=a\r\n\r\nx";
+        assert_eq!(error("multipart/form-data; boundary=b", part), "unterminated part");
+        assert_eq!(error("multipart/form-data; boundary=b", "--b\r\n\r\nx\r\n--b--"), "missing form-data name");
+        let attachment = "--b\r\nContent-Disposition: attachment; name=a\r\n\r\nx\r\n--b--";
+        assert_eq!(error("multipart/form-data; boundary=b", attachment), "missing form-data name");
+        assert_eq!(error("multipart/form-data; boundary=b", "--bx\r\n\r\n--b--"), "invalid boundary line");
+        assert_eq!(error("multipart/form-data; boundary=b", "--b\r\nbad\r\n\r\nx\r\n--b--"), "invalid part header");
+        assert!(multipart("multipart/form-data; boundary=b", "--b--").unwrap().is_empty());
+
+        let req = request("multipart/form-data; boundary=XyZ", MULTIPART.as_bytes());
+        assert!(matches!(BodyParser::new().max_parts(2).multipart(&req), Err(BodyError::TooLarge)));
+        assert!(matches!(multipart("multipart/mixed; boundary=b", "--b--"), Err(BodyError::UnsupportedMediaType)));
+    }
+
+    #[test]
+    fn test_parse_disposition() {
+        let (kind, params) = parse_disposition("form-data; NAME=\"a;b\" ; filename=c.txt");
+        assert_eq!(kind, "form-data");
+        assert_eq!(params, [("name".to_string(), "a;b".to_string()), ("filename".to_string(), "c.txt".to_string())]);
+        assert_eq!(parse_disposition("inline"), (String::from("inline"), vec![]));
+    }
+}
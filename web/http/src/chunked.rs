@@ -0,0 +1,313 @@
+// chunked 传输编码 (RFC 9112 7.1)
+//
+// `ChunkedDecoder` 和 `Parser` 一样是增量式的：每次传入请求体目前收到的全部字节，
+// 它记住解析到的位置；用 `discard` 可以丢掉已经处理的字节。`ChunkedWriter` 把写入的数据按块发送，用于长度未知的响应体。
+use crate::headermap::HeaderMap;
+use crate::httpparser::{parse_header, ParseError, MAX_HEAD};
+use std::io::{self, Write};
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Size,        // 等待 `size[;ext]` 行
+    Data(usize), // 块中还没有收到的字节数
+    DataEnd,     // 块后的 CRLF
+    Trailers,    // 最后一个块之后的尾部字段
+    Done,
+}
+
+#[derive(Debug, Default)]
+pub struct ChunkedDecoder {
+    pos: usize, // 下一个没有处理的字节
+    state: State,
+    body: Vec<u8>,
+    len: usize, // 解码后的总字节数，包括已经用 take_body 取走的
+    trailers: HeaderMap,
+    trailer_start: usize,
+    max_body: Option<usize>,
+}
+
+impl ChunkedDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 解码后请求体的最大字节数：块大小一到就检查，不等数据到达
+    pub fn max_body(mut self, max_body: usize) -> Self {
+        self.max_body = Some(max_body);
+        self
+    }
+
+    // `buf` 从请求体的第一个字节开始；完成时返回 chunked 数据占用的字节数
+    pub fn decode(&mut self, buf: &[u8]) -> Result<Option<usize>, ParseError> {
+        loop {
+            match self.state {
+                State::Size => {
+                    let Some(line) = self.line(buf)? else {
+                        return Ok(None);
+                    };
+                    self.state = match chunk_size(line)? {
+                        0 => {
+                            self.trailer_start = self.pos;
+                            State::Trailers
+                        }
+                        size if self.max_body.is_some_and(|max| size > max - self.len) => {
+                            return Err(ParseError::BodyTooLarge);
+                        }
+                        size => State::Data(size),
+                    };
+                }
+                State::Data(left) => {
+                    let n = left.min(buf.len() - self.pos);
+                    self.body.extend_from_slice(&buf[self.pos..self.pos + n]);
+                    self.pos += n;
+                    self.len += n;
+                    if n < left {
+                        self.state = State::Data(left - n);
+                        return Ok(None);
+                    }
+                    self.state = State::DataEnd;
+                }
+                State::DataEnd => match self.line(buf)? {
+                    Some([]) => self.state = State::Size,
+                    Some(_) => return Err(ParseError::Chunked),
+                    None => return Ok(None),
+                },
+                State::Trailers => {
+                    let Some(line) = self.line(buf)? else {
+                        return Ok(None);
+                    };
+                    if self.pos - self.trailer_start > MAX_HEAD {
+                        return Err(ParseError::TooLarge);
+                    }
+                    if line.is_empty() {
+                        self.state = State::Done;
+                    } else {
+                        let (name, value) = parse_header(line)?;
+                        self.trailers.append(name, value)?;
+                    }
+                }
+                State::Done => return Ok(Some(self.pos)),
+            }
+        }
+    }
+
+    // 解码后、还没有取走的请求体；可以在完成之前多次调用，一段一段地取
+    pub fn take_body(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.body)
+    }
+
+    pub fn take_trailers(&mut self) -> HeaderMap {
+        std::mem::take(&mut self.trailers)
+    }
+
+    // 已经处理、不再需要的字节数。调用者从 `buf` 的开头删除这么多字节，
+    // 之后传入的 `buf` 从剩下的字节开始；尾部字段要保留到结束，用来检查大小
+    pub fn discard(&mut self) -> usize {
+        let n = match self.state {
+            State::Trailers | State::Done => self.trailer_start,
+            _ => self.pos,
+        };
+        self.pos -= n;
+        self.trailer_start = self.trailer_start.saturating_sub(n);
+        n
+    }
+
+    // 下一行，不含 CRLF 或 LF；还没有收到完整的行时返回 None
+    fn line<'b>(&mut self, buf: &'b [u8]) -> Result<Option<&'b [u8]>, ParseError> {
+        let Some(end) = buf[self.pos..].iter().position(|&b| b == b'\n') else {
+            if buf.len() - self.pos > MAX_HEAD {
+                return Err(ParseError::TooLarge);
+            }
+            return Ok(None);
+        };
+        let line = &buf[self.pos..self.pos + end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        self.pos += end + 1;
+        if line.contains(&b'\r') {
+            return Err(ParseError::LineEnding);
+        }
+        Ok(Some(line))
+    }
+}
+
+// `1a;name=value` 中的十六进制长度，扩展被忽略
+fn chunk_size(line: &[u8]) -> Result<usize, ParseError> {
+    let end = line.iter().position(|&b| b == b';').unwrap_or(line.len());
+    let size = line[..end].trim_ascii_end();
+    // 最多 15 位，不会溢出
+    if size.is_empty() || size.len() > 15 || !size.iter().all(u8::is_ascii_hexdigit) {
+        return Err(ParseError::Chunked);
+    }
+    let size = std::str::from_utf8(size).unwrap();
+    usize::from_str_radix(size, 16).map_err(|_| ParseError::Chunked)
+}
+
+// 每次 write 发送一个块，finish 发送最后的空块
+#[derive(Debug)]
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ChunkedWriter { inner }
+    }
+
+    // 发送最后的空块和尾部字段，返回内部的写入流
+    pub fn finish(self, trailers: &HeaderMap) -> io::Result<W> {
+        let mut inner = self.inner;
+        write!(inner, "0\r\n{}\r\n", trailers)?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // 空块表示结束，所以不发送
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.inner, "{:x}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(buf: &[u8]) -> Result<Option<(usize, Vec<u8>, HeaderMap)>, ParseError> {
+        let mut decoder = ChunkedDecoder::new();
+        Ok(decoder.decode(buf)?.map(|n| (n, decoder.take_body(), decoder.take_trailers())))
+    }
+
+    #[test]
+    fn test_decode() {
+        let (n, body, trailers) = decode(b"5\r\nhello\r\n1;ext=1\r\n \r\n0\r\n\r\nnext").unwrap().unwrap();
+        assert_eq!(n, 27);
+        assert_eq!(body, b"hello ");
+        assert!(trailers.is_empty());
+
+        let (_, body, _) = decode(b"A\nabcdefghij\n0\n\n").unwrap().unwrap();
+        assert_eq!(body, b"abcdefghij");
+        let (_, body, _) = decode(b"0\r\n\r\n").unwrap().unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn test_trailers() {
+        let (_, body, trailers) = decode(b"3\r\n\x00\r\n\r\n0\r\nChecksum: abc\r\nX-A: 1\r\n\r\n").unwrap().unwrap();
+        assert_eq!(body, b"\x00\r\n");
+        assert_eq!(trailers.get("checksum"), Some("abc"));
+        assert_eq!(trailers.get("x-a"), Some("1"));
+    }
+
+    #[test]
+    fn test_incremental() {
+        let raw = b"4\r\nWiki\r\n5\r\npedia\r\n0\r\nT: 1\r\n\r\n";
+        let mut decoder = ChunkedDecoder::new();
+        for end in 0..raw.len() {
+            assert_eq!(decoder.decode(&raw[..end]), Ok(None), "{}", end);
+        }
+        assert_eq!(decoder.decode(raw), Ok(Some(raw.len())));
+        assert_eq!(decoder.take_body(), b"Wikipedia");
+        assert_eq!(decoder.take_trailers().get("T"), Some("1"));
+    }
+
+    // 丢掉处理过的字节，一段一段取出请求体，最大长度照样检查
+    #[test]
+    fn test_discard() {
+        let raw = b"4\r\nWiki\r\n5\r\npedia\r\n0\r\nT: 1\r\n\r\n";
+        let mut decoder = ChunkedDecoder::new().max_body(9);
+        let mut buf = Vec::new();
+        let mut body = Vec::new();
+        for &b in raw {
+            buf.push(b);
+            let done = decoder.decode(&buf).unwrap();
+            body.extend(decoder.take_body());
+            if let Some(n) = done {
+                assert_eq!(n, buf.len());
+                break;
+            }
+            buf.drain(..decoder.discard());
+            assert!(buf.len() <= 8, "{:?}", buf);
+        }
+        assert_eq!(body, b"Wikipedia");
+        assert_eq!(decoder.take_trailers().get("T"), Some("1"));
+
+        let mut decoder = ChunkedDecoder::new().max_body(8);
+        assert_eq!(decoder.decode(b"5\r\nhello\r\n"), Ok(None));
+        decoder.take_body();
+        assert_eq!(decoder.discard(), 10);
+        assert_eq!(decoder.decode(b"4\r\n"), Err(ParseError::BodyTooLarge));
+    }
+
+    #[test]
+    fn test_errors() {
+        for raw in [
+            &b"x\r\n"[..],
+            b"\r\n",
+            b"-1\r\n",
+            b" 1\r\na\r\n0\r\n\r\n",
+            b"1000000000000000\r\n",
+            b"3\r\nabcd\r\n",
+            b"1\r\nab\r\n",
+        ] {
+            assert_eq!(decode(raw).err(), Some(ParseError::Chunked), "{:?}", raw);
+        }
+        assert_eq!(decode(b"1\ra\r\n").err(), Some(ParseError::LineEnding));
+        assert_eq!(decode(b"0\r\nbad trailer\r\n\r\n").err(), Some(ParseError::HeaderName));
+        assert_eq!(decode(&[b'1'; MAX_HEAD + 1]).err(), Some(ParseError::TooLarge));
+    }
+
+    // 块大小一到就检查，数据还没有收到
+    #[test]
+    fn test_max_body() {
+        let mut decoder = ChunkedDecoder::new().max_body(8);
+        assert_eq!(decoder.decode(b"5\r\nhello\r\n3\r\n"), Ok(None));
+        assert_eq!(decoder.decode(b"5\r\nhello\r\n3\r\nabc\r\n0\r\n\r\n"), Ok(Some(23)));
+
+        let mut decoder = ChunkedDecoder::new().max_body(8);
+        assert_eq!(decoder.decode(b"5\r\nhello\r\n4\r\n"), Err(ParseError::BodyTooLarge));
+        let mut decoder = ChunkedDecoder::new().max_body(8);
+        assert_eq!(decoder.decode(b"ffffffff\r\n"), Err(ParseError::BodyTooLarge));
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut writer = ChunkedWriter::new(Vec::new());
+        writer.write_all(b"hello").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(&[b'x'; 26]).unwrap();
+        let out = writer.finish(&HeaderMap::new()).unwrap();
+        assert_eq!(out, format!("5\r\nhello\r\n1a\r\n{}\r\n0\r\n\r\n", "x".repeat(26)).as_bytes());
+
+        let writer = ChunkedWriter::new(Vec::new());
+        let trailers: HeaderMap = [("Checksum", "abc")].into_iter().collect();
+        assert_eq!(writer.finish(&trailers).unwrap(), b"0\r\nChecksum: abc\r\n\r\n");
+    }
+
+    // 编码后再解码得到原来的数据
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let mut writer = ChunkedWriter::new(Vec::new());
+        for piece in data.chunks(777) {
+            writer.write_all(piece).unwrap();
+        }
+        let trailers: HeaderMap = [("X-Count", "10000")].into_iter().collect();
+        let encoded = writer.finish(&trailers).unwrap();
+        let (n, body, decoded_trailers) = decode(&encoded).unwrap().unwrap();
+        assert_eq!(n, encoded.len());
+        assert_eq!(body, data);
+        assert_eq!(decoded_trailers, trailers);
+    }
+}
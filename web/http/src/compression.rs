@@ -0,0 +1,377 @@
+// 响应压缩 (RFC 9110 8.4, 12.5.3)
+//
+// 按请求的 Accept-Encoding 选择 br、gzip 或 deflate。内存中的响应体直接压缩，
+// 长度仍然已知；文件和流在发送时边读边压缩，用 chunked 编码发送 (HTTP/1.0 时以关闭连接结束)。
+use crate::body::Body;
+use crate::headermap::HeaderMap;
+use crate::httpreponse::HttpResponse;
+use std::io::{Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate, // zlib 格式，HTTP 中的 deflate 就是它
+}
+
+// q 相同时按这个顺序选择
+const PREFERENCE: [Encoding; 3] = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+const BUFFER_SIZE: usize = 4096;
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    fn matches(&self, coding: &str) -> bool {
+        coding == self.as_str() || (*self == Encoding::Gzip && coding == "x-gzip")
+    }
+
+    fn encode(&self, bytes: &[u8]) -> Vec<u8> {
+        // 写入 Vec 不会出错
+        match self {
+            Encoding::Brotli => {
+                let mut writer =
+                    brotli::CompressorWriter::new(Vec::new(), BUFFER_SIZE, BROTLI_QUALITY, BROTLI_WINDOW);
+                writer.write_all(bytes).unwrap();
+                writer.into_inner()
+            }
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+            Encoding::Deflate => {
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+        }
+    }
+
+    fn encode_reader(&self, reader: Box<dyn Read + Send>) -> Body {
+        match self {
+            Encoding::Brotli => Body::reader(brotli::CompressorReader::new(
+                reader,
+                BUFFER_SIZE,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            )),
+            Encoding::Gzip => Body::reader(flate2::read::GzEncoder::new(reader, Default::default())),
+            Encoding::Deflate => Body::reader(flate2::read::ZlibEncoder::new(reader, Default::default())),
+        }
+    }
+}
+
+// Accept-Encoding 中的 `coding;q=0.5` 列表，coding 为小写；q 不合法的项被忽略
+pub fn parse_accept_encoding(value: &str) -> Vec<(String, f32)> {
+    value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let coding = parts.next()?.trim().to_ascii_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+            let mut q = 1.0;
+            for param in parts {
+                let (name, value) = param.split_once('=')?;
+                if name.trim().eq_ignore_ascii_case("q") {
+                    q = parse_qvalue(value.trim())?;
+                }
+            }
+            Some((coding, q))
+        })
+        .collect()
+}
+
+// `0`、`0.5`、`1.000` 等，最多三位小数
+fn parse_qvalue(s: &str) -> Option<f32> {
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    let valid = matches!(int, "0" | "1")
+        && frac.len() <= 3
+        && frac.bytes().all(|b| b.is_ascii_digit())
+        && (int == "0" || frac.bytes().all(|b| b == b'0'));
+    if !valid {
+        return None;
+    }
+    s.parse().ok()
+}
+
+// 选择 q 最大的编码；没有 Accept-Encoding、没有可接受的编码，
+// 或 identity 的 q 比所有编码都大时返回 None，即不压缩
+pub fn negotiate(accept_encoding: Option<&str>) -> Option<Encoding> {
+    let codings = parse_accept_encoding(accept_encoding?);
+    let q = |coding: &str| codings.iter().find(|(c, _)| c == coding).map(|(_, q)| *q);
+    let wildcard = q("*");
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in PREFERENCE {
+        let explicit = codings.iter().find(|(c, _)| encoding.matches(c)).map(|(_, q)| *q);
+        let q = explicit.or(wildcard).unwrap_or(0.0);
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((encoding, q));
+        }
+    }
+    // 只有明确列出的 identity 参与比较
+    let identity = q("identity").unwrap_or(0.0);
+    best.filter(|(_, q)| *q >= identity).map(|(encoding, _)| encoding)
+}
+
+// 压缩的配置：小于 min_size 的响应体和不在 mime_types 中的类型不压缩。
+// mime_types 中的 `text/*` 匹配所有 text 类型。
+#[derive(Debug, Clone)]
+pub struct Compression {
+    min_size: u64,
+    mime_types: Vec<String>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            min_size: 1024,
+            mime_types: [
+                "text/*",
+                "application/json",
+                "application/javascript",
+                "application/xml",
+                "image/svg+xml",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn mime_types<S: Into<String>>(mut self, mime_types: impl IntoIterator<Item = S>) -> Self {
+        self.mime_types = mime_types.into_iter().map(|m| m.into().to_ascii_lowercase()).collect();
+        self
+    }
+
+    fn compressible(&self, headers: &HeaderMap) -> bool {
+        let Some(content_type) = headers.content_type() else {
+            return false;
+        };
+        self.mime_types.iter().any(|m| match m.strip_suffix('*') {
+            Some(prefix) => content_type.mime.starts_with(prefix),
+            None => content_type.mime == *m,
+        })
+    }
+
+    // 按请求头压缩响应，返回使用的编码。
+    // 可以压缩的类型总是加上 `Vary: Accept-Encoding`，即使这次没有压缩；
+    // 已有 Content-Encoding 的响应不再处理。长度未知的流不受 min_size 限制。
+    pub fn apply(&self, request: &HeaderMap, response: &mut HttpResponse) -> Option<Encoding> {
+        let headers = response.headers();
+        if !response.status().allows_body()
+            || headers.contains_key("Content-Encoding")
+            || !self.compressible(headers)
+        {
+            return None;
+        }
+        let varies = headers
+            .get_all("Vary")
+            .flat_map(|v| v.split(','))
+            .any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case("Accept-Encoding"));
+        if !varies {
+            response.headers_mut().append_unchecked("Vary", "Accept-Encoding");
+        }
+        if response.body().len().is_some_and(|len| len < self.min_size) {
+            return None;
+        }
+        let encoding = negotiate(request.get("Accept-Encoding"))?;
+        let body = match response.take_body() {
+            Body::Bytes(bytes) => Body::from(encoding.encode(&bytes)),
+            body => encoding.encode_reader(body.into_reader()),
+        };
+        response.set_body(body);
+        let headers = response.headers_mut();
+        headers.remove("Content-Length");
+        headers.insert_unchecked("Content-Encoding", encoding.as_str());
+        Some(encoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::httprequest::Version;
+
+    fn request(accept_encoding: &str) -> HeaderMap {
+        [("Accept-Encoding", accept_encoding)].into_iter().collect()
+    }
+
+    fn text(len: usize) -> String {
+        "compressible text ".repeat(len / 18 + 1)[..len].to_string()
+    }
+
+    fn decode(encoding: Encoding, bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        match encoding {
+            Encoding::Brotli => brotli::Decompressor::new(bytes, BUFFER_SIZE).read_to_end(&mut out),
+            Encoding::Gzip => flate2::read::GzDecoder::new(bytes).read_to_end(&mut out),
+            Encoding::Deflate => flate2::read::ZlibDecoder::new(bytes).read_to_end(&mut out),
+        }
+        .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_parse_accept_encoding() {
+        assert_eq!(
+            parse_accept_encoding("gzip;q=0.8, BR , *;q=0, deflate; q=0.123"),
+            vec![
+                (String::from("gzip"), 0.8),
+                (String::from("br"), 1.0),
+                (String::from("*"), 0.0),
+                (String::from("deflate"), 0.123)
+            ]
+        );
+        // 不合法的 q
+        for value in ["gzip;q=2", "gzip;q=0.1234", "gzip;q=1.5", "gzip;q=", "gzip;q", "gzip;q=-1"] {
+            assert_eq!(parse_accept_encoding(value), vec![], "{}", value);
+        }
+        assert_eq!(parse_accept_encoding(""), vec![]);
+        assert_eq!(parse_accept_encoding(" , gzip"), vec![(String::from("gzip"), 1.0)]);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let cases = [
+            ("gzip, deflate, br", Some(Encoding::Brotli)),
+            ("gzip, deflate", Some(Encoding::Gzip)),
+            ("br;q=0.5, gzip;q=0.9", Some(Encoding::Gzip)),
+            ("deflate", Some(Encoding::Deflate)),
+            ("x-gzip", Some(Encoding::Gzip)),
+            ("*", Some(Encoding::Brotli)),
+            ("br;q=0, *;q=0.5", Some(Encoding::Gzip)),
+            ("gzip;q=0, deflate;q=0, br;q=0", None),
+            ("identity", None),
+            ("identity;q=1, gzip;q=0.5", None),
+            ("identity;q=0, gzip;q=0.5", Some(Encoding::Gzip)),
+            ("compress, zstd", None),
+            ("", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(negotiate(Some(value)), expected, "{}", value);
+        }
+        assert_eq!(negotiate(None), None);
+    }
+
+    #[test]
+    fn test_apply() {
+        let compression = Compression::new();
+        for encoding in PREFERENCE {
+            let body = text(2000);
+            let mut response = HttpResponse::builder()
+                .content_type("text/html; charset=utf-8")
+                .body(body.clone());
+            let used = compression.apply(&request(encoding.as_str()), &mut response);
+            assert_eq!(used, Some(encoding));
+            assert_eq!(response.headers().get("Content-Encoding"), Some(encoding.as_str()));
+            assert_eq!(response.headers().get("Vary"), Some("Accept-Encoding"));
+            let compressed = response.body().as_bytes().unwrap();
+            assert!(compressed.len() < body.len());
+            assert_eq!(decode(encoding, compressed), body.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_not_compressed() {
+        let compression = Compression::new();
+        // 太小：不压缩，但仍然加上 Vary
+        let mut response = HttpResponse::builder().content_type("application/json").body(text(100));
+        assert_eq!(compression.apply(&request("gzip"), &mut response), None);
+        assert_eq!(response.headers().get("Vary"), Some("Accept-Encoding"));
+        assert_eq!(response.body().len(), Some(100));
+
+        // 类型不在列表中
+        let mut response = HttpResponse::builder().content_type("image/png").body(text(2000));
+        assert_eq!(compression.apply(&request("gzip"), &mut response), None);
+        assert!(!response.headers().contains_key("Vary"));
+
+        // 已经编码过
+        let mut response = HttpResponse::builder()
+            .content_type("text/plain")
+            .header("Content-Encoding", "gzip")
+            .body(text(2000));
+        assert_eq!(compression.apply(&request("br"), &mut response), None);
+
+        // 客户端不接受，Vary 不重复
+        let mut response = HttpResponse::builder()
+            .content_type("text/css")
+            .header("Vary", "Origin, accept-encoding")
+            .body(text(2000));
+        assert_eq!(compression.apply(&request("identity"), &mut response), None);
+        assert_eq!(response.headers().get_all("Vary").count(), 1);
+        assert!(response.headers().get("Content-Encoding").is_none());
+
+        // 没有响应体的状态
+        let mut response = HttpResponse::builder().status(304).content_type("text/html").build();
+        assert_eq!(compression.apply(&request("gzip"), &mut response), None);
+    }
+
+    #[test]
+    fn test_config() {
+        let compression = Compression::new().min_size(0).mime_types(["Application/Wasm"]);
+        let mut response = HttpResponse::builder().content_type("application/wasm").body("x");
+        assert_eq!(compression.apply(&request("gzip"), &mut response), Some(Encoding::Gzip));
+        let mut response = HttpResponse::builder().content_type("text/html").body("x");
+        assert_eq!(compression.apply(&request("gzip"), &mut response), None);
+    }
+
+    // 流在发送时压缩，HTTP/1.1 用 chunked 编码
+    #[test]
+    fn test_stream() {
+        let body = text(50_000);
+        let mut response = HttpResponse::builder()
+            .content_type("text/plain")
+            .header("Content-Length", "50000")
+            .body(Body::reader(Cursor::new(body.clone())));
+        let used = Compression::new().apply(&request("gzip;q=1, br;q=0.5"), &mut response);
+        assert_eq!(used, Some(Encoding::Gzip));
+        let out: Vec<u8> = response.into();
+        let split = out.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&out[..split]);
+        assert!(head.contains("Transfer-Encoding: chunked\r\n"), "{}", head);
+        assert!(!head.contains("Content-Length"));
+        let mut decoder = crate::chunked::ChunkedDecoder::new();
+        assert!(decoder.decode(&out[split..]).unwrap().is_some());
+        assert_eq!(decode(Encoding::Gzip, &decoder.take_body()), body.as_bytes());
+    }
+
+    // HTTP/1.0 没有 chunked：压缩的流以关闭连接结束
+    #[test]
+    fn test_stream_http_1_0() {
+        let body = text(50_000);
+        let mut response = HttpResponse::builder()
+            .content_type("text/plain")
+            .body(Body::reader(Cursor::new(body.clone())));
+        response.set_version(Version::V1_0);
+        assert_eq!(Compression::new().apply(&request("gzip"), &mut response), Some(Encoding::Gzip));
+        let out: Vec<u8> = response.into();
+        let split = out.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&out[..split]);
+        assert!(head.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(head.contains("Connection: close\r\n"), "{}", head);
+        assert!(!head.contains("Transfer-Encoding") && !head.contains("Content-Length"));
+        assert_eq!(decode(Encoding::Gzip, &out[split..]), body.as_bytes());
+    }
+}
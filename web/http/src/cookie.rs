@@ -0,0 +1,433 @@
+// Cookie (RFC 6265)
+//
+// 请求的 Cookie 头部解析为 `CookieJar`，只有名字和值，解析时宽松处理；
+// 响应用 `CookieBuilder` 生成 Set-Cookie，构建时检查名字、值和属性。
+use crate::headermap::HeaderMap;
+use crate::httpparser::is_tchar;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    expires: Option<SystemTime>,
+    max_age: Option<u64>, // 秒，0 表示立即删除
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CookieError {
+    Name,                 // 名字不是 token
+    Value,                // 值中有 cookie-octet 以外的字符
+    Path,                 // Path 为空或有控制字符、`;`
+    Domain,               // Domain 不是合法的主机名
+    InsecureSameSiteNone, // SameSite=None 必须同时有 Secure
+    Prefix,               // `__Secure-` 和 `__Host-` 前缀的要求没有满足
+}
+
+impl fmt::Display for CookieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            CookieError::Name => "invalid cookie name",
+            CookieError::Value => "invalid cookie value",
+            CookieError::Path => "invalid cookie path",
+            CookieError::Domain => "invalid cookie domain",
+            CookieError::InsecureSameSiteNone => "SameSite=None requires Secure",
+            CookieError::Prefix => "cookie name prefix requirements not met",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for CookieError {}
+
+impl Cookie {
+    // 只有名字和值的 cookie
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Result<Cookie, CookieError> {
+        Cookie::builder(name, value).build()
+    }
+
+    pub fn builder(name: impl Into<String>, value: impl Into<String>) -> CookieBuilder {
+        CookieBuilder {
+            cookie: Cookie {
+                name: name.into(),
+                value: value.into(),
+                path: None,
+                domain: None,
+                expires: None,
+                max_age: None,
+                secure: false,
+                http_only: false,
+                same_site: None,
+            },
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // 请求中用双引号括起来的值已经去掉引号
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    pub fn max_age(&self) -> Option<u64> {
+        self.max_age
+    }
+
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+
+    pub fn http_only(&self) -> bool {
+        self.http_only
+    }
+
+    pub fn same_site(&self) -> Option<SameSite> {
+        self.same_site
+    }
+
+    fn validate(&self) -> Result<(), CookieError> {
+        if self.name.is_empty() || !self.name.bytes().all(is_tchar) {
+            return Err(CookieError::Name);
+        }
+        let value = self.value.as_bytes();
+        let value = match value {
+            [b'"', inner @ .., b'"'] => inner,
+            _ => value,
+        };
+        if !value.iter().all(|&b| is_cookie_octet(b)) {
+            return Err(CookieError::Value);
+        }
+        if let Some(path) = &self.path {
+            if path.is_empty() || path.bytes().any(|b| b.is_ascii_control() || b == b';') {
+                return Err(CookieError::Path);
+            }
+        }
+        if let Some(domain) = &self.domain {
+            let domain = domain.strip_prefix('.').unwrap_or(domain);
+            let valid = domain.split('.').all(|label| {
+                !label.is_empty()
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            });
+            if !valid {
+                return Err(CookieError::Domain);
+            }
+        }
+        if self.same_site == Some(SameSite::None) && !self.secure {
+            return Err(CookieError::InsecureSameSiteNone);
+        }
+        let prefix_ok = if self.name.starts_with("__Host-") {
+            self.secure && self.domain.is_none() && self.path.as_deref() == Some("/")
+        } else {
+            !self.name.starts_with("__Secure-") || self.secure
+        };
+        if !prefix_ok {
+            return Err(CookieError::Prefix);
+        }
+        Ok(())
+    }
+}
+
+// Set-Cookie 的值，如 `id=a3fWa; Path=/; Max-Age=3600; Secure; HttpOnly`
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", http_date(expires))?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age)?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+// %x21 / %x23-2B / %x2D-3A / %x3C-5B / %x5D-7E：没有空白、双引号、逗号、分号和反斜杠
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"]; // 1970-01-01 是星期四
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+// IMF-fixdate (RFC 9110 5.6.7)，如 `Sun, 06 Nov 1994 08:49:37 GMT`；1970 年以前按 1970 年
+pub fn http_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // 从 1970-01-01 起的天数换算成年月日，三月作为一年的开始，闰日在最后
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + yoe + u64::from(month <= 2);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[derive(Debug)]
+pub struct CookieBuilder {
+    cookie: Cookie,
+}
+
+impl CookieBuilder {
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.cookie.path = Some(path.into());
+        self
+    }
+
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.cookie.domain = Some(domain.into());
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.cookie.expires = Some(expires);
+        self
+    }
+
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.cookie.max_age = Some(seconds);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.cookie.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.cookie.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.cookie.same_site = Some(same_site);
+        self
+    }
+
+    // 检查名字、值和属性
+    pub fn build(self) -> Result<Cookie, CookieError> {
+        self.cookie.validate()?;
+        Ok(self.cookie)
+    }
+}
+
+// 请求带来的 cookie，按收到的顺序；同名的 cookie 分别保留
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 解析所有 Cookie 头部，如 `a=1; b="x"`；没有 `=` 或名字不合法的项被跳过
+    pub fn from_headers(headers: &HeaderMap) -> CookieJar {
+        let cookies = headers
+            .get_all("Cookie")
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                let name = name.trim();
+                if name.is_empty() || !name.bytes().all(is_tchar) {
+                    return None;
+                }
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                Some(Cookie::builder(name, value).cookie)
+            })
+            .collect();
+        CookieJar { cookies }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    // 第一个同名 cookie 的值，名字区分大小写
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies.iter().find(|c| c.name == name).map(|c| c.value.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.cookies.iter().filter(move |c| c.name == name).map(|c| c.value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Cookie> {
+        self.cookies.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a CookieJar {
+    type Item = &'a Cookie;
+    type IntoIter = std::slice::Iter<'a, Cookie>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.cookies.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn jar(values: &[&str]) -> CookieJar {
+        let headers: HeaderMap = values.iter().map(|v| ("Cookie", *v)).collect();
+        CookieJar::from_headers(&headers)
+    }
+
+    #[test]
+    fn test_jar() {
+        let jar = jar(&["a=1; b=\"x y\" ;c=; =skip; novalue; d=e=f", "a=2;bad name=1"]);
+        let pairs: Vec<(&str, &str)> = jar.iter().map(|c| (c.name(), c.value())).collect();
+        assert_eq!(pairs, [("a", "1"), ("b", "x y"), ("c", ""), ("d", "e=f"), ("a", "2")]);
+        assert_eq!(jar.get("a"), Some("1"));
+        assert_eq!(jar.get_all("a").collect::<Vec<_>>(), ["1", "2"]);
+        assert_eq!(jar.get("A"), None);
+        assert!(jar.contains("c"));
+        assert_eq!(jar.len(), 5);
+        assert!(CookieJar::from_headers(&HeaderMap::new()).is_empty());
+    }
+
+    #[test]
+    fn test_set_cookie() {
+        assert_eq!(Cookie::new("id", "a3fWa").unwrap().to_string(), "id=a3fWa");
+        let cookie = Cookie::builder("session", "\"abc\"")
+            .path("/")
+            .domain("example.com")
+            .expires(UNIX_EPOCH + Duration::from_secs(784_111_777))
+            .max_age(3600)
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .build()
+            .unwrap();
+        assert_eq!(
+            cookie.to_string(),
+            "session=\"abc\"; Path=/; Domain=example.com; Expires=Sun, 06 Nov 1994 08:49:37 GMT; \
+             Max-Age=3600; Secure; HttpOnly; SameSite=Lax"
+        );
+        assert_eq!(cookie.max_age(), Some(3600));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(Cookie::new("", "v"), Err(CookieError::Name));
+        assert_eq!(Cookie::new("a b", "v"), Err(CookieError::Name));
+        assert_eq!(Cookie::new("a=b", "v"), Err(CookieError::Name));
+        for value in ["a b", "a;b", "a,b", "a\\b", "\"a", "caf\u{e9}", "a\"b\""] {
+            assert_eq!(Cookie::new("n", value), Err(CookieError::Value), "{}", value);
+        }
+        assert!(Cookie::new("n", "").is_ok());
+        assert!(Cookie::new("n", "\"\"").is_ok());
+
+        let cookie = |builder: CookieBuilder| builder.build().err();
+        assert_eq!(cookie(Cookie::builder("n", "v").path("/a;b")), Some(CookieError::Path));
+        assert_eq!(cookie(Cookie::builder("n", "v").path("")), Some(CookieError::Path));
+        assert_eq!(cookie(Cookie::builder("n", "v").domain("a..b")), Some(CookieError::Domain));
+        assert_eq!(cookie(Cookie::builder("n", "v").domain("-a.com")), Some(CookieError::Domain));
+        assert_eq!(cookie(Cookie::builder("n", "v").domain(".a.com")), None);
+        assert_eq!(
+            cookie(Cookie::builder("n", "v").same_site(SameSite::None)),
+            Some(CookieError::InsecureSameSiteNone)
+        );
+        assert_eq!(cookie(Cookie::builder("n", "v").same_site(SameSite::None).secure(true)), None);
+
+        assert_eq!(cookie(Cookie::builder("__Secure-id", "v")), Some(CookieError::Prefix));
+        assert_eq!(cookie(Cookie::builder("__Secure-id", "v").secure(true)), None);
+        assert_eq!(cookie(Cookie::builder("__Host-id", "v").secure(true)), Some(CookieError::Prefix));
+        let host = Cookie::builder("__Host-id", "v").secure(true).path("/").domain("a.com");
+        assert_eq!(cookie(host), Some(CookieError::Prefix));
+        assert_eq!(cookie(Cookie::builder("__Host-id", "v").secure(true).path("/")), None);
+    }
+
+    #[test]
+    fn test_http_date() {
+        let date = |secs| http_date(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(date(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(date(951_782_400), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(date(4_133_980_799), "Fri, 31 Dec 2100 23:59:59 GMT");
+        assert_eq!(http_date(UNIX_EPOCH - Duration::from_secs(1)), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+}
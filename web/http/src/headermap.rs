@@ -0,0 +1,396 @@
+// 请求和响应共用的头部集合
+//
+// 名称不区分大小写，但保留收到或设置时的写法；同一名称可以有多个值
+// (如 Set-Cookie)，迭代时按加入的顺序。名称必须是 token，值中不能有
+// CR、LF、NUL 等控制字符，否则写出去会多出头部行 (响应拆分)。
+use crate::httpparser::is_tchar;
+use std::fmt;
+use std::ops::Index;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+// Content-Type 的值：小写的媒体类型和参数，如 `text/html; charset=utf-8`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentType {
+    pub mime: String,                  // 如 `text/html`
+    pub params: Vec<(String, String)>, // 名称小写，值去掉了引号
+}
+
+impl ContentType {
+    // 参数的值，名称不区分大小写
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+}
+
+impl From<&str> for ContentType {
+    fn from(s: &str) -> ContentType {
+        let mut parts = s.split(';');
+        let mime = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let params = parts
+            .filter_map(|p| p.split_once('='))
+            .map(|(n, v)| {
+                let v = v.trim();
+                let v = v.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(v);
+                (n.trim().to_ascii_lowercase(), v.to_string())
+            })
+            .collect();
+        ContentType { mime, params }
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mime)?;
+        for (name, value) in &self.params {
+            write!(f, "; {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+// 设置头部时名称或值无效
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeaderError {
+    Name,  // 名称为空或不是 token
+    Value, // 值中有 HTAB 以外的控制字符
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            HeaderError::Name => "invalid header name",
+            HeaderError::Value => "invalid header value",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+// 名称是 token，值只有 field-vchar、SP 和 HTAB (UTF-8 的非 ASCII 字节算作 obs-text)
+pub fn validate(name: &str, value: &str) -> Result<(), HeaderError> {
+    if name.is_empty() || !name.bytes().all(is_tchar) {
+        return Err(HeaderError::Name);
+    }
+    if value.bytes().any(|b| (b < b' ' && b != b'\t') || b == 0x7f) {
+        return Err(HeaderError::Value);
+    }
+    Ok(())
+}
+
+// Connection 头部中的连接选项
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connection {
+    KeepAlive,
+    Close,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // 第一个值
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // 所有的值，按加入的顺序
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // 设置一个值，替换所有同名的旧值；位置保持在第一个旧值处
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) -> Result<(), HeaderError> {
+        let (name, value) = (name.into(), value.into());
+        validate(&name, &value)?;
+        self.insert_unchecked(name, value);
+        Ok(())
+    }
+
+    // 加入一个值，保留同名的旧值
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) -> Result<(), HeaderError> {
+        let (name, value) = (name.into(), value.into());
+        validate(&name, &value)?;
+        self.entries.push((name, value));
+        Ok(())
+    }
+
+    // 不检查的 insert，只用于已知合法的名称和值
+    pub(crate) fn insert_unchecked(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        match self.entries.iter().position(|(n, _)| n.eq_ignore_ascii_case(&name)) {
+            Some(i) => {
+                let mut j = 0;
+                self.entries.retain(|(n, _)| {
+                    j += 1;
+                    j <= i + 1 || !n.eq_ignore_ascii_case(&name)
+                });
+                self.entries[i] = (name, value.into());
+            }
+            None => self.entries.push((name, value.into())),
+        }
+    }
+
+    // 不检查的 append，只用于已知合法的名称和值
+    pub(crate) fn append_unchecked(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    // 删除所有同名的值，返回它们
+    pub fn remove(&mut self, name: &str) -> Vec<String> {
+        let mut removed = Vec::new();
+        self.entries.retain_mut(|(n, v)| {
+            if n.eq_ignore_ascii_case(name) {
+                removed.push(std::mem::take(v));
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+
+    pub fn iter(&self) -> <&HeaderMap as IntoIterator>::IntoIter {
+        self.into_iter()
+    }
+
+    // 无效、多个值不一致或没有时返回 None
+    pub fn content_length(&self) -> Option<u64> {
+        let mut length = None;
+        for v in self.get_all("Content-Length").flat_map(|v| v.split(',')) {
+            let v = v.trim();
+            if v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let n = v.parse().ok()?;
+            if length.is_some_and(|l| l != n) {
+                return None;
+            }
+            length = Some(n);
+        }
+        length
+    }
+
+    pub fn set_content_length(&mut self, length: u64) {
+        self.insert_unchecked("Content-Length", length.to_string());
+    }
+
+    pub fn content_type(&self) -> Option<ContentType> {
+        self.get("Content-Type").map(ContentType::from)
+    }
+
+    pub fn set_content_type(&mut self, content_type: &str) -> Result<(), HeaderError> {
+        self.insert("Content-Type", content_type)
+    }
+
+    // Connection 是逗号分隔的选项列表，close 优先于 keep-alive
+    pub fn connection(&self) -> Option<Connection> {
+        let options: Vec<&str> = self
+            .get_all("Connection")
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .collect();
+        if options.iter().any(|o| o.eq_ignore_ascii_case("close")) {
+            Some(Connection::Close)
+        } else if options.iter().any(|o| o.eq_ignore_ascii_case("keep-alive")) {
+            Some(Connection::KeepAlive)
+        } else {
+            None
+        }
+    }
+
+    pub fn set_connection(&mut self, connection: Connection) {
+        let value = match connection {
+            Connection::KeepAlive => "keep-alive",
+            Connection::Close => "close",
+        };
+        self.insert_unchecked("Connection", value);
+    }
+}
+
+// 和 HashMap 一样，名称不存在时 panic
+impl Index<&str> for HeaderMap {
+    type Output = str;
+
+    fn index(&self, name: &str) -> &str {
+        self.get(name).unwrap_or_else(|| panic!("no header named {:?}", name))
+    }
+}
+
+// 无效的头部被丢弃
+impl<N: Into<String>, V: Into<String>> FromIterator<(N, V)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (N, V)>>(iter: I) -> Self {
+        let mut headers = HeaderMap::new();
+        for (name, value) in iter {
+            let _ = headers.append(name, value);
+        }
+        headers
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a str, &'a str);
+    type IntoIter = std::iter::Map<
+        std::slice::Iter<'a, (String, String)>,
+        fn(&'a (String, String)) -> (&'a str, &'a str),
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+// 按 HTTP 的格式输出，每行以 CRLF 结束
+impl fmt::Display for HeaderMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in self {
+            write!(f, "{}: {}\r\n", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::httprequest::HttpRequest;
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/html").unwrap();
+        assert_eq!(headers.get("content-type"), Some("text/html"));
+        assert_eq!(&headers["CONTENT-TYPE"], "text/html");
+        assert!(headers.contains_key("Content-type"));
+        assert!(!headers.contains_key("Content"));
+
+        // 替换时使用新的写法
+        headers.insert("content-type", "text/plain").unwrap();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers.iter().collect::<Vec<_>>(), [("content-type", "text/plain")]);
+    }
+
+    #[test]
+    fn test_multiple_values() {
+        let mut headers: HeaderMap = [("Set-Cookie", "a=1"), ("Host", "x"), ("set-cookie", "b=2")]
+            .into_iter()
+            .collect();
+        assert_eq!(headers.get("Set-Cookie"), Some("a=1"));
+        assert_eq!(headers.get_all("SET-COOKIE").collect::<Vec<_>>(), ["a=1", "b=2"]);
+        assert_eq!(headers.to_string(), "Set-Cookie: a=1\r\nHost: x\r\nset-cookie: b=2\r\n");
+
+        headers.append("Accept", "*/*").unwrap();
+        headers.insert("Set-Cookie", "c=3").unwrap();
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            [("Set-Cookie", "c=3"), ("Host", "x"), ("Accept", "*/*")]
+        );
+        assert_eq!(headers.remove("host"), ["x"]);
+        assert!(headers.remove("host").is_empty());
+        assert_eq!(headers.len(), 2);
+    }
+
+    #[test]
+    fn test_content_length() {
+        let length = |values: &[&str]| {
+            values.iter().map(|v| ("Content-Length", *v)).collect::<HeaderMap>().content_length()
+        };
+        assert_eq!(length(&[]), None);
+        assert_eq!(length(&["42"]), Some(42));
+        assert_eq!(length(&["7, 7", "7"]), Some(7));
+        assert_eq!(length(&["7", "8"]), None);
+        assert_eq!(length(&["+7"]), None);
+        assert_eq!(length(&[""]), None);
+
+        let mut headers = HeaderMap::new();
+        headers.set_content_length(10);
+        assert_eq!(headers.get("content-length"), Some("10"));
+    }
+
+    #[test]
+    fn test_content_type() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(headers.content_type(), None);
+        headers.set_content_type("Multipart/Form-Data; Boundary=\"x y\" ; charset=UTF-8").unwrap();
+        let content_type = headers.content_type().unwrap();
+        assert_eq!(content_type.mime, "multipart/form-data");
+        assert_eq!(content_type.param("boundary"), Some("x y"));
+        assert_eq!(content_type.charset(), Some("UTF-8"));
+        assert_eq!(ContentType::from("text/html").to_string(), "text/html");
+    }
+
+    #[test]
+    fn test_connection() {
+        let connection = |value: &str| [("Connection", value)].into_iter().collect::<HeaderMap>().connection();
+        assert_eq!(HeaderMap::new().connection(), None);
+        assert_eq!(connection("Keep-Alive"), Some(Connection::KeepAlive));
+        assert_eq!(connection("upgrade, close"), Some(Connection::Close));
+        assert_eq!(connection("keep-alive, Close"), Some(Connection::Close));
+        assert_eq!(connection("upgrade"), None);
+
+        let mut headers = HeaderMap::new();
+        headers.set_connection(Connection::Close);
+        assert_eq!(headers.to_string(), "Connection: close\r\n");
+    }
+
+    // 无效的名称和值不能加入，否则会拆分出新的头部行
+    #[test]
+    fn test_invalid_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(headers.insert("X", "a\r\nInjected: 1"), Err(HeaderError::Value));
+        assert_eq!(headers.append("X", "a\nb"), Err(HeaderError::Value));
+        assert_eq!(headers.append("X", "a\0b"), Err(HeaderError::Value));
+        assert_eq!(headers.append("X", "a\x7fb"), Err(HeaderError::Value));
+        assert_eq!(headers.insert("X Y", "1"), Err(HeaderError::Name));
+        assert_eq!(headers.insert("X:", "1"), Err(HeaderError::Name));
+        assert_eq!(headers.insert("", "1"), Err(HeaderError::Name));
+        assert_eq!(headers.set_content_type("text/html\r\nX: 1"), Err(HeaderError::Value));
+        assert!(headers.is_empty());
+
+        headers.insert("X-Ok", "tab\tand café").unwrap();
+        headers.append("X-Empty", "").unwrap();
+        assert_eq!(headers.len(), 2);
+
+        let headers: HeaderMap = [("Good", "1"), ("Bad", "\r\n")].into_iter().collect();
+        assert_eq!(headers.to_string(), "Good: 1\r\n");
+    }
+
+    // 头部只在第一个冒号处分开，值两边的空白被去掉
+    #[test]
+    fn test_first_colon() {
+        let req: HttpRequest =
+            String::from("GET / HTTP/1.1\r\nHost:  localhost:8080 \r\nReferer: http://a:1/b:c\r\n\r\n").into();
+        assert_eq!(req.headers.get("host"), Some("localhost:8080"));
+        assert_eq!(req.headers.get("referer"), Some("http://a:1/b:c"));
+    }
+}
@@ -0,0 +1,691 @@
+// 增量式 HTTP/1.1 请求解析器 (RFC 9112)
+//
+// 每次调用 `parse` 传入目前收到的全部字节，解析器记住已经解析到的位置，
+// 只处理新到达的完整行。请求头结束后按 Content-Length 读取原始字节的请求体。
+// 请求体超过 max_body 时立即报错 (声明的 Content-Length 或 chunked 的块大小)，
+// 不等它全部到达，调用者可以停止读取。
+//
+// 用 `body_parser` 设置后，multipart/form-data 的请求体在到达时交给
+// `MultipartDecoder`，结果放在 `HttpRequest::parts` 中，`msg_body` 为空；
+// 调用者在每次读取之前用 `discard_body` 删除已经交出去的字节。
+use crate::bodyparser::{BodyError, BodyParser, MultipartDecoder};
+use crate::httprequest::{HttpRequest, Method, Resource, Version};
+use crate::chunked::ChunkedDecoder;
+use crate::cookie::CookieJar;
+use crate::headermap::{ContentType, HeaderError, HeaderMap};
+use crate::statuscode::StatusCode;
+use std::fmt;
+
+// 请求行和请求头的最大字节数
+pub const MAX_HEAD: usize = 64 * 1024;
+
+// 默认的请求体最大字节数，可以用 `Parser::max_body` 修改
+pub const MAX_BODY: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum Parsed {
+    Incomplete,      // 需要更多字节
+    Complete(usize), // 一个完整的请求，以及它占用的字节数
+    Error(ParseError),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    RequestLine,                 // 请求行不是 `method SP target SP version`
+    Method,                      // 方法不是 token
+    Target,                      // 请求目标为空或含有非法字符
+    Version,                     // 版本格式不对
+    UnsupportedVersion,          // 格式正确，但不是 HTTP/1.0 或 HTTP/1.1
+    LineEnding,                  // 单独的 CR
+    HeaderName,                  // 头部名称不是 token，或名称和冒号之间有空白
+    HeaderValue,                 // 头部值含有控制字符
+    Folding,                     // 第一个头部之前的续行
+    ContentLength,               // Content-Length 无效或多个值不一致
+    TransferEncoding,            // chunked 不是最后一个编码，或同时有 Content-Length
+    UnsupportedTransferEncoding, // chunked 之外的传输编码
+    Chunked,                     // chunked 的块格式不对
+    TooLarge,                    // 请求行和请求头超过 MAX_HEAD
+    BodyTooLarge,                // 请求体超过 max_body
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            ParseError::RequestLine => "malformed request line",
+            ParseError::Method => "invalid method",
+            ParseError::Target => "invalid request target",
+            ParseError::Version => "malformed HTTP version",
+            ParseError::UnsupportedVersion => "unsupported HTTP version",
+            ParseError::LineEnding => "bare CR in request",
+            ParseError::HeaderName => "invalid header name",
+            ParseError::HeaderValue => "invalid header value",
+            ParseError::Folding => "line folding before the first header",
+            ParseError::ContentLength => "invalid Content-Length",
+            ParseError::TransferEncoding => "invalid Transfer-Encoding",
+            ParseError::UnsupportedTransferEncoding => "unsupported transfer coding",
+            ParseError::Chunked => "invalid chunked body",
+            ParseError::TooLarge => "request head too large",
+            ParseError::BodyTooLarge => "request body too large",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<HeaderError> for ParseError {
+    fn from(err: HeaderError) -> Self {
+        match err {
+            HeaderError::Name => ParseError::HeaderName,
+            HeaderError::Value => ParseError::HeaderValue,
+        }
+    }
+}
+
+impl ParseError {
+    // 应答的状态码：不支持的版本是 505，不支持的传输编码是 501，请求体太大是 413，
+    // 其他都是 400
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ParseError::UnsupportedVersion => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            ParseError::UnsupportedTransferEncoding => StatusCode::NOT_IMPLEMENTED,
+            ParseError::BodyTooLarge => StatusCode::CONTENT_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+// 请求体的长度由什么决定
+#[derive(Debug)]
+enum Framing {
+    Length(usize),
+    Chunked(ChunkedDecoder),
+}
+
+#[derive(Debug)]
+pub struct Parser {
+    pos: usize, // 下一行的开始
+    request_line: Option<(Method, Resource, Version)>,
+    headers: Vec<(String, String)>,
+    body: Option<(usize, Framing)>, // 请求头结束后：请求体的开始
+    request: Option<HttpRequest>,
+    consumed: usize, // 完成后，请求占用的字节数
+    max_body: usize,
+    body_parser: Option<BodyParser>,
+    multipart: Option<Result<MultipartDecoder, BodyError>>, // 出错后只保留错误
+    fed: usize, // Content-Length 的请求体中已经交给 multipart 的字节数
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser {
+            pos: 0,
+            request_line: None,
+            headers: Vec::new(),
+            body: None,
+            request: None,
+            consumed: 0,
+            max_body: MAX_BODY,
+            body_parser: None,
+            multipart: None,
+            fed: 0,
+        }
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 请求体的最大字节数，默认是 MAX_BODY
+    pub fn max_body(mut self, max_body: usize) -> Self {
+        self.max_body = max_body;
+        self
+    }
+
+    // multipart/form-data 的请求体按 `body_parser` 边接收边解析
+    pub fn body_parser(mut self, body_parser: BodyParser) -> Self {
+        self.body_parser = Some(body_parser);
+        self
+    }
+
+    // `buf` 是这个请求目前收到的全部字节，除了用 `discard_body` 删除的，
+    // 每次调用只会变长
+    pub fn parse(&mut self, buf: &[u8]) -> Parsed {
+        match self.parse_head(buf) {
+            Ok(true) => (),
+            Ok(false) => return Parsed::Incomplete,
+            Err(e) => return Parsed::Error(e),
+        }
+        if self.request.is_some() {
+            return Parsed::Complete(self.consumed);
+        }
+        let (start, framing) = self.body.as_mut().unwrap();
+        let start = *start;
+        let streaming = self.multipart.is_some();
+        let (end, msg_body, trailers) = match framing {
+            Framing::Length(len) => {
+                let end = buf.len().min(start + *len);
+                if streaming {
+                    feed(&mut self.multipart, &buf[start + self.fed..end]);
+                    self.fed = end - start;
+                }
+                if end < start + *len {
+                    return Parsed::Incomplete;
+                }
+                let msg_body = if streaming { Vec::new() } else { buf[start..end].to_vec() };
+                (end, msg_body, HeaderMap::new())
+            }
+            Framing::Chunked(decoder) => {
+                let done = decoder.decode(&buf[start..]);
+                if streaming {
+                    feed(&mut self.multipart, &decoder.take_body());
+                }
+                match done {
+                    Ok(None) => return Parsed::Incomplete,
+                    Ok(Some(n)) => (start + n, decoder.take_body(), decoder.take_trailers()),
+                    Err(e) => return Parsed::Error(e),
+                }
+            }
+        };
+        let (method, resource, version) = self.request_line.take().unwrap();
+        // 同名的头部分别保留，按收到的顺序
+        let headers = self.headers.drain(..).collect();
+        let cookies = CookieJar::from_headers(&headers);
+        self.request = Some(HttpRequest {
+            method,
+            version,
+            resource,
+            headers,
+            cookies,
+            msg_body,
+            trailers,
+            parts: self.multipart.take().map(|m| m.and_then(MultipartDecoder::finish)),
+        });
+        self.consumed = end;
+        Parsed::Complete(end)
+    }
+
+    // 取出解析完成的请求，解析器可以接着解析下一个请求
+    pub fn take(&mut self) -> Option<HttpRequest> {
+        let request = self.request.take()?;
+        *self = Parser {
+            max_body: self.max_body,
+            body_parser: self.body_parser.take(),
+            ..Parser::new()
+        };
+        Some(request)
+    }
+
+    // 从 `buf` 中删除已经交给 multipart 的请求体字节，之后传入删除后的 `buf`。
+    // 没有边接收边解析时什么也不做
+    pub fn discard_body(&mut self, buf: &mut Vec<u8>) {
+        if self.multipart.is_none() || self.request.is_some() {
+            return;
+        }
+        let Some((start, framing)) = &mut self.body else {
+            return;
+        };
+        let n = match framing {
+            Framing::Length(len) => {
+                *len -= self.fed;
+                std::mem::take(&mut self.fed)
+            }
+            Framing::Chunked(decoder) => decoder.discard(),
+        };
+        buf.drain(*start..*start + n);
+    }
+
+    // 解析新到达的完整行，请求头结束时返回 true
+    fn parse_head(&mut self, buf: &[u8]) -> Result<bool, ParseError> {
+        while self.body.is_none() {
+            let Some(end) = buf[self.pos..].iter().position(|&b| b == b'\n') else {
+                if buf.len() > MAX_HEAD {
+                    return Err(ParseError::TooLarge);
+                }
+                return Ok(false);
+            };
+            let line = &buf[self.pos..self.pos + end];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            self.pos += end + 1;
+            if self.pos > MAX_HEAD {
+                return Err(ParseError::TooLarge);
+            }
+            if line.contains(&b'\r') {
+                return Err(ParseError::LineEnding);
+            }
+
+            if self.request_line.is_none() {
+                // 请求行之前的空行被忽略
+                if !line.is_empty() {
+                    self.request_line = Some(parse_request_line(line)?);
+                }
+            } else if line.is_empty() {
+                self.body = Some((self.pos, self.framing()?));
+                self.multipart = self.multipart_decoder();
+            } else if line[0] == b' ' || line[0] == b'\t' {
+                // 过时的续行 (obs-fold)，用一个空格替换
+                let Some((_, value)) = self.headers.last_mut() else {
+                    return Err(ParseError::Folding);
+                };
+                let more = header_value(line)?;
+                if !more.is_empty() {
+                    if !value.is_empty() {
+                        value.push(' ');
+                    }
+                    value.push_str(&more);
+                }
+            } else {
+                self.headers.push(parse_header(line)?);
+            }
+        }
+        Ok(true)
+    }
+
+    // RFC 9112 6.3：有 Transfer-Encoding 时只支持 chunked，且不能同时有
+    // Content-Length (否则可能被用来夹带请求)
+    fn framing(&self) -> Result<Framing, ParseError> {
+        let codings: Vec<String> = self
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("transfer-encoding"))
+            .flat_map(|(_, value)| value.split(','))
+            .map(|coding| coding.trim().to_ascii_lowercase())
+            .filter(|coding| !coding.is_empty())
+            .collect();
+        if codings.is_empty() {
+            let length = self.content_length()?;
+            if length > self.max_body {
+                return Err(ParseError::BodyTooLarge);
+            }
+            return Ok(Framing::Length(length));
+        }
+        let has_length = self.headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-length"));
+        let chunked = codings.iter().filter(|c| *c == "chunked").count();
+        let http_1_0 = matches!(self.request_line, Some((_, _, Version::V1_0)));
+        if has_length || http_1_0 || chunked != 1 || codings.last().unwrap() != "chunked" {
+            return Err(ParseError::TransferEncoding);
+        }
+        if codings.len() > 1 {
+            return Err(ParseError::UnsupportedTransferEncoding);
+        }
+        Ok(Framing::Chunked(ChunkedDecoder::new().max_body(self.max_body)))
+    }
+
+    // 设置了 body_parser 并且是 multipart/form-data 时的解码器
+    fn multipart_decoder(&self) -> Option<Result<MultipartDecoder, BodyError>> {
+        let body_parser = self.body_parser.as_ref()?;
+        let (_, value) = self.headers.iter().find(|(name, _)| name.eq_ignore_ascii_case("content-type"))?;
+        let content_type = ContentType::from(value.as_str());
+        (content_type.mime == "multipart/form-data").then(|| MultipartDecoder::new(body_parser.clone(), &content_type))
+    }
+
+    fn content_length(&self) -> Result<usize, ParseError> {
+        let mut length = None;
+        for (name, value) in &self.headers {
+            if !name.eq_ignore_ascii_case("content-length") {
+                continue;
+            }
+            // 重复的值必须相同，如 `Content-Length: 5, 5`
+            for v in value.split(',').map(str::trim) {
+                if v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(ParseError::ContentLength);
+                }
+                let n: usize = v.parse().map_err(|_| ParseError::ContentLength)?;
+                if length.is_some_and(|l| l != n) {
+                    return Err(ParseError::ContentLength);
+                }
+                length = Some(n);
+            }
+        }
+        Ok(length.unwrap_or(0))
+    }
+}
+
+// 交给 multipart 解码器；出错后不再处理之后的字节
+fn feed(multipart: &mut Option<Result<MultipartDecoder, BodyError>>, data: &[u8]) {
+    if let Some(Ok(decoder)) = multipart {
+        if let Err(e) = decoder.feed(data) {
+            *multipart = Some(Err(e));
+        }
+    }
+}
+
+// RFC 9110 的 tchar
+pub(crate) fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+fn parse_request_line(line: &[u8]) -> Result<(Method, Resource, Version), ParseError> {
+    let mut parts = line.split(|&b| b == b' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::RequestLine);
+    };
+    let method = std::str::from_utf8(method).map_err(|_| ParseError::Method)?;
+    let method = Method::try_from(method)?;
+    if target.is_empty() || !target.iter().all(|&b| b.is_ascii_graphic()) {
+        return Err(ParseError::Target);
+    }
+    let version = std::str::from_utf8(version).map_err(|_| ParseError::Version)?;
+    // HTTP/2 不使用文本的请求行，只认识它的名字 (如连接前言 `PRI * HTTP/2.0`)
+    let version = match Version::try_from(version)? {
+        Version::V2_0 => return Err(ParseError::UnsupportedVersion),
+        version => version,
+    };
+    // 上面已经检查过是 ASCII
+    let target = Resource::try_from(std::str::from_utf8(target).unwrap())?;
+    // `*` 只用于 OPTIONS，CONNECT 只用 `host:port`
+    match (&method, &target) {
+        (Method::Options, Resource::Asterisk) => (),
+        (_, Resource::Asterisk) => return Err(ParseError::Target),
+        (Method::Connect, Resource::Authority(_)) => (),
+        (Method::Connect, _) | (_, Resource::Authority(_)) => return Err(ParseError::Target),
+        _ => (),
+    }
+    Ok((method, target, version))
+}
+
+// `name ":" OWS value OWS`，名称和冒号之间不能有空白
+pub(crate) fn parse_header(line: &[u8]) -> Result<(String, String), ParseError> {
+    let colon = line.iter().position(|&b| b == b':').ok_or(ParseError::HeaderName)?;
+    let name = &line[..colon];
+    if name.is_empty() || !name.iter().all(|&b| is_tchar(b)) {
+        return Err(ParseError::HeaderName);
+    }
+    let name = std::str::from_utf8(name).unwrap().to_string();
+    Ok((name, header_value(&line[colon + 1..])?))
+}
+
+fn header_value(value: &[u8]) -> Result<String, ParseError> {
+    let value = value.trim_ascii();
+    if value.iter().any(|&b| (b < b' ' && b != b'\t') || b == 0x7f) {
+        return Err(ParseError::HeaderValue);
+    }
+    Ok(String::from_utf8_lossy(value).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 合法的请求，都以一个完整请求结束
+    const VALID: &[&[u8]] = &[
+        b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n",
+        b"GET /index.html HTTP/1.0\r\n\r\n",
+        b"GET /a?b=c&d=e HTTP/1.1\nHost: x\nAccept: */*\n\n",
+        b"\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        b"POST /api HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello",
+        b"POST /bin HTTP/1.1\r\ncontent-length: 4\r\n\r\n\x00\r\n\xff",
+        b"POST /api HTTP/1.1\r\nContent-Length: 3, 3\r\n\r\nabc",
+        b"PUT /x HTTP/1.1\r\nX-Folded: a\r\n  b\r\n\tc\r\nX-Empty:\r\n\r\n",
+        b"GET / HTTP/1.1\r\nAccept: a\r\nAccept: b\r\nUser-Agent: HTTP client\r\n\r\n",
+        b"GET / HTTP/1.1\r\nX-Latin: caf\xe9\r\n\r\n",
+        b"DELETE /items/1 HTTP/1.1\r\n\r\n",
+        b"PROPFIND /dav HTTP/1.1\r\nDepth: 1\r\n\r\n",
+        b"OPTIONS * HTTP/1.1\r\n\r\n",
+        b"CONNECT example.com:443 HTTP/1.1\r\n\r\n",
+        b"GET http://example.com:8080/a/b?c HTTP/1.1\r\n\r\n",
+        b"POST /up HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n3;x=y\r\nabc\r\n2\r\nde\r\n0\r\nDigest: 1\r\n\r\n",
+        b"POST /up HTTP/1.1\nTransfer-Encoding: chunked\n\n0\n\n",
+    ];
+
+    // 不合法的请求和应有的错误
+    const INVALID: &[(&[u8], ParseError)] = &[
+        (b"GET  / HTTP/1.1\r\n\r\n", ParseError::RequestLine),
+        (b"GET / HTTP/1.1 extra\r\n\r\n", ParseError::RequestLine),
+        (b"GET /\r\n\r\n", ParseError::RequestLine),
+        (b"G(T / HTTP/1.1\r\n\r\n", ParseError::Method),
+        (b"GET / HTTP/2.0\r\n\r\n", ParseError::UnsupportedVersion),
+        (b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n", ParseError::UnsupportedVersion),
+        (b"GET / HTTP/1.x\r\n\r\n", ParseError::Version),
+        (b"GET / http/1.1\r\n\r\n", ParseError::Version),
+        (b"GET /a\x7fb HTTP/1.1\r\n\r\n", ParseError::Target),
+        (b"GET * HTTP/1.1\r\n\r\n", ParseError::Target),
+        (b"GET example.com:80 HTTP/1.1\r\n\r\n", ParseError::Target),
+        (b"CONNECT / HTTP/1.1\r\n\r\n", ParseError::Target),
+        (b"GET index.html HTTP/1.1\r\n\r\n", ParseError::Target),
+        (b"GET 1http://x/ HTTP/1.1\r\n\r\n", ParseError::Target),
+        (b"GET / HTTP/1.1\rHost: x\r\n\r\n", ParseError::LineEnding),
+        (b"GET / HTTP/1.1\r\nHost : x\r\n\r\n", ParseError::HeaderName),
+        (b"GET / HTTP/1.1\r\nNo colon\r\n\r\n", ParseError::HeaderName),
+        (b"GET / HTTP/1.1\r\n: empty\r\n\r\n", ParseError::HeaderName),
+        (b"GET / HTTP/1.1\r\nX: a\x01b\r\n\r\n", ParseError::HeaderValue),
+        (b"GET / HTTP/1.1\r\n folded\r\n\r\n", ParseError::Folding),
+        (b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n", ParseError::ContentLength),
+        (b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab", ParseError::ContentLength),
+        (b"POST / HTTP/1.1\r\nContent-Length: 99999999999999999999999\r\n\r\n", ParseError::ContentLength),
+        (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 5\r\n\r\n0\r\n\r\n", ParseError::TransferEncoding),
+        (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n", ParseError::TransferEncoding),
+        (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n", ParseError::TransferEncoding),
+        (b"POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n", ParseError::TransferEncoding),
+        (b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n", ParseError::UnsupportedTransferEncoding),
+        (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nz\r\n", ParseError::Chunked),
+        (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n0\r\n\r\n", ParseError::Chunked),
+    ];
+
+    fn parse_all(buf: &[u8]) -> (Parsed, Option<HttpRequest>) {
+        let mut parser = Parser::new();
+        let parsed = parser.parse(buf);
+        (parsed, parser.take())
+    }
+
+    // 按 `sizes` 的大小一段一段地送入
+    fn parse_chunks(buf: &[u8], sizes: impl Iterator<Item = usize>) -> (Parsed, Option<HttpRequest>) {
+        let mut parser = Parser::new();
+        let mut end = 0;
+        for size in sizes {
+            end = (end + size).min(buf.len());
+            match parser.parse(&buf[..end]) {
+                Parsed::Incomplete if end < buf.len() => (),
+                parsed => return (parsed, parser.take()),
+            }
+        }
+        (parser.parse(buf), parser.take())
+    }
+
+    fn assert_same(a: &(Parsed, Option<HttpRequest>), b: &(Parsed, Option<HttpRequest>)) {
+        assert_eq!(a.0, b.0);
+        match (&a.1, &b.1) {
+            (Some(a), Some(b)) => {
+                assert_eq!(a.method, b.method);
+                assert_eq!(a.version, b.version);
+                assert_eq!(a.resource, b.resource);
+                assert_eq!(a.headers, b.headers);
+                assert_eq!(a.msg_body, b.msg_body);
+                assert_eq!(a.trailers, b.trailers);
+            }
+            (None, None) => (),
+            _ => panic!("only one request was parsed"),
+        }
+    }
+
+    #[test]
+    fn test_valid_corpus() {
+        for &raw in VALID {
+            let (parsed, req) = parse_all(raw);
+            assert_eq!(parsed, Parsed::Complete(raw.len()), "{:?}", String::from_utf8_lossy(raw));
+            assert!(req.is_some());
+        }
+    }
+
+    #[test]
+    fn test_invalid_corpus() {
+        for (raw, err) in INVALID {
+            let (parsed, req) = parse_all(raw);
+            assert_eq!(parsed, Parsed::Error(err.clone()), "{:?}", String::from_utf8_lossy(raw));
+            assert!(req.is_none());
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let (parsed, req) = parse_all(b"POST /api?x=1 HTTP/1.1\r\nHost: localhost:3000\r\nContent-Length: 7\r\n\r\n\x00b\r\nody");
+        assert_eq!(parsed, Parsed::Complete(74));
+        let req = req.unwrap();
+        assert_eq!(req.method, Method::Post);
+        assert_eq!(req.version, Version::V1_1);
+        assert_eq!(req.resource, Resource::Path("/api?x=1".to_string()));
+        assert_eq!(&req.headers["host"], "localhost:3000");
+        assert_eq!(req.headers.content_length(), Some(7));
+        assert_eq!(req.msg_body, b"\x00b\r\nody");
+    }
+
+    #[test]
+    fn test_headers() {
+        let (_, req) = parse_all(VALID[7]);
+        let req = req.unwrap();
+        assert_eq!(&req.headers["X-Folded"], "a b c");
+        assert_eq!(&req.headers["X-Empty"], "");
+
+        let (_, req) = parse_all(VALID[8]);
+        let req = req.unwrap();
+        assert_eq!(req.headers.get_all("accept").collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(req.method, Method::Get); // a value containing "HTTP" is still a header
+        assert_eq!(&req.headers["User-Agent"], "HTTP client");
+
+        let (_, req) = parse_all(VALID[9]);
+        assert_eq!(&req.unwrap().headers["X-Latin"], "caf\u{fffd}");
+    }
+
+    #[test]
+    fn test_methods() {
+        assert_eq!(parse_all(VALID[10]).1.unwrap().method, Method::Delete);
+        let req = parse_all(VALID[11]).1.unwrap();
+        assert_eq!(req.method, Method::Extension("PROPFIND".to_string()));
+        assert_eq!(req.method.to_string(), "PROPFIND");
+    }
+
+    #[test]
+    fn test_status_code() {
+        let status = |raw: &[u8]| match parse_all(raw).0 {
+            Parsed::Error(e) => e.status_code().as_u16(),
+            parsed => panic!("{:?}", parsed),
+        };
+        assert_eq!(status(b"GET / HTTP/2.0\r\n\r\n"), 505);
+        assert_eq!(status(b"GET / HTTP/9.9\r\n\r\n"), 505);
+        assert_eq!(status(b"GET / HTTP/1\r\n\r\n"), 400);
+        assert_eq!(status(b"G@T / HTTP/1.1\r\n\r\n"), 400);
+        assert_eq!(status(b"POST / HTTP/1.1\r\nTransfer-Encoding: br, chunked\r\n\r\n"), 501);
+        assert_eq!(status(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n"), 400);
+    }
+
+    #[test]
+    fn test_chunked() {
+        let req = parse_all(VALID[15]).1.unwrap();
+        assert_eq!(req.msg_body, b"abcde");
+        assert_eq!(req.trailers.get("digest"), Some("1"));
+        assert_eq!(req.headers.get("transfer-encoding"), Some("Chunked"));
+        assert!(parse_all(VALID[16]).1.unwrap().msg_body.is_empty());
+
+        // 请求体之后的字节属于下一个请求
+        let mut raw = VALID[15].to_vec();
+        raw.extend_from_slice(b"GET /next HTTP/1.1\r\n\r\n");
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(&raw), Parsed::Complete(VALID[15].len()));
+        assert_eq!(parser.parse(&raw), Parsed::Complete(VALID[15].len()));
+        parser.take().unwrap();
+        assert_eq!(parser.parse(&raw[VALID[15].len()..]), Parsed::Complete(22));
+        assert_eq!(parser.take().unwrap().resource, Resource::Path("/next".to_string()));
+    }
+
+    // 声明的长度太大时不等请求体到达就报错，解析下一个请求时限制不变
+    #[test]
+    fn test_max_body() {
+        let mut parser = Parser::new().max_body(10);
+        assert_eq!(parser.parse(b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\n"), Parsed::Error(ParseError::BodyTooLarge));
+        assert_eq!(ParseError::BodyTooLarge.status_code(), StatusCode::CONTENT_TOO_LARGE);
+
+        let mut parser = Parser::new().max_body(10);
+        let raw = b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\n0123456789";
+        assert_eq!(parser.parse(raw), Parsed::Complete(raw.len()));
+        parser.take().unwrap();
+        let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n6\r\n012345\r\n6\r\n";
+        assert_eq!(parser.parse(raw), Parsed::Error(ParseError::BodyTooLarge));
+
+        let raw = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert_eq!(parse_all(raw.as_bytes()).0, Parsed::Error(ParseError::BodyTooLarge));
+    }
+
+    #[test]
+    fn test_pipelined() {
+        let raw = b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n";
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(raw), Parsed::Complete(19));
+        assert_eq!(parser.take().unwrap().resource, Resource::Path("/a".to_string()));
+        assert_eq!(parser.parse(&raw[19..]), Parsed::Complete(19));
+        assert_eq!(parser.take().unwrap().resource, Resource::Path("/b".to_string()));
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut raw = b"GET / HTTP/1.1\r\nX: ".to_vec();
+        raw.resize(MAX_HEAD + 1, b'a');
+        assert_eq!(parse_all(&raw).0, Parsed::Error(ParseError::TooLarge));
+        assert_eq!(parse_all(&vec![b'\n'; MAX_HEAD + 1]).0, Parsed::Error(ParseError::TooLarge));
+    }
+
+    // 每个前缀都是 Incomplete，逐字节送入和一次送入的结果相同
+    #[test]
+    fn test_byte_by_byte() {
+        for &raw in VALID {
+            for end in 0..raw.len() {
+                assert_eq!(parse_all(&raw[..end]).0, Parsed::Incomplete);
+            }
+            assert_same(&parse_chunks(raw, std::iter::repeat(1)), &parse_all(raw));
+        }
+        for (raw, _) in INVALID {
+            assert_same(&parse_chunks(raw, std::iter::repeat(1)), &parse_all(raw));
+        }
+    }
+
+    // xorshift，固定种子使结果可以重现
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    // 随机变异语料：解析器不能 panic，consumed 不超过输入，
+    // 以随机大小分段送入和一次送入的结果相同
+    #[test]
+    fn test_fuzz_mutations() {
+        let interesting = b"\r\n :\t\x00\x7f\xffGET HTTP/1.1Content-Length0123456789,";
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let corpus: Vec<&[u8]> = VALID.iter().copied().chain(INVALID.iter().map(|(raw, _)| *raw)).collect();
+        for _ in 0..5000 {
+            let mut raw = corpus[rng.below(corpus.len())].to_vec();
+            for _ in 0..1 + rng.below(4) {
+                let i = rng.below(raw.len() + 1);
+                match rng.below(4) {
+                    0 if i < raw.len() => raw[i] = interesting[rng.below(interesting.len())],
+                    1 if i < raw.len() => {
+                        raw.remove(i);
+                    }
+                    2 => raw.insert(i, interesting[rng.below(interesting.len())]),
+                    _ => raw.truncate(i),
+                }
+            }
+            let whole = parse_all(&raw);
+            if let Parsed::Complete(n) = whole.0 {
+                assert!(n <= raw.len());
+                assert!(whole.1.is_some());
+            }
+            let seed = rng.next();
+            let mut sizes = Rng(seed | 1);
+            let chunked = parse_chunks(&raw, std::iter::from_fn(|| Some(1 + sizes.below(16))));
+            assert_same(&chunked, &whole);
+        }
+    }
+}
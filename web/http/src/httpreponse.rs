@@ -1,116 +1,223 @@
+use crate::body::Body;
+use crate::chunked::ChunkedWriter;
+use crate::cookie::Cookie;
+use crate::headermap::{Connection, HeaderError, HeaderMap};
+use crate::httprequest::Version;
+use crate::statuscode::StatusCode;
 use std::collections::HashMap;
-use std::io::{Result, Write};
+use std::io::{self, Write};
 
 // 定义一个表示 HTTP 响应的结构体
-#[derive(Debug, PartialEq, Clone)]
-pub struct HttpResponse<'a> {
-    version: &'a str,                           // HTTP 版本号
-    status_code: &'a str,                       // 状态码
-    status_text: &'a str,                       // 状态文本
-    headers: Option<HashMap<&'a str, &'a str>>, // 可选的头部信息
-    body: Option<String>,                       // 可选的响应体
+#[derive(Debug, PartialEq)]
+pub struct HttpResponse {
+    version: Version,   // HTTP 版本号
+    status: StatusCode, // 状态码
+    headers: HeaderMap, // 头部信息
+    body: Body,         // 响应体，发送时才从文件或流中读出
 }
 
 // HttpResponse 结构体的默认实现
-impl<'a> Default for HttpResponse<'a> {
+impl Default for HttpResponse {
     fn default() -> Self {
         Self {
-            version: "HTTP/1.1".into(),
-            status_code: "200".into(),
-            status_text: "OK".into(),
-            headers: None,
-            body: None,
+            version: Version::V1_1,
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Body::Empty,
         }
     }
 }
 
-// 将 HttpResponse 转换为 String 的实现
-impl<'a> From<HttpResponse<'a>> for String {
-    fn from(res: HttpResponse<'a>) -> String {
-        let res1 = res.clone();
-        format!(
-            "{} {} {}\r\n{}Content-length: {}\r\n\r\n{}",
-            &res1.version(),
-            &res1.status_code(),
-            &res1.status_text(),
-            &res1.headers(),
-            &res.body.unwrap().len(),
-            &res1.body()
-        )
+// 将 HttpResponse 转换为发送时的字节；读取文件或流出错时 panic
+impl From<HttpResponse> for Vec<u8> {
+    fn from(res: HttpResponse) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        res.send_response(&mut bytes).expect("failed to read the response body");
+        bytes
+    }
+}
+
+// 将 HttpResponse 转换为 String 的实现，响应体不是 UTF-8 时有损
+impl From<HttpResponse> for String {
+    fn from(res: HttpResponse) -> String {
+        String::from_utf8_lossy(&Vec::from(res)).into_owned()
     }
 }
 
 // HttpResponse 结构体的实现
-impl<'a> HttpResponse<'a> {
-    // 创建一个新的 HttpResponse
+impl HttpResponse {
+    // 创建一个新的 HttpResponse，没有给出头部时 Content-Type 是 text/html。
+    // 状态码不在 100 到 599 之间时 panic。
     pub fn new(
-        status_code: &'a str,
-        headers: Option<HashMap<&'a str, &'a str>>,
+        status_code: u16,
+        headers: Option<HashMap<&str, &str>>,
         body: Option<String>,
-    ) -> HttpResponse<'a> {
-        let mut response = HttpResponse::default();
-        if status_code != "200" {
-            response.status_code = status_code.into();
-        };
-        response.headers = match &headers {
-            Some(_h) => headers,
-            None => {
-                let mut h = HashMap::new();
-                h.insert("Content-Type", "text/html");
-                Some(h)
-            }
-        };
-        response.status_text = match response.status_code {
-            "200" => "OK".into(),
-            "400" => "Bad Request".into(),
-            "404" => "Not Found".into(),
-            "500" => "Internal Server Error".into(),
-            _ => "Not Found".into(),
-        };
-        response.body = body;
-        response
+    ) -> HttpResponse {
+        let headers = headers.unwrap_or_else(|| HashMap::from([("Content-Type", "text/html")]));
+        let mut builder = HttpResponse::builder().status(status_code);
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        builder.body(body.unwrap_or_default())
+    }
+
+    pub fn builder() -> ResponseBuilder {
+        ResponseBuilder {
+            response: HttpResponse::default(),
+        }
     }
 
-    // 将 HttpResponse 发送到指定的写入流
-    pub fn send_response(&self, write_stream: &mut impl Write) -> Result<()> {
-        let res = self.clone();
-        let response_string: String = String::from(res);
-        let _ = write!(write_stream, "{}", response_string);
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
 
-        Ok(())
+    pub fn set_status(&mut self, status: StatusCode) {
+        self.status = status;
     }
 
-    // 获取 HTTP 版本号
-    fn version(&self) -> &str {
+    pub fn version(&self) -> Version {
         self.version
     }
 
-    // 获取状态码
-    fn status_code(&self) -> &str {
-        self.status_code
+    // 应答 HTTP/1.0 的请求时设为 V1_0，长度未知的响应体就不用 chunked
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
     }
 
-    // 获取状态文本
-    fn status_text(&self) -> &str {
-        self.status_text
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
     }
 
-    // 获取头部信息的字符串表示
-    fn headers(&self) -> String {
-        let map: HashMap<&str, &str> = self.headers.clone().unwrap();
-        let mut header_string: String = "".into();
-        for (k, v) in map.iter() {
-            header_string = format!("{}{}: {}\r\n", header_string, k, v);
-        }
-        header_string
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    // 设置一个头部，替换同名的旧值；名称或值无效时不设置
+    pub fn set_header(&mut self, name: impl Into<String>, value: impl Into<String>) -> Result<(), HeaderError> {
+        self.headers.insert(name, value)
+    }
+
+    // 加入一个头部，保留同名的旧值，如多个 Set-Cookie；名称或值无效时不加入
+    pub fn append_header(&mut self, name: impl Into<String>, value: impl Into<String>) -> Result<(), HeaderError> {
+        self.headers.append(name, value)
+    }
+
+    // 加入一个 Set-Cookie 头部，Cookie 构建时已经检查过
+    pub fn add_cookie(&mut self, cookie: &Cookie) {
+        self.headers.append_unchecked("Set-Cookie", cookie.to_string());
     }
 
     // 获取响应体
-    pub fn body(&self) -> &str {
-        match &self.body {
-            Some(b) => b.as_str(),
-            None => "",
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    pub fn set_body(&mut self, body: impl Into<Body>) {
+        self.body = body.into();
+    }
+
+    // 取出响应体，留下 Body::Empty
+    pub fn take_body(&mut self) -> Body {
+        std::mem::take(&mut self.body)
+    }
+
+    // 状态行和头部，以空行结束；响应体需要 chunked 编码时返回 true。
+    // 1xx 和 204 不发送 Content-Length；304 只保留明确设置的值 (即 200 时的长度)；
+    // 其他状态码没有设置时按响应体补上，长度未知时用 Transfer-Encoding: chunked。
+    // HTTP/1.0 没有 chunked，长度未知的响应体以关闭连接结束 (Connection: close)。
+    fn head(&self) -> (String, bool) {
+        let mut headers = self.headers.clone();
+        let mut chunked = false;
+        if self.status.is_informational() || self.status == StatusCode::NO_CONTENT {
+            headers.remove("Content-Length");
+        } else if self.status.allows_body() && !headers.contains_key("Content-Length") {
+            match self.body.len() {
+                Some(len) => headers.set_content_length(len),
+                None if self.version == Version::V1_0 => headers.set_connection(Connection::Close),
+                None => {
+                    headers.insert_unchecked("Transfer-Encoding", "chunked");
+                    chunked = true;
+                }
+            }
         }
+        let head = format!(
+            "{} {} {}\r\n{}\r\n",
+            self.version,
+            self.status.as_u16(),
+            self.status.reason().unwrap_or(""),
+            headers
+        );
+        (head, chunked)
+    }
+
+    // 发送头部，再把响应体直接从内存、文件或流写入 `write_stream`
+    pub fn send_response(self, write_stream: &mut impl Write) -> io::Result<()> {
+        let (head, chunked) = self.head();
+        write_stream.write_all(head.as_bytes())?;
+        if self.status.allows_body() {
+            if chunked {
+                let mut writer = ChunkedWriter::new(&mut *write_stream);
+                self.body.write_to(&mut writer)?;
+                writer.finish(&HeaderMap::new())?;
+            } else {
+                self.body.write_to(write_stream)?;
+            }
+        }
+        write_stream.flush()
+    }
+
+    // 只发送状态行和头部，用于应答 HEAD 请求；Content-Length 仍是响应体的长度
+    pub fn send_head(&self, write_stream: &mut impl Write) -> io::Result<()> {
+        write_stream.write_all(self.head().0.as_bytes())?;
+        write_stream.flush()
+    }
+}
+
+// HttpResponse::builder().status(201).header("Location", "/x").body("created")
+#[derive(Debug)]
+pub struct ResponseBuilder {
+    response: HttpResponse,
+}
+
+impl ResponseBuilder {
+    // 状态码不在 100 到 599 之间时 panic
+    pub fn status(mut self, code: u16) -> Self {
+        self.response.status = StatusCode::try_from(code).unwrap_or_else(|e| panic!("{}", e));
+        self
+    }
+
+    pub fn status_code(mut self, status: StatusCode) -> Self {
+        self.response.status = status;
+        self
+    }
+
+    // 加入一个头部，保留同名的旧值。名称或值无效的头部被丢弃，
+    // 不可信的值应该用 HttpResponse::append_header 检查结果
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let _ = self.response.headers.append(name, value);
+        self
+    }
+
+    // 无效时同样被丢弃
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        let _ = self.response.headers.set_content_type(content_type);
+        self
+    }
+
+    pub fn cookie(mut self, cookie: &Cookie) -> Self {
+        self.response.add_cookie(cookie);
+        self
+    }
+
+    // 给出响应体，完成构建
+    pub fn body(mut self, body: impl Into<Body>) -> HttpResponse {
+        self.response.body = body.into();
+        self.response
+    }
+
+    // 没有响应体
+    pub fn build(self) -> HttpResponse {
+        self.response
     }
 }
 
@@ -118,20 +225,16 @@ impl<'a> HttpResponse<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Cursor;
 
     #[test]
     fn test_response_struct_creation_200() {
-        let response_actual = HttpResponse::new("200", None, Some("xxxx".into()));
+        let response_actual = HttpResponse::new(200, None, Some("xxxx".into()));
         let response_expected = HttpResponse {
-            version: "HTTP/1.1",
-            status_code: "200",
-            status_text: "OK",
-            headers: {
-                let mut h = HashMap::new();
-                h.insert("Content-Type", "text/html");
-                Some(h)
-            },
-            body: Some("xxxx".into()),
+            version: Version::V1_1,
+            status: StatusCode::OK,
+            headers: [("Content-Type", "text/html")].into_iter().collect(),
+            body: Body::from("xxxx"),
         };
 
         assert_eq!(response_actual, response_expected);
@@ -139,17 +242,12 @@ mod tests {
 
     #[test]
     fn test_response_struct_creation_404() {
-        let response_actual = HttpResponse::new("404", None, Some("xxxx".into()));
+        let response_actual = HttpResponse::new(404, None, Some("xxxx".into()));
         let response_expected = HttpResponse {
-            version: "HTTP/1.1",
-            status_code: "404",
-            status_text: "Not Found",
-            headers: {
-                let mut h = HashMap::new();
-                h.insert("Content-Type", "text/html");
-                Some(h)
-            },
-            body: Some("xxxx".into()),
+            version: Version::V1_1,
+            status: StatusCode::NOT_FOUND,
+            headers: [("Content-Type", "text/html")].into_iter().collect(),
+            body: Body::from("xxxx"),
         };
 
         assert_eq!(response_actual, response_expected);
@@ -158,19 +256,169 @@ mod tests {
     #[test]
     fn test_http_response_creation() {
         let response_expected = HttpResponse {
-            version: "HTTP/1.1",
-            status_code: "404",
-            status_text: "Not Found",
-            headers: {
-                let mut h = HashMap::new();
-                h.insert("Content-Type", "text/html");
-                Some(h)
-            },
-            body: Some("xxxx".into()),
+            version: Version::V1_1,
+            status: StatusCode::NOT_FOUND,
+            headers: [("Content-Type", "text/html")].into_iter().collect(),
+            body: Body::from("xxxx"),
         };
         let http_string: String = response_expected.into();
         let actual_string =
-            "HTTP/1.1 404 Not Found\r\nContent-Type: text/html\r\nContent-length: 4\r\n\r\nxxxx";
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/html\r\nContent-Length: 4\r\n\r\nxxxx";
         assert_eq!(http_string, actual_string);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_multiple_headers() {
+        let mut response = HttpResponse::new(200, None, Some("".into()));
+        response.append_header("Set-Cookie", "a=1").unwrap();
+        response.append_header("Set-Cookie", "b=2").unwrap();
+        response.set_header("content-type", "text/plain").unwrap();
+        let http_string: String = response.into();
+        assert_eq!(
+            http_string,
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nContent-Length: 0\r\n\r\n"
+        );
+    }
+
+    // 响应拆分：值中的 CRLF 不能变成新的头部行
+    #[test]
+    fn test_invalid_headers() {
+        let mut response = HttpResponse::builder()
+            .header("X", "a\r\nInjected: 1")
+            .header("Bad Name", "1")
+            .content_type("text/html\r\nInjected: 1")
+            .build();
+        assert_eq!(response.set_header("X", "a\r\nInjected: 1"), Err(HeaderError::Value));
+        assert_eq!(response.append_header("X\r\nInjected", "1"), Err(HeaderError::Name));
+        let out: String = response.into();
+        assert_eq!(out, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn test_send_head() {
+        let response = HttpResponse::new(200, None, Some("xxxx".into()));
+        let mut out = Vec::new();
+        response.send_head(&mut out).unwrap();
+        let head = String::from_utf8(out).unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.ends_with("Content-Length: 4\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_cookies() {
+        let session = Cookie::builder("session", "abc").path("/").http_only(true).build().unwrap();
+        let mut response = HttpResponse::builder().cookie(&session).build();
+        response.add_cookie(&Cookie::builder("theme", "dark").max_age(0).build().unwrap());
+        let out: String = response.into();
+        assert_eq!(
+            out,
+            "HTTP/1.1 200 OK\r\nSet-Cookie: session=abc; Path=/; HttpOnly\r\n\
+             Set-Cookie: theme=dark; Max-Age=0\r\nContent-Length: 0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let response = HttpResponse::builder()
+            .status(201)
+            .header("Location", "/orders/7")
+            .content_type("application/json")
+            .body(vec![b'{', b'}']);
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers().get("location"), Some("/orders/7"));
+        assert_eq!(response.body().as_bytes(), Some(&b"{}"[..]));
+        let bytes: Vec<u8> = response.into();
+        assert_eq!(
+            bytes,
+            b"HTTP/1.1 201 Created\r\nLocation: /orders/7\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}"
+        );
+
+        // 没有响应体的 200 仍有 Content-Length: 0
+        let empty: String = HttpResponse::builder().build().into();
+        assert_eq!(empty, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        // 没有注册的状态码没有原因短语
+        let custom: String = HttpResponse::builder().status(299).build().into();
+        assert!(custom.starts_with("HTTP/1.1 299 \r\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid status code 600")]
+    fn test_builder_invalid_status() {
+        HttpResponse::builder().status(600);
+    }
+
+    #[test]
+    fn test_byte_body() {
+        let body = vec![0, 159, 146, 150, 255];
+        let response = HttpResponse::builder().body(body.clone());
+        let mut out = Vec::new();
+        response.send_response(&mut out).unwrap();
+        assert!(out.ends_with(b"Content-Length: 5\r\n\r\n\x00\x9f\x92\x96\xff"));
+    }
+
+    // 长度未知的响应体用 chunked 编码，文件按打开时的长度发送
+    #[test]
+    fn test_stream_bodies() {
+        let response = HttpResponse::builder().body(Body::reader(Cursor::new(vec![b'a'; 10000])));
+        let out: String = response.into();
+        let (head, chunks) = out.split_once("\r\n\r\n").unwrap();
+        assert_eq!(head, "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked");
+        assert!(chunks.ends_with("\r\n0\r\n\r\n"));
+        let mut decoder = crate::chunked::ChunkedDecoder::new();
+        assert_eq!(decoder.decode(chunks.as_bytes()), Ok(Some(chunks.len())));
+        assert_eq!(decoder.take_body(), vec![b'a'; 10000]);
+
+        let response = HttpResponse::builder().body(Body::chunks(vec![b"ab".to_vec(), b"c".to_vec()]));
+        let out: String = response.into();
+        assert!(out.ends_with("Transfer-Encoding: chunked\r\n\r\n2\r\nab\r\n1\r\nc\r\n0\r\n\r\n"));
+
+        // HTTP/1.0：不用 chunked，关闭连接表示结束
+        let mut response = HttpResponse::builder().body(Body::chunks(vec![b"ab".to_vec(), b"c".to_vec()]));
+        response.set_version(Version::V1_0);
+        let out: String = response.into();
+        assert_eq!(out, "HTTP/1.0 200 OK\r\nConnection: close\r\n\r\nabc");
+        let mut response = HttpResponse::builder().body("abc");
+        response.set_version(Version::V1_0);
+        let out: String = response.into();
+        assert_eq!(out, "HTTP/1.0 200 OK\r\nContent-Length: 3\r\n\r\nabc");
+
+        // 明确给出长度时直接发送
+        let response = HttpResponse::builder()
+            .header("Content-Length", "3")
+            .body(Body::reader(Cursor::new("abc")));
+        let out: String = response.into();
+        assert_eq!(out, "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc");
+
+        let path = std::env::temp_dir().join(format!("httpreponse-{}", std::process::id()));
+        std::fs::write(&path, "from a file").unwrap();
+        let body = Body::file(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut out = Vec::new();
+        HttpResponse::builder().body(body).send_response(&mut out).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(out.ends_with(b"Content-Length: 11\r\n\r\nfrom a file"));
+
+        // 204 只有头部
+        let response = HttpResponse::builder().status(204).body(Body::reader(Cursor::new("x")));
+        let out: String = response.into();
+        assert_eq!(out, "HTTP/1.1 204 No Content\r\n\r\n");
+    }
+
+    // 204 和 304 不发送响应体；204 也不发送 Content-Length
+    #[test]
+    fn test_no_body_statuses() {
+        let no_content: String = HttpResponse::builder()
+            .status(204)
+            .header("Content-Length", "4")
+            .body("xxxx")
+            .into();
+        assert_eq!(no_content, "HTTP/1.1 204 No Content\r\n\r\n");
+
+        let not_modified: String = HttpResponse::builder().status(304).header("ETag", "\"1\"").body("xxxx").into();
+        assert_eq!(not_modified, "HTTP/1.1 304 Not Modified\r\nETag: \"1\"\r\n\r\n");
+
+        let mut out = Vec::new();
+        let response = HttpResponse::builder().status(304).header("Content-Length", "4").body("xxxx");
+        response.send_response(&mut out).unwrap();
+        assert_eq!(out, b"HTTP/1.1 304 Not Modified\r\nContent-Length: 4\r\n\r\n");
+    }
+}
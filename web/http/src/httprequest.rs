@@ -1,44 +1,116 @@
 // Http解析
-use std::collections::HashMap;
-
-#[derive(Debug, PartialEq)]
+use crate::bodyparser::{BodyError, BodyParser, Multipart};
+use crate::cookie::CookieJar;
+use crate::headermap::HeaderMap;
+use crate::httpparser::{is_tchar, ParseError, Parsed, Parser};
+use crate::resource::QueryMap;
+pub use crate::resource::Resource;
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Method {
     Get,
     Post,
+    Put,
+    Delete,
+    Patch,
+    Head,
+    Options,
+    Connect,
+    Trace,
+    Extension(String), // 其他合法的 token，如 WebDAV 的 PROPFIND
     Uninitialized,
 }
 
-impl From<&str> for Method {
-    fn from(s: &str) -> Method {
-        match s {
+// 方法区分大小写；不是 token 的返回 ParseError::Method
+impl TryFrom<&str> for Method {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Method, ParseError> {
+        Ok(match s {
             "GET" => Method::Get,
             "POST" => Method::Post,
-            _ => Method::Uninitialized,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "PATCH" => Method::Patch,
+            "HEAD" => Method::Head,
+            "OPTIONS" => Method::Options,
+            "CONNECT" => Method::Connect,
+            "TRACE" => Method::Trace,
+            _ if !s.is_empty() && s.bytes().all(is_tchar) => Method::Extension(s.to_string()),
+            _ => return Err(ParseError::Method),
+        })
+    }
+}
+
+impl Method {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Patch => "PATCH",
+            Method::Head => "HEAD",
+            Method::Options => "OPTIONS",
+            Method::Connect => "CONNECT",
+            Method::Trace => "TRACE",
+            Method::Extension(s) => s,
+            Method::Uninitialized => "",
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Version {
     V1_0,
     V1_1,
+    V2_0,
     Uninitialized,
 }
 
-impl From<&str> for Version {
-    fn from(s: &str) -> Version {
+// 格式正确但不认识的版本 (如 HTTP/3.0) 返回 ParseError::UnsupportedVersion，
+// 其他返回 ParseError::Version
+impl TryFrom<&str> for Version {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Version, ParseError> {
         match s {
-            "HTTP/1.0" => Version::V1_0,
-            "HTTP/1.1" => Version::V1_1, // Add this line to correctly match the version
-            _ => Version::Uninitialized,
+            "HTTP/1.0" => Ok(Version::V1_0),
+            "HTTP/1.1" => Ok(Version::V1_1),
+            "HTTP/2.0" | "HTTP/2" => Ok(Version::V2_0),
+            _ => match s.strip_prefix("HTTP/").map(str::as_bytes) {
+                Some([major, b'.', minor]) if major.is_ascii_digit() && minor.is_ascii_digit() => {
+                    Err(ParseError::UnsupportedVersion)
+                }
+                _ => Err(ParseError::Version),
+            },
+        }
+    }
+}
+
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::V1_0 => "HTTP/1.0",
+            Version::V1_1 => "HTTP/1.1",
+            Version::V2_0 => "HTTP/2.0",
+            Version::Uninitialized => "",
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Resource {
-    // Path
-    Path(String),
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Debug)]
@@ -46,69 +118,51 @@ pub struct HttpRequest {
     pub method: Method,
     pub version: Version,
     pub resource: Resource,
-    pub headers: HashMap<String, String>,
-    pub msg_body: String,
+    pub headers: HeaderMap,
+    pub cookies: CookieJar,  // 从 Cookie 头部解析
+    pub msg_body: Vec<u8>,   // 原始字节；chunked 的请求体已经解码
+    pub trailers: HeaderMap, // chunked 请求体之后的尾部字段
+    // 用 `Parser::body_parser` 边接收边解析的 multipart 请求体，这时 msg_body 为空
+    pub parts: Option<Result<Multipart, BodyError>>,
 }
 
-impl From<String> for HttpRequest {
-    fn from(req: String) -> Self {
-        let mut parsed_method = Method::Uninitialized;
-        let mut parsed_version = Version::Uninitialized;
-        let mut parsed_resource = Resource::Path("".to_string());
-        let mut parsed_headers = HashMap::new();
-        let mut parsed_msg_body = "";
-
-        for line in req.lines() {
-            if line.contains("HTTP") {
-                let (method, resource, version) = process_req_line(line);
-                parsed_method = method;
-                parsed_resource = resource;
-                parsed_version = version;
-            } else if line.contains(":") {
-                let (key, value) = process_headers_line(line);
-                parsed_headers.insert(key, value);
-            } else if line.len() == 0 {
-                // Skip empty line
-            } else {
-                parsed_msg_body = line;
-            }
-        }
+// 按默认的 BodyParser 解析请求体；需要其他限制时直接使用 BodyParser
+impl HttpRequest {
+    pub fn form(&self) -> Result<QueryMap, BodyError> {
+        BodyParser::default().form(self)
+    }
 
-        HttpRequest {
-            method: parsed_method,
-            version: parsed_version,
-            resource: parsed_resource,
-            headers: parsed_headers,
-            msg_body: parsed_msg_body.to_owned(),
-        }
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, BodyError> {
+        BodyParser::default().json(self)
     }
-}
 
-fn process_req_line(s: &str) -> (Method, Resource, Version) {
-    let mut words = s.split_whitespace();
-    let method = words.next().unwrap();
-    let resource = words.next().unwrap();
-    let version = words.next().unwrap();
-
-    (
-        method.into(),
-        Resource::Path(resource.to_string()),
-        version.into(),
-    )
+    // 已经边接收边解析时取出那个结果，只能取一次
+    pub fn multipart(&mut self) -> Result<Multipart, BodyError> {
+        match self.parts.take() {
+            Some(parts) => parts,
+            None => BodyParser::default().multipart(self),
+        }
+    }
 }
 
-fn process_headers_line(s: &str) -> (String, String) {
-    let mut headers_items = s.split(":");
-    let mut key = String::from(":");
-    let mut value = String::from(":");
-    if let Some(k) = headers_items.next() {
-        key = k.to_string();
-    }
-    if let Some(v) = headers_items.next() {
-        value = v.to_string();
+// 用 httpparser 解析一个完整的请求；不完整或出错时，方法和版本为 Uninitialized
+impl From<String> for HttpRequest {
+    fn from(req: String) -> Self {
+        let mut parser = Parser::new();
+        match parser.parse(req.as_bytes()) {
+            Parsed::Complete(_) => parser.take().unwrap(),
+            Parsed::Incomplete | Parsed::Error(_) => HttpRequest {
+                method: Method::Uninitialized,
+                version: Version::Uninitialized,
+                resource: Resource::Path("".to_string()),
+                headers: HeaderMap::new(),
+                cookies: CookieJar::new(),
+                msg_body: Vec::new(),
+                trailers: HeaderMap::new(),
+                parts: None,
+            },
+        }
     }
-
-    (key, value)
 }
 
 #[cfg(test)]
@@ -117,29 +171,55 @@ mod tests {
 
     #[test]
     fn test_method_from() {
-        let m: Method = Method::from("GET"); // "GET".into() also works
-        assert_eq!(m, Method::Get);
+        let m = Method::try_from("GET"); // "GET".try_into() also works
+        assert_eq!(m, Ok(Method::Get));
+        for name in ["POST", "PUT", "DELETE", "PATCH", "HEAD", "OPTIONS", "CONNECT", "TRACE", "PROPFIND"] {
+            assert_eq!(Method::try_from(name).unwrap().as_str(), name);
+        }
+        assert_eq!(Method::try_from("get"), Ok(Method::Extension("get".to_string())));
+        assert_eq!(Method::try_from(""), Err(ParseError::Method));
+        assert_eq!(Method::try_from("GE T"), Err(ParseError::Method));
+        assert_eq!(Method::try_from("G\u{e9}T"), Err(ParseError::Method));
     }
 
     #[test]
     fn test_version_from() {
-        let v: Version = Version::from("HTTP/1.0");
-        assert_eq!(v, Version::V1_0);
+        let v = Version::try_from("HTTP/1.0");
+        assert_eq!(v, Ok(Version::V1_0));
+        assert_eq!(Version::try_from("HTTP/1.1"), Ok(Version::V1_1));
+        assert_eq!(Version::try_from("HTTP/2.0"), Ok(Version::V2_0));
+        assert_eq!(Version::try_from("HTTP/2"), Ok(Version::V2_0));
+        assert_eq!(Version::try_from("HTTP/3.0"), Err(ParseError::UnsupportedVersion));
+        assert_eq!(Version::try_from("HTTP/1.12"), Err(ParseError::Version));
+        assert_eq!(Version::try_from("http/1.1"), Err(ParseError::Version));
+        assert_eq!(Version::V2_0.to_string(), "HTTP/2.0");
     }
 
     #[test]
     fn test_read_http() {
         let s: String = String::from("GET /greeting HTTP/1.1\r\nHost:localhost:8080\r\nUser-Agent:curl/7.64.1\r\nAccept:*/*\r\n\r\n");
-        let mut headers = HashMap::new();
-        headers.insert("Host".into(), "localhost".into());
-        headers.insert("Accept".into(), "*/*".into());
-        headers.insert("User-Agent".into(), "curl/7.64.1".into());
+        let headers: HeaderMap = [
+            ("Host", "localhost:8080"),
+            ("User-Agent", "curl/7.64.1"),
+            ("Accept", "*/*"),
+        ]
+        .into_iter()
+        .collect();
         let req: HttpRequest = s.into();
 
         assert_eq!(Method::Get, req.method);
         assert_eq!(Version::V1_1, req.version);
         assert_eq!(Resource::Path("/greeting".to_string()), req.resource);
         assert_eq!(headers, req.headers);
-        assert_eq!("", req.msg_body); // Check the message body
+        assert!(req.cookies.is_empty());
+        assert!(req.msg_body.is_empty()); // Check the message body
+    }
+
+    #[test]
+    fn test_cookies() {
+        let s = String::from("GET / HTTP/1.1\r\nCookie: id=a3fWa; theme=dark\r\n\r\n");
+        let req: HttpRequest = s.into();
+        assert_eq!(req.cookies.get("id"), Some("a3fWa"));
+        assert_eq!(req.cookies.get("theme"), Some("dark"));
     }
 }
\ No newline at end of file
@@ -1,2 +1,11 @@
+pub mod body;
+pub mod bodyparser;
+pub mod chunked;
+pub mod compression;
+pub mod cookie;
+pub mod headermap;
+pub mod httpparser;
 pub mod httpreponse;
-pub mod httprequest;
\ No newline at end of file
+pub mod httprequest;
+pub mod resource;
+pub mod statuscode;
@@ -0,0 +1,347 @@
+// 请求目标 (RFC 9112 3.2) 和查询字符串
+//
+// `Resource` 保存原始的目标，按需给出解码、规范化后的路径和解析后的查询参数。
+use crate::httpparser::ParseError;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resource {
+    // origin-form：`/path?query`
+    Path(String),
+    // absolute-form：`http://host/path?query`，target 是其中的 `/path?query`
+    Absolute {
+        scheme: String,
+        authority: String,
+        target: String,
+    },
+    // asterisk-form：`OPTIONS * HTTP/1.1`
+    Asterisk,
+    // authority-form：`CONNECT host:port HTTP/1.1`
+    Authority(String),
+}
+
+impl TryFrom<&str> for Resource {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Resource, ParseError> {
+        if s == "*" {
+            return Ok(Resource::Asterisk);
+        }
+        if s.starts_with('/') {
+            return Ok(Resource::Path(s.to_string()));
+        }
+        if let Some((scheme, rest)) = s.split_once("://") {
+            let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+            if !valid_scheme {
+                return Err(ParseError::Target);
+            }
+            let end = rest.find(['/', '?']).unwrap_or(rest.len());
+            let (authority, target) = rest.split_at(end);
+            if authority.is_empty() {
+                return Err(ParseError::Target);
+            }
+            let target = match target {
+                "" => "/".to_string(),
+                t if t.starts_with('?') => format!("/{}", t),
+                t => t.to_string(),
+            };
+            return Ok(Resource::Absolute {
+                scheme: scheme.to_ascii_lowercase(),
+                authority: authority.to_string(),
+                target,
+            });
+        }
+        // host:port，端口必须是数字
+        match s.rsplit_once(':') {
+            Some((host, port))
+                if !host.is_empty()
+                    && !host.contains(['/', '?', '#', '@'])
+                    && !port.is_empty()
+                    && port.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                Ok(Resource::Authority(s.to_string()))
+            }
+            _ => Err(ParseError::Target),
+        }
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Resource::Path(s) | Resource::Authority(s) => f.write_str(s),
+            Resource::Absolute { scheme, authority, target } => {
+                write!(f, "{}://{}{}", scheme, authority, target)
+            }
+            Resource::Asterisk => f.write_str("*"),
+        }
+    }
+}
+
+impl Resource {
+    // `/path?query` 部分；asterisk-form 和 authority-form 没有
+    fn origin(&self) -> Option<&str> {
+        match self {
+            Resource::Path(s) => Some(s),
+            Resource::Absolute { target, .. } => Some(target),
+            Resource::Asterisk | Resource::Authority(_) => None,
+        }
+    }
+
+    // 主机，来自 absolute-form 或 authority-form
+    pub fn host(&self) -> Option<&str> {
+        match self {
+            Resource::Absolute { authority, .. } | Resource::Authority(authority) => Some(authority),
+            _ => None,
+        }
+    }
+
+    // 未解码的路径，不含查询字符串
+    pub fn raw_path(&self) -> Option<&str> {
+        let origin = self.origin()?;
+        Some(origin.split_once('?').map_or(origin, |(path, _)| path))
+    }
+
+    // 未解码的查询字符串，不含 `?`
+    pub fn raw_query(&self) -> Option<&str> {
+        self.origin()?.split_once('?').map(|(_, query)| query)
+    }
+
+    // 解码后的路径段，已经去掉 `.` 和 `..`，不会越过根。每段分别解码，
+    // 所以 `%2F` 留在段内：`/a%2Fb/c` 是 ["a/b", "c"]。
+    // 以 `/` 结尾时最后一段为空，`/` 本身是 [""]。
+    pub fn segments(&self) -> Vec<String> {
+        let Some(path) = self.raw_path() else {
+            return Vec::new();
+        };
+        let parts: Vec<&str> = path.strip_prefix('/').unwrap_or(path).split('/').collect();
+        let mut segments = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            let segment = decode(part);
+            match segment.as_str() {
+                "." => (),
+                ".." => {
+                    segments.pop();
+                }
+                _ => {
+                    segments.push(segment);
+                    continue;
+                }
+            }
+            // 以 `.` 或 `..` 结尾时，路径以 `/` 结尾
+            if i + 1 == parts.len() {
+                segments.push(String::new());
+            }
+        }
+        segments
+    }
+
+    // 解码、规范化后的路径，如 `/a/./b/../c%20d` 是 `/a/c d`。
+    // asterisk-form 是 `*`，authority-form 是空字符串。
+    pub fn path(&self) -> String {
+        match self {
+            Resource::Asterisk => "*".to_string(),
+            Resource::Authority(_) => String::new(),
+            _ => format!("/{}", self.segments().join("/")),
+        }
+    }
+
+    // 解析后的查询参数；没有查询字符串时为空
+    pub fn query(&self) -> QueryMap {
+        self.raw_query().map(QueryMap::parse).unwrap_or_default()
+    }
+}
+
+// 查询参数：同一名称可以有多个值，迭代时按出现的顺序
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryMap {
+    pairs: Vec<(String, String)>,
+}
+
+impl QueryMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 解析 `a=1&b=2&a=3`：`+` 是空格，没有 `=` 的值为空，空的部分被忽略
+    pub fn parse(s: &str) -> QueryMap {
+        s.split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_form(name), decode_form(value))
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    // 第一个值，名称区分大小写
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs.iter().filter(move |(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.pairs.push((name.into(), value.into()));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+impl<N: Into<String>, V: Into<String>> FromIterator<(N, V)> for QueryMap {
+    fn from_iter<I: IntoIterator<Item = (N, V)>>(iter: I) -> Self {
+        let mut query = QueryMap::new();
+        for (name, value) in iter {
+            query.append(name, value);
+        }
+        query
+    }
+}
+
+// 百分号解码；不完整或不是十六进制的 `%` 原样保留
+pub fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match (bytes[i], bytes.get(i + 1).and_then(|&b| hex(b)), bytes.get(i + 2).and_then(|&b| hex(b))) {
+            (b'%', Some(hi), Some(lo)) => {
+                out.push((hi * 16 + lo) as u8);
+                i += 3;
+            }
+            (b, _, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+// 解码为字符串，无效的 UTF-8 被替换为 U+FFFD
+pub fn decode(s: &str) -> String {
+    String::from_utf8_lossy(&percent_decode(s)).into_owned()
+}
+
+// application/x-www-form-urlencoded 的解码，`+` 是空格
+pub fn decode_form(s: &str) -> String {
+    decode(&s.replace('+', " "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(s: &str) -> Resource {
+        Resource::try_from(s).unwrap()
+    }
+
+    #[test]
+    fn test_forms() {
+        assert_eq!(resource("/a?b"), Resource::Path("/a?b".to_string()));
+        assert_eq!(resource("*"), Resource::Asterisk);
+        assert_eq!(resource("example.com:443"), Resource::Authority("example.com:443".to_string()));
+        assert_eq!(
+            resource("HTTP://example.com:8080?x=1"),
+            Resource::Absolute {
+                scheme: "http".to_string(),
+                authority: "example.com:8080".to_string(),
+                target: "/?x=1".to_string(),
+            }
+        );
+        for bad in ["", "a/b", "http://", "http:///a", "1x://a/", "host:", "host:port", ":80"] {
+            assert_eq!(Resource::try_from(bad), Err(ParseError::Target), "{:?}", bad);
+        }
+        for s in ["/a?b", "*", "example.com:443", "https://h/x?y"] {
+            assert_eq!(resource(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_path_and_query() {
+        let r = resource("http://h:1/a%20b/c?x=1&y=%41");
+        assert_eq!(r.host(), Some("h:1"));
+        assert_eq!(r.raw_path(), Some("/a%20b/c"));
+        assert_eq!(r.raw_query(), Some("x=1&y=%41"));
+        assert_eq!(r.path(), "/a b/c");
+
+        let r = resource("/plain");
+        assert_eq!(r.host(), None);
+        assert_eq!(r.raw_query(), None);
+        assert!(r.query().is_empty());
+
+        assert_eq!(resource("*").path(), "*");
+        assert_eq!(resource("*").segments(), Vec::<String>::new());
+        assert_eq!(resource("h:1").path(), "");
+        assert_eq!(resource("h:1").raw_query(), None);
+    }
+
+    #[test]
+    fn test_segments() {
+        let segments = |s: &str| resource(s).segments();
+        assert_eq!(segments("/"), [""]);
+        assert_eq!(segments("/a/b"), ["a", "b"]);
+        assert_eq!(segments("/a/b/"), ["a", "b", ""]);
+        assert_eq!(segments("/a%2Fb/c"), ["a/b", "c"]);
+        assert_eq!(segments("/a//b"), ["a", "", "b"]);
+        assert_eq!(segments("/a/b?c/d"), ["a", "b"]);
+    }
+
+    // RFC 3986 5.2.4 的例子，以及编码过的点
+    #[test]
+    fn test_dot_segments() {
+        let path = |s: &str| resource(s).path();
+        assert_eq!(path("/a/b/c/./../../g"), "/a/g");
+        assert_eq!(path("/mid/content=5/../6"), "/mid/6");
+        assert_eq!(path("/a/./b/../c%20d"), "/a/c d");
+        assert_eq!(path("/a/b/.."), "/a/");
+        assert_eq!(path("/a/."), "/a/");
+        assert_eq!(path("/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(path("/%2e%2E/%2e/secret"), "/secret");
+        assert_eq!(path("/.."), "/");
+        assert_eq!(path("/a/..b/c."), "/a/..b/c.");
+    }
+
+    #[test]
+    fn test_query() {
+        let q = QueryMap::parse("a=1&b=x+y&a=%32&&c&d=&e%3D=%26");
+        assert_eq!(q.get("a"), Some("1"));
+        assert_eq!(q.get_all("a").collect::<Vec<_>>(), ["1", "2"]);
+        assert_eq!(q.get("b"), Some("x y"));
+        assert_eq!(q.get("c"), Some(""));
+        assert_eq!(q.get("d"), Some(""));
+        assert_eq!(q.get("e="), Some("&"));
+        assert!(!q.contains_key("A"));
+        assert_eq!(q.len(), 6);
+        assert_eq!(resource("/?a=1&a=2").query(), [("a", "1"), ("a", "2")].into_iter().collect());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b%2fc"), b"a b/c");
+        assert_eq!(percent_decode("100%"), b"100%");
+        assert_eq!(percent_decode("%zz%4"), b"%zz%4");
+        assert_eq!(percent_decode("%FF"), [0xff]);
+        assert_eq!(decode("%FF"), "\u{fffd}");
+        assert_eq!(decode("a+b"), "a+b");
+        assert_eq!(decode_form("a+b%2B"), "a b+");
+    }
+}
@@ -0,0 +1,203 @@
+// HTTP 状态码 (RFC 9110 15)
+use std::fmt;
+
+// 100 到 599 之间的状态码；没有注册的状态码没有原因短语
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StatusCode(u16);
+
+// IANA 注册的状态码和原因短语
+const REASONS: &[(u16, &str)] = &[
+    (100, "Continue"),
+    (101, "Switching Protocols"),
+    (102, "Processing"),
+    (103, "Early Hints"),
+    (200, "OK"),
+    (201, "Created"),
+    (202, "Accepted"),
+    (203, "Non-Authoritative Information"),
+    (204, "No Content"),
+    (205, "Reset Content"),
+    (206, "Partial Content"),
+    (207, "Multi-Status"),
+    (208, "Already Reported"),
+    (226, "IM Used"),
+    (300, "Multiple Choices"),
+    (301, "Moved Permanently"),
+    (302, "Found"),
+    (303, "See Other"),
+    (304, "Not Modified"),
+    (305, "Use Proxy"),
+    (307, "Temporary Redirect"),
+    (308, "Permanent Redirect"),
+    (400, "Bad Request"),
+    (401, "Unauthorized"),
+    (402, "Payment Required"),
+    (403, "Forbidden"),
+    (404, "Not Found"),
+    (405, "Method Not Allowed"),
+    (406, "Not Acceptable"),
+    (407, "Proxy Authentication Required"),
+    (408, "Request Timeout"),
+    (409, "Conflict"),
+    (410, "Gone"),
+    (411, "Length Required"),
+    (412, "Precondition Failed"),
+    (413, "Content Too Large"),
+    (414, "URI Too Long"),
+    (415, "Unsupported Media Type"),
+    (416, "Range Not Satisfiable"),
+    (417, "Expectation Failed"),
+    (421, "Misdirected Request"),
+    (422, "Unprocessable Content"),
+    (423, "Locked"),
+    (424, "Failed Dependency"),
+    (425, "Too Early"),
+    (426, "Upgrade Required"),
+    (428, "Precondition Required"),
+    (429, "Too Many Requests"),
+    (431, "Request Header Fields Too Large"),
+    (451, "Unavailable For Legal Reasons"),
+    (500, "Internal Server Error"),
+    (501, "Not Implemented"),
+    (502, "Bad Gateway"),
+    (503, "Service Unavailable"),
+    (504, "Gateway Timeout"),
+    (505, "HTTP Version Not Supported"),
+    (506, "Variant Also Negotiates"),
+    (507, "Insufficient Storage"),
+    (508, "Loop Detected"),
+    (510, "Not Extended"),
+    (511, "Network Authentication Required"),
+];
+
+impl StatusCode {
+    pub const OK: StatusCode = StatusCode(200);
+    pub const CREATED: StatusCode = StatusCode(201);
+    pub const NO_CONTENT: StatusCode = StatusCode(204);
+    pub const MOVED_PERMANENTLY: StatusCode = StatusCode(301);
+    pub const FOUND: StatusCode = StatusCode(302);
+    pub const NOT_MODIFIED: StatusCode = StatusCode(304);
+    pub const BAD_REQUEST: StatusCode = StatusCode(400);
+    pub const FORBIDDEN: StatusCode = StatusCode(403);
+    pub const NOT_FOUND: StatusCode = StatusCode(404);
+    pub const METHOD_NOT_ALLOWED: StatusCode = StatusCode(405);
+    pub const CONTENT_TOO_LARGE: StatusCode = StatusCode(413);
+    pub const UNSUPPORTED_MEDIA_TYPE: StatusCode = StatusCode(415);
+    pub const INTERNAL_SERVER_ERROR: StatusCode = StatusCode(500);
+    pub const NOT_IMPLEMENTED: StatusCode = StatusCode(501);
+    pub const HTTP_VERSION_NOT_SUPPORTED: StatusCode = StatusCode(505);
+
+    // 不在 100 到 599 之间时返回 None
+    pub fn new(code: u16) -> Option<StatusCode> {
+        (100..=599).contains(&code).then_some(StatusCode(code))
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    // 原因短语，没有注册的状态码返回 None
+    pub fn reason(&self) -> Option<&'static str> {
+        REASONS.iter().find(|(code, _)| *code == self.0).map(|(_, reason)| *reason)
+    }
+
+    pub fn is_informational(&self) -> bool {
+        self.0 < 200
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.0)
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        self.0 >= 500
+    }
+
+    // 1xx、204 和 304 的响应没有响应体
+    pub fn allows_body(&self) -> bool {
+        !self.is_informational() && self.0 != 204 && self.0 != 304
+    }
+}
+
+impl TryFrom<u16> for StatusCode {
+    type Error = InvalidStatusCode;
+
+    fn try_from(code: u16) -> Result<StatusCode, InvalidStatusCode> {
+        StatusCode::new(code).ok_or(InvalidStatusCode(code))
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status: StatusCode) -> u16 {
+        status.0
+    }
+}
+
+// 如 `404 Not Found`；没有原因短语时只有数字
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.reason() {
+            Some(reason) => write!(f, "{} {}", self.0, reason),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidStatusCode(pub u16);
+
+impl fmt::Display for InvalidStatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid status code {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidStatusCode {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        assert_eq!(StatusCode::new(200), Some(StatusCode::OK));
+        assert_eq!(StatusCode::new(599).map(u16::from), Some(599));
+        assert_eq!(StatusCode::new(99), None);
+        assert_eq!(StatusCode::try_from(600), Err(InvalidStatusCode(600)));
+        assert_eq!(InvalidStatusCode(600).to_string(), "invalid status code 600");
+    }
+
+    #[test]
+    fn test_reason() {
+        assert_eq!(StatusCode::NOT_FOUND.reason(), Some("Not Found"));
+        assert_eq!(StatusCode::new(413).unwrap().reason(), Some("Content Too Large"));
+        assert_eq!(StatusCode::new(299).unwrap().reason(), None);
+        assert_eq!(StatusCode::HTTP_VERSION_NOT_SUPPORTED.to_string(), "505 HTTP Version Not Supported");
+        assert_eq!(StatusCode::new(299).unwrap().to_string(), "299");
+        // 表按状态码排序，没有重复
+        assert!(REASONS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_classes() {
+        let status = |code| StatusCode::new(code).unwrap();
+        assert!(status(101).is_informational());
+        assert!(status(204).is_success());
+        assert!(status(308).is_redirection());
+        assert!(status(451).is_client_error());
+        assert!(status(599).is_server_error());
+        assert!(!status(100).allows_body());
+        assert!(!status(204).allows_body());
+        assert!(!status(304).allows_body());
+        assert!(status(200).allows_body());
+        assert!(status(404).allows_body());
+    }
+}
@@ -0,0 +1,9 @@
+-- /lua/echo: the request body back, with the method and path as headers
+return {
+  headers = {
+    ["Content-Type"] = "text/plain",
+    ["X-Method"] = request.method,
+    ["X-Path"] = request.path,
+  },
+  body = request.body,
+}
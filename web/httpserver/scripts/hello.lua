@@ -0,0 +1,6 @@
+-- GET /lua/hello
+return {
+  status = 200,
+  headers = {["Content-Type"] = "text/plain"},
+  body = "Hello from Lua",
+}
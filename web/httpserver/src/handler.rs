@@ -1,24 +1,37 @@
-use http::{httpreponse::HttpResponse, httprequest::HttpRequest};
+use http::{body::Body, httpreponse::HttpResponse, httprequest::HttpRequest, statuscode::StatusCode};
+use lua_rs::parse;
+use lua_rs::value::{Table, Value};
+use lua_rs::vm::ExeState;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::panic::{self, AssertUnwindSafe};
 
 pub trait Handler {
     fn handle(req: &HttpRequest) -> HttpResponse;
     fn load_file(file_name: &str) -> Option<String> {
+        let contents = fs::read_to_string(Self::public_path(file_name));
+        contents.ok()
+    }
+    // Like load_file, but the file is streamed when the response is sent.
+    // Directories and other non-regular files are None.
+    fn open_file(file_name: &str) -> Option<Body> {
+        let file = File::open(Self::public_path(file_name)).ok()?;
+        Body::file(file).ok()
+    }
+    fn public_path(file_name: &str) -> String {
         let default_path = format!("{}/public", env!("CARGO_MANIFEST_DIR"));
         let public_path = env::var("PUBLIC_PATH").unwrap_or(default_path);
-        let full_path = format!("{}/{}", public_path, file_name);
-
-        let contents = fs::read_to_string(full_path);
-        contents.ok()
+        format!("{}/{}", public_path, file_name)
     }
 }
 
 pub struct StaticPageHandler;
 pub struct PageNotFoundHandler;
 pub struct WebServiceHandler;
+pub struct LuaHandler;
 
 #[derive(Serialize, Deserialize)]
 pub struct OrderStatus {
@@ -29,31 +42,33 @@ pub struct OrderStatus {
 
 impl Handler for PageNotFoundHandler {
     fn handle(_req: &HttpRequest) -> HttpResponse {
-        HttpResponse::new("404", None, Self::load_file("404.html"))
+        HttpResponse::new(404, None, Self::load_file("404.html"))
     }
 }
 
 impl Handler for StaticPageHandler {
     fn handle(req: &HttpRequest) -> HttpResponse {
-        let http::httprequest::Resource::Path(s) = &req.resource;
-        let route: Vec<&str> = s.split("/").collect();
-        match route[1] {
-            "" => HttpResponse::new("200", None, Self::load_file("index.html")),
-            "health" => HttpResponse::new("200", None, Self::load_file("health.html")),
-            path => match Self::load_file(path) {
-                Some(contents) => {
-                    let mut map: HashMap<&str, &str> = HashMap::new();
-                    if path.ends_with(".css") {
-                        map.insert("Content-Type", "text/css");
-                    } else if path.ends_with(".js") {
-                        map.insert("Content-Type", "text/javascript");
-                    } else {
-                        map.insert("Content-Type", "text/html");
-                    }
-                    HttpResponse::new("200", Some(map), Some(contents))
-                }
-                None => HttpResponse::new("404", None, Self::load_file("404.html")),
-            },
+        let segments = req.resource.segments();
+        let route: Vec<&str> = segments.iter().map(String::as_str).collect();
+        let path = match route.as_slice() {
+            [""] => String::from("index.html"),
+            ["health"] => String::from("health.html"),
+            // dot segments are already gone; a decoded `/` must not reach the file system
+            names if names.iter().all(|s| !s.is_empty() && !s.contains(['/', '\\'])) => {
+                names.join("/")
+            }
+            _ => return PageNotFoundHandler::handle(req),
+        };
+        let content_type = if path.ends_with(".css") {
+            "text/css"
+        } else if path.ends_with(".js") {
+            "text/javascript"
+        } else {
+            "text/html"
+        };
+        match Self::open_file(&path) {
+            Some(body) => HttpResponse::builder().content_type(content_type).body(body),
+            None => PageNotFoundHandler::handle(req),
         }
     }
 }
@@ -72,18 +87,250 @@ impl WebServiceHandler {
 
 impl Handler for WebServiceHandler {
     fn handle(req: &HttpRequest) -> HttpResponse {
-        let http::httprequest::Resource::Path(s) = &req.resource;
-        let route: Vec<&str> = s.split("/").collect();
+        let segments = req.resource.segments();
+        let route: Vec<&str> = segments.iter().map(String::as_str).collect();
         // localhost:3000/api/shipping/orders
-        match route[2] {
-            "shipping" if route.len() > 2 && route[3] == "orders" => {
+        match route.as_slice() {
+            ["api", "shipping", "orders"] => {
                 let body = Some(serde_json::to_string(&Self::load_json()).unwrap());
                 let mut headers: HashMap<&str, &str> = HashMap::new();
                 headers.insert("Content-Type", "application/json");
-                HttpResponse::new("200", Some(headers), body)
+                HttpResponse::new(200, Some(headers), body)
             }
 
-            _ => HttpResponse::new("404", None, Self::load_file("404.html")),
+            _ => PageNotFoundHandler::handle(req),
+        }
+    }
+}
+
+// localhost:3000/lua/<name> runs scripts/<name>.lua with the global
+// `request` = {method, path, query, version, headers, body}. `path` is
+// decoded and normalized; `query` has the first value of each parameter.
+// The script returns
+// the response as {status = 200, headers = {...}, body = "..."}.
+// `print` output is discarded, and a script is stopped after
+// MAX_INSTRUCTIONS, so a loop like `while true do end` can not hold
+// the server thread.
+const MAX_INSTRUCTIONS: u32 = 1_000_000;
+
+impl LuaHandler {
+    fn load_script(name: &str) -> Option<File> {
+        let default_path = format!("{}/scripts", env!("CARGO_MANIFEST_DIR"));
+        let scripts_path = env::var("SCRIPTS_PATH").unwrap_or(default_path);
+        let valid = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return None;
+        }
+        File::open(format!("{}/{}.lua", scripts_path, name)).ok()
+    }
+
+    fn request_table(req: &HttpRequest) -> Value {
+        let mut headers = Table::new();
+        // repeated headers are joined into one string
+        for (k, _) in &req.headers {
+            let values: Vec<&str> = req.headers.get_all(k).collect();
+            headers.set(k.into(), values.join(", ").as_str().into());
+        }
+        let params = req.resource.query();
+        let mut query = Table::new();
+        for (k, _) in params.iter() {
+            query.set(k.into(), params.get(k).unwrap().into());
+        }
+        let mut t = Table::new();
+        t.set("method".into(), req.method.as_str().into());
+        t.set("path".into(), req.resource.path().as_str().into());
+        t.set("query".into(), query.into());
+        t.set("version".into(), req.version.as_str().into());
+        t.set("headers".into(), headers.into());
+        t.set("body".into(), req.msg_body.as_slice().into());
+        t.into()
+    }
+
+    // Run a script for `req`. Errors in the script, or a bad response
+    // table, are a 500 with the error message as body.
+    pub fn run(script: impl Read + Seek, name: &str, req: &HttpRequest) -> HttpResponse {
+        let request = Self::request_table(req);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let proto = parse::load(script, name);
+            let mut state = ExeState::new();
+            state.set_stdout(Box::new(io::sink()));
+            state.sethook(Box::new(|_, _| {
+                panic!("script exceeded {} instructions", MAX_INSTRUCTIONS);
+            }), "", MAX_INSTRUCTIONS);
+            state.set_global("request", request);
+            state.execute(&proto)
+        }));
+        let response = match result {
+            Ok(values) => Self::response(values.into_iter().next().unwrap_or(Value::Nil)),
+            Err(err) => match (err.downcast_ref::<String>(), err.downcast_ref::<&str>()) {
+                (Some(s), _) => Err(s.clone()),
+                (_, Some(s)) => Err(s.to_string()),
+                _ => Err(String::from("unknown error")),
+            },
+        };
+        response.unwrap_or_else(|msg| {
+            let mut headers = HashMap::new();
+            headers.insert("Content-Type", "text/plain");
+            HttpResponse::new(500, Some(headers), Some(format!("{}: {}", name, msg)))
+        })
+    }
+
+    fn response(v: Value) -> Result<HttpResponse, String> {
+        let Value::Table(t) = v else {
+            return Err(format!("script returned {} instead of a table", v.type_name()));
+        };
+        let t = t.borrow();
+        let status = match t.get(&"status".into()) {
+            Value::Nil => StatusCode::OK,
+            Value::Integer(i) => u16::try_from(i)
+                .ok()
+                .and_then(StatusCode::new)
+                .ok_or(format!("unsupported status {}", i))?,
+            v => return Err(format!("status is {} instead of an integer", v.type_name())),
+        };
+        let body = match t.get(&"body".into()) {
+            Value::Nil => Vec::new(),
+            Value::String(s) => s.to_vec(),
+            v => return Err(format!("body is {} instead of a string", v.type_name())),
+        };
+        let mut response = HttpResponse::builder()
+            .status_code(status)
+            .content_type("text/html")
+            .body(body);
+        match t.get(&"headers".into()) {
+            Value::Nil => (),
+            Value::Table(headers) => {
+                let headers = headers.borrow();
+                let entries = headers.array.iter().enumerate()
+                    .map(|(i, v)| (Value::Integer(i as i64 + 1), v.clone()))
+                    .chain(headers.map.iter().map(|(k, v)| (k.clone(), v.clone())));
+                for (k, v) in entries {
+                    match (k, v) {
+                        (Value::String(k), Value::String(v)) => {
+                            let k = String::from_utf8_lossy(&k).into_owned();
+                            response.set_header(k.as_str(), String::from_utf8_lossy(&v))
+                                .map_err(|err| format!("header {:?}: {}", k, err))?;
+                        }
+                        (k, _) => return Err(format!("header {:?} is not a string pair", k)),
+                    }
+                }
+            }
+            v => return Err(format!("headers is {} instead of a table", v.type_name())),
+        }
+        Ok(response)
+    }
+}
+
+impl Handler for LuaHandler {
+    fn handle(req: &HttpRequest) -> HttpResponse {
+        let segments = req.resource.segments();
+        // localhost:3000/lua/hello
+        let script = match segments.as_slice() {
+            [_, name] => Self::load_script(name).map(|f| (name, f)),
+            _ => None,
+        };
+        match script {
+            Some((name, script)) => Self::run(script, name, req),
+            None => PageNotFoundHandler::handle(req),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn request(s: &str) -> HttpRequest {
+        s.to_string().into()
+    }
+
+    fn run(script: &str, req: &HttpRequest) -> String {
+        LuaHandler::run(Cursor::new(script.to_string()), "test", req).into()
+    }
+
+    #[test]
+    fn test_lua_hello() {
+        let req = request("GET /lua/hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let resp: String = LuaHandler::handle(&req).into();
+        assert_eq!(
+            resp,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 14\r\n\r\nHello from Lua"
+        );
+    }
+
+    #[test]
+    fn test_lua_request_table() {
+        let req = request("POST /lua/echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 8\r\n\r\nname=lua");
+        let resp = LuaHandler::handle(&req);
+        assert_eq!(resp.body().as_bytes(), Some(&b"name=lua"[..]));
+        let resp: String = resp.into();
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(resp.contains("X-Method: POST\r\n"));
+        assert!(resp.contains("X-Path: /lua/echo\r\n"));
+
+        let resp = run("return {body = request.headers.Host}", &req);
+        assert!(resp.ends_with("\r\n\r\nlocalhost"));
+
+        let req = request("GET /lua/./x/../echo%2D?a=1&b=x+y&a=2 HTTP/1.1\r\n\r\n");
+        let resp = run("return {headers = {p = request.path, a = request.query.a, b = request.query.b}}", &req);
+        assert!(resp.contains("p: /lua/echo-\r\n"), "{}", resp);
+        assert!(resp.contains("a: 1\r\n"));
+        assert!(resp.contains("b: x y\r\n"));
+    }
+
+    #[test]
+    fn test_lua_status() {
+        let req = request("GET /lua/x HTTP/1.1\r\n\r\n");
+        let resp = run("return {status = 404, body = 'none'}", &req);
+        assert!(resp.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let resp = run("return {status = 201}", &req);
+        assert!(resp.starts_with("HTTP/1.1 201 Created\r\n"));
+    }
+
+    #[test]
+    fn test_lua_errors() {
+        let req = request("GET /lua/x HTTP/1.1\r\n\r\n");
+        let resp = run("missing.field = 1", &req);
+        assert!(resp.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(resp.ends_with("test: attempt to index a nil value"));
+
+        let resp = run("print 'no return'", &req);
+        assert!(resp.ends_with("test: script returned nil instead of a table"));
+        let resp = run("return {status = 999}", &req);
+        assert!(resp.ends_with("test: unsupported status 999"));
+        let resp = run("return {headers = {x = {}}}", &req);
+        assert!(resp.ends_with("is not a string pair"));
+    }
+
+    #[test]
+    fn test_lua_endless_loop() {
+        let req = request("GET /lua/x HTTP/1.1\r\n\r\n");
+        let resp = run("while true do end", &req);
+        assert!(resp.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(resp.ends_with("test: script exceeded 1000000 instructions"));
+    }
+
+    // a decoded CRLF in the query must not split the response
+    #[test]
+    fn test_lua_header_injection() {
+        let req = request("GET /lua/x?a=%0d%0aEvil:%201 HTTP/1.1\r\n\r\n");
+        let resp = run("return {headers = {x = request.query.a}}", &req);
+        assert!(resp.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(!resp.contains("\r\nEvil"));
+        assert!(resp.ends_with("invalid header value"));
+
+        let resp = run("return {headers = {['a b'] = 'x'}}", &req);
+        assert!(resp.ends_with("invalid header name"));
+    }
+
+    #[test]
+    fn test_lua_script_not_found() {
+        for path in ["/lua/missing", "/lua/", "/lua/..%2Fsrc%2Fmain"] {
+            let req = request(&format!("GET {} HTTP/1.1\r\n\r\n", path));
+            let resp: String = LuaHandler::handle(&req).into();
+            assert!(resp.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", path);
+        }
+    }
+}
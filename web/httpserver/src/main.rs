@@ -3,8 +3,13 @@ mod router;
 mod server;
 
 use server::Server;
+use std::env;
 
 fn main() {
-    let server = Server::new("localhost:3000");
+    let mut server = Server::new("localhost:3000");
+    // MAX_BODY_SIZE in bytes, if set
+    if let Some(size) = env::var("MAX_BODY_SIZE").ok().and_then(|s| s.parse().ok()) {
+        server = server.max_body_size(size);
+    }
     server.run();
 }
@@ -1,31 +1,110 @@
-use super::handler::{Handler, PageNotFoundHandler, StaticPageHandler, WebServiceHandler};
-use http::{httpreponse::HttpResponse, httprequest, httprequest::HttpRequest};
+use super::handler::{Handler, LuaHandler, StaticPageHandler, WebServiceHandler};
+use http::{compression::Compression, httpreponse::HttpResponse, httprequest, httprequest::HttpRequest};
 use std::io::prelude::*;
 
 pub struct Router;
 
 impl Router {
-    pub fn route(req: HttpRequest, stream: &mut impl Write) -> () {
-        match req.method {
-            httprequest::Method::Get => match &req.resource {
-                httprequest::Resource::Path(s) => {
-                    let route: Vec<&str> = s.split("/").collect();
-                    match route[1] {
-                        "api" => {
-                            let resp: HttpResponse = WebServiceHandler::handle(&req);
-                            let _ = resp.send_response(stream);
-                        }
-                        _ => {
-                            let resp: HttpResponse = StaticPageHandler::handle(&req);
-                            let _ = resp.send_response(stream);
-                        }
-                    }
-                }
+    pub fn route(req: HttpRequest, stream: &mut impl Write) {
+        let segments = req.resource.segments();
+        let mut resp: HttpResponse = match req.method {
+            // scripts handle any method
+            _ if segments.first().is_some_and(|s| s == "lua") => LuaHandler::handle(&req),
+            httprequest::Method::Get | httprequest::Method::Head => match segments.first().map(String::as_str) {
+                Some("api") => WebServiceHandler::handle(&req),
+                _ => StaticPageHandler::handle(&req),
             },
-            _ => {
-                let resp: HttpResponse = PageNotFoundHandler::handle(&req);
-                let _ = resp.send_response(stream);
-            }
+            _ => HttpResponse::builder().status(405).header("Allow", "GET, HEAD").build(),
+        };
+        // HTTP/1.0 clients get a 1.0 response, which is never chunked
+        resp.set_version(req.version);
+        Compression::default().apply(&req.headers, &mut resp);
+        // HEAD gets the same head as GET, without the body
+        let _ = match req.method {
+            httprequest::Method::Head => resp.send_head(stream),
+            _ => resp.send_response(stream),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(raw: &str) -> String {
+        let mut out = Vec::new();
+        Router::route(raw.to_string().into(), &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_head() {
+        let get = route("GET /health HTTP/1.1\r\n\r\n");
+        let head = route("HEAD /health HTTP/1.1\r\n\r\n");
+        let (get_head, body) = get.split_once("\r\n\r\n").unwrap();
+        assert!(!body.is_empty());
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(head.len(), get_head.len() + 4);
+        assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+    }
+
+    #[test]
+    fn test_http_1_0() {
+        let resp = route("GET /health HTTP/1.0\r\n\r\n");
+        assert!(resp.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(resp.contains("Content-Length: "));
+        assert!(!resp.contains("Transfer-Encoding"));
+    }
+
+    #[test]
+    fn test_paths() {
+        let index = route("GET / HTTP/1.1\r\n\r\n");
+        assert!(index.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(route("GET /a/../?x=1 HTTP/1.1\r\n\r\n"), index);
+        assert_eq!(route("GET http://localhost:3000/ HTTP/1.1\r\n\r\n"), index);
+
+        let css = route("GET /%73tyles.css HTTP/1.1\r\n\r\n");
+        assert!(css.contains("Content-Type: text/css\r\n"));
+        // files are streamed with the length they had when opened
+        let size = std::fs::metadata(concat!(env!("CARGO_MANIFEST_DIR"), "/public/styles.css")).unwrap().len();
+        assert!(css.contains(&format!("Content-Length: {}\r\n\r\n", size)));
+        assert_eq!(css.split_once("\r\n\r\n").unwrap().1.len() as u64, size);
+        let health = route("GET /x/./../health HTTP/1.1\r\n\r\n");
+        assert!(health.starts_with("HTTP/1.1 200 OK\r\n"));
+        let orders = route("GET /api/shipping/orders?page=1 HTTP/1.1\r\n\r\n");
+        assert!(orders.contains("Content-Type: application/json\r\n"));
+
+        for path in ["/../Cargo.toml", "/..%2FCargo.toml", "/%2e%2e/Cargo.toml", "/missing", "/api/shipping"] {
+            let resp = route(&format!("GET {} HTTP/1.1\r\n\r\n", path));
+            assert!(resp.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_compression() {
+        // the bundled pages are below the minimum size: not compressed, but still varied
+        for path in ["/api/shipping/orders", "/styles.css", "/"] {
+            let resp = route(&format!("GET {} HTTP/1.1\r\nAccept-Encoding: gzip, br\r\n\r\n", path));
+            assert!(resp.contains("Vary: Accept-Encoding\r\n"), "{}", resp);
+            assert!(!resp.contains("Content-Encoding"));
+        }
+        let resp = route("POST /health HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n");
+        assert!(!resp.contains("Vary"));
+    }
+
+    #[test]
+    fn test_chunked_upload() {
+        let resp = route("POST /lua/echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nname\r\n4\r\n=lua\r\n0\r\n\r\n");
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(resp.ends_with("Content-Length: 8\r\n\r\nname=lua"));
+    }
+
+    #[test]
+    fn test_method_not_allowed() {
+        for method in ["POST", "PUT", "DELETE", "PROPFIND"] {
+            let resp = route(&format!("{} /health HTTP/1.1\r\n\r\n", method));
+            assert!(resp.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"), "{}", resp);
+            assert!(resp.contains("Allow: GET, HEAD\r\n"));
         }
     }
 }
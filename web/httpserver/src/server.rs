@@ -1,16 +1,25 @@
 use super::router::Router;
-use http::httprequest::HttpRequest;
+use http::bodyparser::BodyParser;
+use http::httpparser::{Parsed, Parser, MAX_BODY};
+use http::httpreponse::HttpResponse;
 use std::io::prelude::*;
-use std::net::TcpListener;
-use std::str;
+use std::net::{TcpListener, TcpStream};
 
 pub struct Server<'a> {
     socket_addr: &'a str,
+    max_body_size: usize,
 }
 
 impl<'a> Server<'a> {
     pub fn new(socket_addr: &'a str) -> Self {
-        Server { socket_addr }
+        Server { socket_addr, max_body_size: MAX_BODY }
+    }
+
+    // Requests with a larger body get a 413, as soon as the declared length
+    // or a chunk size is seen; the rest of the body is not read.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
     }
 
     pub fn run(&self) {
@@ -20,12 +29,77 @@ impl<'a> Server<'a> {
         for stream in connection_listener.incoming() {
             let mut stream = stream.unwrap();
             println!("Connection established!");
+            handle_connection(&mut stream, self.max_body_size, &BodyParser::new());
+        }
+    }
+}
 
-            let mut read_buffer = [0, 200];
-            stream.read(&mut read_buffer).unwrap();
-
-            let req: HttpRequest = String::from_utf8(read_buffer.to_vec()).unwrap().into();
-            Router::route(req, &mut stream);
+// Read until a whole request is parsed, then route it. Bad requests get a
+// 400, a 505 for an HTTP version other than 1.0 and 1.1, or a 413 for a
+// body over `max_body_size`. Multipart bodies are handed to `body_parser` as
+// they arrive and dropped from the buffer before the next read.
+fn handle_connection(stream: &mut TcpStream, max_body_size: usize, body_parser: &BodyParser) {
+    let mut parser = Parser::new().max_body(max_body_size).body_parser(body_parser.clone());
+    let mut buffer = Vec::new();
+    let mut read_buffer = [0; 4096];
+    loop {
+        match parser.parse(&buffer) {
+            Parsed::Complete(_) => {
+                let req = parser.take().unwrap();
+                Router::route(req, stream);
+                return;
+            }
+            Parsed::Error(e) => {
+                let resp = HttpResponse::builder()
+                    .status_code(e.status_code())
+                    .content_type("text/plain")
+                    .body(e.to_string());
+                let _ = resp.send_response(stream);
+                return;
+            }
+            Parsed::Incomplete => {
+                parser.discard_body(&mut buffer);
+                match stream.read(&mut read_buffer) {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buffer.extend_from_slice(&read_buffer[..n]),
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Serve one connection with `max_body_size`, send `raw` and read the
+    // response until the server closes the connection.
+    fn exchange(raw: &[u8], max_body_size: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            handle_connection(&mut stream, max_body_size, &BodyParser::new());
+        });
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(raw).unwrap();
+        let mut resp = String::new();
+        client.read_to_string(&mut resp).unwrap();
+        server.join().unwrap();
+        resp
+    }
+
+    #[test]
+    fn test_body_too_large() {
+        // answered without waiting for the body, which is never sent
+        let resp = exchange(b"POST /lua/echo HTTP/1.1\r\nContent-Length: 1000000\r\n\r\n", 1024);
+        assert!(resp.starts_with("HTTP/1.1 413 Content Too Large\r\n"), "{}", resp);
+
+        let chunked = b"POST /lua/echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n400\r\n";
+        assert!(exchange(chunked, 1023).starts_with("HTTP/1.1 413 "));
+
+        let resp = exchange(b"POST /lua/echo HTTP/1.1\r\nContent-Length: 8\r\n\r\nname=lua", 8);
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+    }
+}
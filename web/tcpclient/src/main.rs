@@ -4,10 +4,10 @@ use std::str;
 
 fn main() {
     let mut stream = TcpStream::connect("localhost:3000").unwrap();
-    stream.write("Hello".as_bytes()).unwrap();
+    stream.write_all("Hello".as_bytes()).unwrap();
 
     let mut buffer = [0; 5];
-    stream.read(&mut buffer).unwrap();
+    stream.read_exact(&mut buffer).unwrap();
 
     println!(
         "Response from server: {:?}",